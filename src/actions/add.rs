@@ -1,8 +1,16 @@
-use crate::actions::{copy_to_clipboard_timed, unlock, unlock_totp_vault, Action, ItemType};
+use crate::actions::{
+    copy_to_clipboard_timed, generate_password, parse_password_rules, password_policy,
+    policy_overrides_from_args, unlock, unlock_totp_vault, Action, ItemType, OnConflict,
+};
 use crate::completion_cache;
-use crate::vault::entities::Error;
+use crate::crypto::breach::BreachDatabase;
+use crate::crypto::strength::estimate_strength;
+use crate::crypto::PolicyOverrides;
+use crate::password_rules::PasswordRules;
+use crate::ui::output::{show_password_strength, show_totp_settings_table};
+use crate::vault::entities::{Credential, Error};
 use crate::vault::vault_trait::Vault;
-use crate::{crypto, ui};
+use crate::{crypto, otp_import, qr, ui};
 use clap::ArgMatches;
 use arboard::Clipboard;
 
@@ -11,6 +19,10 @@ pub struct AddAction {
     pub clipboard: bool,
     pub item_type: ItemType,
     pub is_totp: bool,
+    pub policy: PolicyOverrides,
+    pub rules: Option<String>,
+    pub breached: Option<String>,
+    pub qr_image: Option<String>,
 }
 
 impl AddAction {
@@ -20,28 +32,74 @@ impl AddAction {
             clipboard: matches.get_one::<bool>("clipboard").map_or(false, |v| *v),
             item_type: ItemType::new_from_args(matches),
             is_totp: matches.get_one::<bool>("otp").map_or(false, |v| *v),
+            policy: policy_overrides_from_args(matches),
+            rules: matches.get_one::<String>("rules").cloned(),
+            breached: matches.get_one::<String>("breached").cloned(),
+            qr_image: matches.get_one::<String>("qr-image").cloned(),
         }
     }
-    fn password_from_clipboard(&self) -> Result<String, Error> {
+    fn password_from_clipboard(&self, rules: Option<&PasswordRules>) -> Result<String, Error> {
         let mut ctx = Clipboard::new()
             .map_err(|e| Error::new(&format!("Unable to access clipboard: {}", e)))?;
         let value = ctx
             .get_text()
             .map_err(|e| Error::new(&format!("Unable to retrieve value from clipboard: {}", e)))?;
-        if !crypto::validate_password(&value) {
-            return Err(Error::new("The text in clipboard is not a valid password"));
+        match rules {
+            Some(rules) if !rules.accepts(&value) => Err(Error::new(
+                "The text in clipboard does not satisfy the password rules",
+            )),
+            None if !crypto::validate_password(&value, &password_policy(&self.policy)?) => {
+                Err(Error::new(
+                    "The text in clipboard is not a valid password for the active password policy",
+                ))
+            }
+            _ => Ok(value),
         }
-        Ok(value)
     }
-    fn get_password(&self) -> Result<String, Error> {
-        if self.generate {
-            Ok(crypto::generate())
-        } else if self.clipboard {
-            self.password_from_clipboard()
+    fn get_password(&self, rules: Option<&PasswordRules>) -> Result<String, Error> {
+        if self.clipboard {
+            self.password_from_clipboard(rules)
         } else {
             Ok(ui::input::ask_password("Enter password to save", None))
         }
     }
+    /// Asks for the service details first, so that the password can follow the
+    /// built-in rules of a known site unless `--rules` or `--passphrase` was given.
+    fn generated_credential(&self, rules: Option<PasswordRules>) -> Result<(String, Credential), Error> {
+        let creds = ui::input::ask_credentials("");
+        let rules = rules.or_else(|| {
+            (!self.policy.passphrase)
+                .then(|| PasswordRules::for_service(creds.service()))
+                .flatten()
+        });
+        if let Some(rules) = &rules {
+            println!("Generating a password with the rules: {}", rules);
+        }
+        let password = generate_password(&self.policy, rules.as_ref())?;
+        let rules = rules.map(|r| r.to_string());
+        let creds = Credential::new(
+            Some(creds.uuid()),
+            &password,
+            creds.service(),
+            creds.username(),
+            creds.note(),
+            None,
+        )
+        .with_password_rules(rules.as_deref());
+        Ok((password, creds))
+    }
+    /// Warns when `--breached` was given and the password is in that dataset.
+    fn warn_if_breached(&self, password: &str) -> Result<(), Error> {
+        if let Some(path) = &self.breached
+            && let Some(count) = BreachDatabase::open(path)?.lookup(password)?
+        {
+            println!(
+                "Warning: this password appears {} times in the breach dataset. Consider using a generated password instead.",
+                count
+            );
+        }
+        Ok(())
+    }
     fn get_vault(&self) -> Result<Box<dyn Vault>, Error> {
         if self.is_totp {
             unlock_totp_vault()
@@ -50,13 +108,36 @@ impl AddAction {
         }
     }
     fn add_credential(&self) -> Result<String, Error> {
-        let password = self.get_password()?;
-
-        let creds = ui::input::ask_credentials(&password);
+        let rules = parse_password_rules(self.rules.as_deref())?;
+        let (password, creds) = if self.generate {
+            self.generated_credential(rules)?
+        } else {
+            let password = self.get_password(rules.as_ref())?;
+            let rules = rules.map(|r| r.to_string());
+            let creds = ui::input::ask_credentials(&password).with_password_rules(rules.as_deref());
+            if !self.clipboard {
+                show_password_strength(&estimate_strength(
+                    &password,
+                    &[creds.service(), creds.username()],
+                ));
+            }
+            self.warn_if_breached(&password)?;
+            (password, creds)
+        };
         let mut vault = self.get_vault()?;
-        vault.save_one_credential(creds.clone())?;
+        let existing = vault.grep(None).into_iter().find(|c| c.is_same_account(&creds));
+        match existing.map(|e| (ui::input::ask_on_conflict(&e), e)) {
+            Some((OnConflict::Skip, _)) => return Ok("Nothing saved.".to_string()),
+            Some((OnConflict::Update, existing)) => {
+                vault.update_credential(creds.replacing(&existing))?;
+                println!("Password updated.");
+            }
+            Some((OnConflict::Duplicate, _)) | None => {
+                vault.save_one_credential(creds)?;
+                println!("Password saved.");
+            }
+        }
         completion_cache::update_cache(&vault);
-        println!("Password saved.");
         println!("Password copied to clipboard! Clipboard will be cleared in 20 seconds.");
         copy_to_clipboard_timed(&password, 20);
         Ok(String::new())
@@ -75,7 +156,26 @@ impl AddAction {
         vault.save_note(&note)?;
         Ok("Note saved.".to_string())
     }
+    /// Reads the authorizers from the QR codes in an image and saves them once
+    /// the user has checked their settings.
+    fn add_totps_from_image(&self, path: &str) -> Result<String, Error> {
+        let mut totps = Vec::new();
+        for text in qr::decode_image(path)? {
+            totps.extend(otp_import::parse_scanned(&text)?);
+        }
+        show_totp_settings_table(&totps);
+        if !ui::input::ask_confirm(&format!("Save {} authorizers?", totps.len()), true) {
+            return Ok("Nothing saved.".to_string());
+        }
+        println!("Saving...");
+        let mut vault = self.get_vault()?;
+        vault.save_totps(&totps)?;
+        Ok("TOTP saved.".to_string())
+    }
     fn add_totp(&self) -> Result<String, Error> {
+        if let Some(path) = &self.qr_image {
+            return self.add_totps_from_image(path);
+        }
         let totp = ui::input::ask_totp_info();
         println!("Saving...");
         let mut vault = self.get_vault()?;
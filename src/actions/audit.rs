@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use clap::ArgMatches;
+use serde::Serialize;
+
+use crate::actions::list::ListOutput;
+use crate::actions::{unlock_totp_vault, UnlockingAction};
+use crate::crypto::breach::BreachDatabase;
+use crate::crypto::strength::estimate_strength;
+use crate::password_rules::host_name;
+use crate::store;
+use crate::ui::output::show_audit_tables;
+use crate::vault::entities::{Credential, Error, Totp};
+use crate::vault::vault_trait::Vault;
+
+pub const DEFAULT_MAX_AGE_DAYS: i64 = 365;
+pub const DEFAULT_MIN_SCORE: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            _ => Err(Error::new(&format!(
+                "Unknown severity '{}', expected low, medium or high",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Empty,
+    Breached,
+    Weak,
+    Reused,
+    Stale,
+    NoTotp,
+    Unlinked,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Check::Empty => "empty password",
+            Check::Breached => "breached password",
+            Check::Weak => "weak password",
+            Check::Reused => "reused password",
+            Check::Stale => "not changed recently",
+            Check::NoTotp => "no one time password",
+            Check::Unlinked => "no linked authorizer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One problem found in one credential. Never includes the password.
+#[derive(Clone, Debug, Serialize)]
+pub struct Finding {
+    pub check: Check,
+    pub severity: Severity,
+    pub service: String,
+    pub username: String,
+    pub detail: String,
+    /// Credentials sharing a password have the same group number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reuse_group: Option<usize>,
+}
+
+pub struct AuditAction {
+    pub json: bool,
+    pub max_age_days: i64,
+    pub min_score: u8,
+    pub check_totp: bool,
+    pub check_link: bool,
+    pub fail_on: Option<Severity>,
+    pub breached: Option<String>,
+}
+
+impl AuditAction {
+    pub fn new(matches: &ArgMatches) -> AuditAction {
+        AuditAction {
+            json: matches.get_one::<bool>("json").is_some_and(|v| *v),
+            max_age_days: matches
+                .get_one::<i64>("max-age")
+                .copied()
+                .unwrap_or(DEFAULT_MAX_AGE_DAYS),
+            min_score: matches
+                .get_one::<u8>("min-score")
+                .copied()
+                .unwrap_or(DEFAULT_MIN_SCORE),
+            check_totp: !matches.get_one::<bool>("no-otp").is_some_and(|v| *v),
+            check_link: matches.get_one::<bool>("unlinked").is_some_and(|v| *v),
+            fail_on: matches
+                .get_one::<String>("fail-on")
+                .and_then(|value| value.parse().ok()),
+            breached: matches.get_one::<String>("breached").cloned(),
+        }
+    }
+
+    /// Checks `credentials` and returns the findings, most severe first. The
+    /// TOTP check is skipped when `totps` is `None`, and the breach check when
+    /// `breaches` is `None`.
+    pub fn findings(
+        &self,
+        credentials: &[Credential],
+        totps: Option<&[Totp]>,
+        breaches: Option<&BreachDatabase>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Finding>, Error> {
+        let mut findings = Vec::new();
+        let mut breach_counts: HashMap<&str, Option<u64>> = HashMap::new();
+        let finding = |check: Check, severity: Severity, credential: &Credential, detail: String| Finding {
+            check,
+            severity,
+            service: credential.service().to_string(),
+            username: credential.username().to_string(),
+            detail,
+            reuse_group: None,
+        };
+
+        for credential in credentials {
+            if credential.password().is_empty() {
+                findings.push(finding(
+                    Check::Empty,
+                    Severity::High,
+                    credential,
+                    "the password is empty".to_string(),
+                ));
+                continue;
+            }
+            if let Some(breaches) = breaches {
+                let count = match breach_counts.get(credential.password()) {
+                    Some(count) => *count,
+                    None => {
+                        let count = breaches.lookup(credential.password())?;
+                        breach_counts.insert(credential.password(), count);
+                        count
+                    }
+                };
+                if let Some(count) = count {
+                    findings.push(finding(
+                        Check::Breached,
+                        Severity::High,
+                        credential,
+                        format!("appears {} times in the breach dataset", count),
+                    ));
+                }
+            }
+            let strength = estimate_strength(
+                credential.password(),
+                &[credential.service(), credential.username()],
+            );
+            if strength.score < self.min_score {
+                let severity = if strength.score <= 1 {
+                    Severity::High
+                } else {
+                    Severity::Medium
+                };
+                let mut detail = format!(
+                    "{} ({}/4), cracked in {}",
+                    strength.score_label(),
+                    strength.score,
+                    strength.crack_time
+                );
+                if let Some(warning) = &strength.warning {
+                    detail.push_str(&format!(": {}", warning));
+                }
+                findings.push(finding(Check::Weak, severity, credential, detail));
+            }
+        }
+
+        let mut by_password: HashMap<&str, Vec<&Credential>> = HashMap::new();
+        for credential in credentials.iter().filter(|c| !c.password().is_empty()) {
+            by_password
+                .entry(credential.password())
+                .or_default()
+                .push(credential);
+        }
+        let mut groups: Vec<Vec<&Credential>> = by_password
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+        groups.sort_by(|a, b| a[0].service().cmp(b[0].service()));
+        for (index, group) in groups.iter().enumerate() {
+            for credential in group {
+                let others: Vec<&str> = group
+                    .iter()
+                    .filter(|other| other.uuid() != credential.uuid())
+                    .map(|other| other.service())
+                    .collect();
+                findings.push(Finding {
+                    reuse_group: Some(index + 1),
+                    ..finding(
+                        Check::Reused,
+                        Severity::High,
+                        credential,
+                        format!("same password as {}", others.join(", ")),
+                    )
+                });
+            }
+        }
+
+        for credential in credentials {
+            let age = (now - *credential.last_modified()).num_days();
+            if age > self.max_age_days {
+                findings.push(finding(
+                    Check::Stale,
+                    Severity::Low,
+                    credential,
+                    format!("not changed in {} days", age),
+                ));
+            }
+        }
+
+        if let Some(totps) = totps {
+            for credential in credentials {
+                if self.check_totp && !has_totp(credential, totps) {
+                    findings.push(finding(
+                        Check::NoTotp,
+                        Severity::Low,
+                        credential,
+                        "no matching entry in the one time passwords vault".to_string(),
+                    ));
+                }
+                if self.check_link && linked_totp(credential, totps).is_none() {
+                    let detail = match credential.totp_id() {
+                        Some(_) => "the linked authorizer is no longer in the one time passwords vault",
+                        None => "not linked to an authorizer, see otp link",
+                    };
+                    findings.push(finding(Check::Unlinked, Severity::Low, credential, detail.to_string()));
+                }
+            }
+        }
+
+        findings.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then_with(|| a.service.to_lowercase().cmp(&b.service.to_lowercase()))
+        });
+        Ok(findings)
+    }
+
+    fn totps(&self) -> Result<Option<Vec<Totp>>, Error> {
+        if !self.check_totp && !self.check_link {
+            return Ok(None);
+        }
+        if !store::has_totp_vault_path() {
+            eprintln!("No one time passwords vault configured, skipping the TOTP check.");
+            return Ok(None);
+        }
+        Ok(Some(unlock_totp_vault()?.find_totp(None)))
+    }
+}
+
+/// Second level labels that country code domains register names under, as in "bbc.co.uk".
+const SECOND_LEVEL_LABELS: [&str; 9] = ["co", "com", "net", "org", "gov", "ac", "edu", "ne", "or"];
+
+/// The site part of a service name: "google" for "https://accounts.google.com/login"
+/// and "bbc" for "www.bbc.co.uk".
+fn site_name(service: &str) -> String {
+    let host = host_name(service);
+    let labels: Vec<&str> = host.split('.').filter(|l| !l.is_empty()).collect();
+    match labels.len() {
+        0 => String::new(),
+        1 => labels[0].to_string(),
+        n if n > 2 && labels[n - 1].len() == 2 && SECOND_LEVEL_LABELS.contains(&labels[n - 2]) => {
+            labels[n - 3].to_string()
+        }
+        n => labels[n - 2].to_string(),
+    }
+}
+
+fn linked_totp<'a>(credential: &Credential, totps: &'a [Totp]) -> Option<&'a Totp> {
+    let id = credential.totp_id()?;
+    totps.iter().find(|totp| totp.id() == id)
+}
+
+fn has_totp(credential: &Credential, totps: &[Totp]) -> bool {
+    if linked_totp(credential, totps).is_some() {
+        return true;
+    }
+    let site = site_name(credential.service());
+    if site.is_empty() {
+        return false;
+    }
+    totps.iter().any(|totp| {
+        totp.issuer().to_lowercase().contains(&site) || totp.label().to_lowercase().contains(&site)
+    })
+}
+
+impl UnlockingAction for AuditAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let credentials = vault.grep(None);
+        let breaches = self
+            .breached
+            .as_deref()
+            .map(BreachDatabase::open)
+            .transpose()?;
+        let totps = self.totps()?;
+        let findings = self.findings(&credentials, totps.as_deref(), breaches.as_ref(), Utc::now())?;
+
+        let failing = self
+            .fail_on
+            .map_or(0, |level| findings.iter().filter(|f| f.severity >= level).count());
+        let output = if self.json {
+            ListOutput::new("audit_findings", findings).to_json()?
+        } else if findings.is_empty() {
+            format!("No issues found in {} credentials.", credentials.len())
+        } else {
+            show_audit_tables(&findings);
+            format!(
+                "Found {} issues in {} credentials.",
+                findings.len(),
+                credentials.len()
+            )
+        };
+        match self.fail_on {
+            Some(level) if failing > 0 => {
+                println!("{}", output);
+                Err(Error::new(&format!(
+                    "{} findings at or above {} severity",
+                    failing, level
+                )))
+            }
+            _ => Ok(Some(output)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn action() -> AuditAction {
+        AuditAction {
+            json: false,
+            max_age_days: 90,
+            min_score: DEFAULT_MIN_SCORE,
+            check_totp: true,
+            check_link: false,
+            fail_on: None,
+            breached: None,
+        }
+    }
+
+    fn credential(service: &str, password: &str, age_days: i64) -> Credential {
+        Credential::new(
+            None,
+            password,
+            service,
+            "user@example.com",
+            None,
+            Some(Utc::now() - Duration::days(age_days)),
+        )
+    }
+
+    fn totp(issuer: &str, label: &str) -> Totp {
+        Totp::new(None, "", label, issuer, "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None)
+    }
+
+    fn checks(findings: &[Finding], service: &str) -> Vec<Check> {
+        findings
+            .iter()
+            .filter(|f| f.service == service)
+            .map(|f| f.check)
+            .collect()
+    }
+
+    #[test]
+    fn reports_each_check() {
+        let credentials = vec![
+            credential("empty.com", "", 1),
+            credential("weak.com", "password1", 1),
+            credential("a.com", "kX9#mQ2$vL7!pR4z", 1),
+            credential("b.com", "kX9#mQ2$vL7!pR4z", 1),
+            credential("old.com", "cT8%wN3@hF6^jB1q", 400),
+            credential("https://accounts.github.com/login", "zR5&tY8*uI2(oP9q", 1),
+        ];
+        let totps = vec![totp("GitHub", "GitHub:user")];
+        let findings = action().findings(&credentials, Some(&totps), None, Utc::now()).unwrap();
+
+        assert_eq!(checks(&findings, "empty.com"), vec![Check::Empty, Check::NoTotp]);
+        assert_eq!(checks(&findings, "weak.com"), vec![Check::Weak, Check::NoTotp]);
+        assert_eq!(checks(&findings, "a.com"), vec![Check::Reused, Check::NoTotp]);
+        assert_eq!(checks(&findings, "old.com"), vec![Check::Stale, Check::NoTotp]);
+        assert!(checks(&findings, "https://accounts.github.com/login").is_empty());
+        assert!(findings.windows(2).all(|w| w[0].severity >= w[1].severity));
+    }
+
+    #[test]
+    fn reports_unlinked_credentials() {
+        let github = totp("GitHub", "GitHub:user");
+        let credentials = vec![
+            credential("github.com", "kX9#mQ2$vL7!pR4z", 1).with_totp_id(Some(*github.id())),
+            credential("gitlab.com", "zR5&tY8*uI2(oP9q", 1).with_totp_id(Some(uuid::Uuid::new_v4())),
+            credential("vpn.example.com", "aQ7^wE3@rT6%yU1i", 1),
+        ];
+        let audit = AuditAction {
+            check_totp: false,
+            check_link: true,
+            ..action()
+        };
+        let findings = audit.findings(&credentials, Some(&[github]), None, Utc::now()).unwrap();
+
+        assert!(checks(&findings, "github.com").is_empty());
+        assert_eq!(checks(&findings, "gitlab.com"), vec![Check::Unlinked]);
+        assert_eq!(checks(&findings, "vpn.example.com"), vec![Check::Unlinked]);
+        assert!(findings[0].detail.contains("no longer"));
+    }
+
+    #[test]
+    fn linked_credential_has_totp() {
+        let vpn = totp("Corp", "Corp:user");
+        let credentials = vec![credential("vpn.example.com", "kX9#mQ2$vL7!pR4z", 1).with_totp_id(Some(*vpn.id()))];
+        let findings = action().findings(&credentials, Some(&[vpn]), None, Utc::now()).unwrap();
+        assert!(findings.is_empty());
+    }
+
+    #[test]
+    fn groups_reused_passwords_without_revealing_them() {
+        let credentials = vec![
+            credential("a.com", "kX9#mQ2$vL7!pR4z", 1),
+            credential("b.com", "kX9#mQ2$vL7!pR4z", 1),
+            credential("c.com", "cT8%wN3@hF6^jB1q", 1),
+            credential("d.com", "cT8%wN3@hF6^jB1q", 1),
+        ];
+        let findings = action().findings(&credentials, None, None, Utc::now()).unwrap();
+        let groups: Vec<(String, Option<usize>)> = findings
+            .iter()
+            .map(|f| (f.service.clone(), f.reuse_group))
+            .collect();
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups.iter().find(|g| g.0 == "a.com").unwrap().1, Some(1));
+        assert_eq!(groups.iter().find(|g| g.0 == "b.com").unwrap().1, Some(1));
+        assert_eq!(groups.iter().find(|g| g.0 == "d.com").unwrap().1, Some(2));
+        let json = serde_json::to_string(&findings).unwrap();
+        assert!(!json.contains("kX9#"));
+        assert!(json.contains("same password as b.com"));
+    }
+
+    #[test]
+    fn weak_severity_depends_on_score() {
+        let credentials = vec![credential("x.com", "password", 1)];
+        let findings = action().findings(&credentials, None, None, Utc::now()).unwrap();
+        assert_eq!(findings[0].check, Check::Weak);
+        assert_eq!(findings[0].severity, Severity::High);
+
+        let lenient = AuditAction { min_score: 0, ..action() };
+        assert!(lenient.findings(&credentials, None, None, Utc::now()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_breached_passwords_once_per_credential() {
+        use sha1::{Digest, Sha1};
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pwned.txt");
+        let hash = hex::encode_upper(Sha1::digest(b"kX9#mQ2$vL7!pR4z"));
+        std::fs::write(&path, format!("{}:42\n", hash)).unwrap();
+        let breaches = BreachDatabase::open(&path.display().to_string()).unwrap();
+
+        let credentials = vec![
+            credential("a.com", "kX9#mQ2$vL7!pR4z", 1),
+            credential("c.com", "cT8%wN3@hF6^jB1q", 1),
+        ];
+        let findings = action()
+            .findings(&credentials, None, Some(&breaches), Utc::now())
+            .unwrap();
+        assert_eq!(checks(&findings, "a.com"), vec![Check::Breached]);
+        assert_eq!(findings[0].detail, "appears 42 times in the breach dataset");
+        assert!(checks(&findings, "c.com").is_empty());
+    }
+
+    #[test]
+    fn site_names() {
+        assert_eq!(site_name("https://accounts.google.com/login"), "google");
+        assert_eq!(site_name("GitHub"), "github");
+        assert_eq!(site_name("www.example.org"), "example");
+        assert_eq!(site_name("https://www.bbc.co.uk/iplayer"), "bbc");
+        assert_eq!(site_name("example.com.au"), "example");
+        assert_eq!(site_name("shop.rakuten.co.jp"), "rakuten");
+        assert_eq!(site_name("example.co"), "example");
+        assert_eq!(site_name(""), "");
+    }
+
+    #[test]
+    fn parses_severities() {
+        assert_eq!("HIGH".parse::<Severity>().unwrap(), Severity::High);
+        assert!("critical".parse::<Severity>().is_err());
+        assert!(Severity::High > Severity::Medium && Severity::Medium > Severity::Low);
+    }
+}
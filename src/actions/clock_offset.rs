@@ -0,0 +1,33 @@
+use crate::actions::Action;
+use crate::store;
+use crate::vault::entities::Error;
+use clap::ArgMatches;
+
+/// Shows or sets the number of seconds added to the system clock when
+/// generating time-based codes, to make up for a clock that is off.
+pub struct ClockOffsetAction {
+    pub offset: Option<i64>,
+}
+
+impl ClockOffsetAction {
+    pub fn new(matches: &ArgMatches) -> ClockOffsetAction {
+        ClockOffsetAction {
+            offset: matches.get_one::<i64>("SECONDS").copied(),
+        }
+    }
+}
+
+impl Action for ClockOffsetAction {
+    fn run(&self) -> Result<String, Error> {
+        match self.offset {
+            Some(offset) => {
+                store::save_clock_offset(offset)?;
+                Ok(format!("Time-based codes now use the system clock moved by {} seconds", offset))
+            }
+            None => Ok(format!(
+                "Time-based codes use the system clock moved by {} seconds",
+                store::load_clock_offset()?
+            )),
+        }
+    }
+}
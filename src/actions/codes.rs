@@ -0,0 +1,207 @@
+use crate::actions::{copy_to_clipboard_timed, UnlockingAction};
+use crate::ui::input::{ask_confirm, ask_index, ask_multiline_with_initial};
+use crate::ui::output::show_recovery_codes_table;
+use crate::vault::entities::{Error, RecoveryCodes};
+use crate::vault::vault_trait::Vault;
+use clap::ArgMatches;
+use std::fs;
+
+/// Stores the recovery codes a service gave when two-factor authentication
+/// was turned on, read from a file or pasted in.
+pub struct AddCodesAction {
+    pub service: String,
+    pub file: Option<String>,
+}
+
+/// Copies the next unused recovery code of a service and marks it used.
+pub struct UseCodeAction {
+    pub service: String,
+    pub warn_below: usize,
+}
+
+pub struct ListCodesAction {
+    pub grep: Option<String>,
+}
+
+pub struct DeleteCodesAction {
+    pub service: String,
+}
+
+impl AddCodesAction {
+    pub fn new(matches: &ArgMatches) -> AddCodesAction {
+        AddCodesAction {
+            service: matches.get_one::<String>("SERVICE").expect("required").to_string(),
+            file: matches.get_one::<String>("file").cloned(),
+        }
+    }
+}
+
+impl UseCodeAction {
+    pub fn new(matches: &ArgMatches) -> UseCodeAction {
+        UseCodeAction {
+            service: matches.get_one::<String>("SERVICE").expect("required").to_string(),
+            warn_below: matches.get_one::<usize>("warn-below").copied().unwrap_or(3),
+        }
+    }
+}
+
+impl ListCodesAction {
+    pub fn new(matches: &ArgMatches) -> ListCodesAction {
+        ListCodesAction {
+            grep: matches.get_one::<String>("SERVICE").cloned(),
+        }
+    }
+}
+
+impl DeleteCodesAction {
+    pub fn new(matches: &ArgMatches) -> DeleteCodesAction {
+        DeleteCodesAction {
+            service: matches.get_one::<String>("SERVICE").expect("required").to_string(),
+        }
+    }
+}
+
+/// Picks one of the recovery codes entries matching a service, asking which
+/// one when there are several.
+fn choose(mut matches: Vec<RecoveryCodes>, verb: &str) -> Result<Option<RecoveryCodes>, Error> {
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop()),
+        _ => {
+            show_recovery_codes_table(&matches, false);
+            let index = ask_index(
+                &format!("To {} one of these, please enter a row number from the table above", verb),
+                matches.len() as i16 - 1,
+                Some("Press q to exit"),
+            )
+            .map_err(|message| Error { message })?;
+            Ok(matches.get(index).cloned())
+        }
+    }
+}
+
+/// The warning shown after using a code, when fewer than `warn_below` unused
+/// codes are left.
+fn low_codes_warning(codes: &RecoveryCodes, warn_below: usize) -> Option<String> {
+    let unused = codes.unused_count();
+    if unused >= warn_below {
+        return None;
+    }
+    Some(match unused {
+        0 => format!(
+            "That was the last recovery code for {}. Generate new ones with the service and add them with `codes add`.",
+            codes.service()
+        ),
+        _ => format!(
+            "Only {} unused recovery codes left for {}. Consider generating new ones.",
+            unused,
+            codes.service()
+        ),
+    })
+}
+
+impl UnlockingAction for AddCodesAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let text = match &self.file {
+            Some(path) => fs::read_to_string(path)?,
+            None => ask_multiline_with_initial(
+                &format!("Paste the recovery codes for {}", self.service),
+                None,
+            ),
+        };
+        let codes = RecoveryCodes::parse_codes(&text)?;
+        let count = codes.len();
+        let existing = vault
+            .find_recovery_codes(Some(&self.service))
+            .into_iter()
+            .find(|entry| entry.service().eq_ignore_ascii_case(&self.service));
+        match existing {
+            Some(existing) => {
+                let question = format!(
+                    "{} already has {} recovery codes ({} unused). Replace them?",
+                    existing.service(),
+                    existing.codes().len(),
+                    existing.unused_count()
+                );
+                if !ask_confirm(&question, false) {
+                    return Ok(None);
+                }
+                let replaced = RecoveryCodes::new(Some(existing.id()), existing.service(), codes, None);
+                vault.update_recovery_codes(&replaced)?;
+            }
+            None => {
+                vault.save_recovery_codes(&RecoveryCodes::new(None, &self.service, codes, None))?;
+            }
+        }
+        Ok(Some(format!("Saved {} recovery codes for {}", count, self.service)))
+    }
+}
+
+impl UnlockingAction for UseCodeAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let mut codes = match choose(vault.find_recovery_codes(Some(&self.service)), "use")? {
+            Some(codes) => codes,
+            None => return Ok(Some("No matching recovery codes found".to_string())),
+        };
+        let code = codes.use_next().ok_or_else(|| {
+            Error::new(&format!("All recovery codes for {} have been used", codes.service()))
+        })?;
+        // Saved before copying, so that the code counts as used even if the
+        // wait for the clipboard to clear is interrupted.
+        vault.update_recovery_codes(&codes)?;
+        if let Some(warning) = low_codes_warning(&codes, self.warn_below) {
+            eprintln!("{}", warning);
+        }
+        println!("Recovery code copied to clipboard! Clipboard will be cleared in 20 seconds.");
+        copy_to_clipboard_timed(&code, 20);
+        Ok(None)
+    }
+}
+
+impl UnlockingAction for ListCodesAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let entries = vault.find_recovery_codes(self.grep.as_deref());
+        if entries.is_empty() {
+            return Ok(Some("No recovery codes found".to_string()));
+        }
+        show_recovery_codes_table(&entries, false);
+        Ok(None)
+    }
+}
+
+impl UnlockingAction for DeleteCodesAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let codes = match choose(vault.find_recovery_codes(Some(&self.service)), "delete")? {
+            Some(codes) => codes,
+            None => return Ok(Some("No matching recovery codes found".to_string())),
+        };
+        let question = format!(
+            "Delete the recovery codes for {} ({} unused)?",
+            codes.service(),
+            codes.unused_count()
+        );
+        if !ask_confirm(&question, false) {
+            return Ok(None);
+        }
+        vault.delete_recovery_codes(codes.id())?;
+        Ok(Some("Deleted".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_when_few_codes_are_left() {
+        let parsed = RecoveryCodes::parse_codes("aaaa bbbb cccc").unwrap();
+        let mut codes = RecoveryCodes::new(None, "GitHub", parsed, None);
+        codes.use_next();
+        assert_eq!(low_codes_warning(&codes, 2), None);
+        assert!(low_codes_warning(&codes, 3).unwrap().starts_with("Only 2 unused"));
+        codes.use_next();
+        codes.use_next();
+        assert!(low_codes_warning(&codes, 0).is_none());
+        assert!(low_codes_warning(&codes, 1).unwrap().contains("last recovery code"));
+    }
+}
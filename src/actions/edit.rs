@@ -1,8 +1,13 @@
 use clap::ArgMatches;
 
+use crate::actions::{copy_to_clipboard_timed, generate_password, parse_password_rules};
 use crate::completion_cache;
+use crate::crypto::strength::estimate_strength;
+use crate::crypto::otp::DEFAULT_RESYNC_WINDOW;
+use crate::crypto::PolicyOverrides;
+use crate::password_rules::PasswordRules;
 use crate::ui::output::{
-    show_credentials_table, show_notes_table, show_payment_cards_table, show_totp_table,
+    show_credentials_table, show_password_strength, show_notes_table, show_payment_cards_table, show_totp_table,
 };
 use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
 use crate::vault::vault_trait::Vault;
@@ -10,16 +15,51 @@ use crate::{handle_matches, ui, ItemType, MatchHandlerTemplate, UnlockingAction}
 
 struct EditCredentialsTemplate<'a> {
     vault: &'a mut Box<dyn Vault>,
+    generate: bool,
+    rules: Option<&'a str>,
 }
 
 impl<'a> EditCredentialsTemplate<'a> {
+    /// The rules given with `--rules`, otherwise the ones stored on the entry.
+    /// A regenerated password follows a known site's built-in rules if the
+    /// entry has none.
+    fn password_rules(&self, credential: &Credential) -> Result<Option<PasswordRules>, Error> {
+        let rules = parse_password_rules(self.rules.or(credential.password_rules()))?;
+        if self.generate && rules.is_none() && self.rules.is_none() {
+            return Ok(PasswordRules::for_service(credential.service()));
+        }
+        Ok(rules)
+    }
+
     fn edit_and_save_credential(
         &mut self,
         credential: &Credential,
     ) -> Result<Option<String>, Error> {
-        let updated = ui::input::ask_modified_credential(credential);
+        let rules = self.password_rules(credential)?;
+        let generated = if self.generate {
+            if let Some(rules) = &rules {
+                println!("Generating a password with the rules: {}", rules);
+            }
+            Some(generate_password(&PolicyOverrides::default(), rules.as_ref())?)
+        } else {
+            None
+        };
+        let rules = rules.map(|r| r.to_string());
+        let updated = ui::input::ask_modified_credential(credential, generated.as_deref())
+            .with_password_rules(rules.as_deref())
+            .with_totp_id(credential.totp_id().copied());
+        if generated.is_none() && updated.password() != credential.password() {
+            show_password_strength(&estimate_strength(
+                updated.password(),
+                &[updated.service(), updated.username()],
+            ));
+        }
         println!("Saving...");
         self.vault.update_credential(updated)?;
+        if let Some(password) = generated {
+            println!("Password copied to clipboard! Clipboard will be cleared in 20 seconds.");
+            copy_to_clipboard_timed(&password, 20);
+        }
         Ok(Some("Saved".to_string()))
     }
 }
@@ -155,10 +195,20 @@ impl<'a> MatchHandlerTemplate for EditPaymentTemplate<'a> {
 
 struct EditTotpTemplate<'a> {
     vault: &'a mut Box<dyn Vault>,
+    resync: Option<&'a str>,
+    window: u64,
 }
 
 impl<'a> EditTotpTemplate<'a> {
     fn edit_and_save(&mut self, totp: &Totp) -> Result<Option<String>, Error> {
+        if let Some(code) = self.resync {
+            let resynced = totp.resynced(code, self.window)?;
+            self.vault.save_hotp_counter(&resynced)?;
+            return Ok(Some(format!(
+                "Resynchronized, the next code is for counter {}",
+                resynced.counter().unwrap_or(0)
+            )));
+        }
         let updated = ui::input::ask_modified_totp(totp);
         println!("Saving...");
         self.vault.update_totp(updated)?;
@@ -205,6 +255,10 @@ pub struct EditAction {
     pub grep: Option<String>,
     pub item_type: ItemType,
     pub is_totp: bool,
+    pub generate: bool,
+    pub rules: Option<String>,
+    pub resync: Option<String>,
+    pub window: u64,
 }
 
 impl EditAction {
@@ -213,6 +267,13 @@ impl EditAction {
             grep: matches.get_one::<String>("REGEXP").cloned(),
             item_type: ItemType::new_from_args(matches),
             is_totp: matches.get_one::<bool>("otp").map_or(false, |v| *v),
+            generate: matches.get_one::<bool>("generate").is_some_and(|v| *v),
+            rules: matches.get_one::<String>("rules").cloned(),
+            resync: matches.get_one::<String>("resync").cloned(),
+            window: matches
+                .get_one::<u64>("window")
+                .copied()
+                .unwrap_or(DEFAULT_RESYNC_WINDOW),
         }
     }
 }
@@ -235,7 +296,11 @@ impl UnlockingAction for EditAction {
                 };
                 handle_matches(
                     vault.grep(Some(grep)),
-                    &mut Box::new(EditCredentialsTemplate { vault }),
+                    &mut Box::new(EditCredentialsTemplate {
+                        vault,
+                        generate: self.generate,
+                        rules: self.rules.as_deref(),
+                    }),
                 )
             }
             ItemType::Payment => handle_matches(
@@ -248,7 +313,11 @@ impl UnlockingAction for EditAction {
             ),
             ItemType::Totp => handle_matches(
                 vault.find_totp(self.grep.as_deref()),
-                &mut Box::new(EditTotpTemplate { vault }),
+                &mut Box::new(EditTotpTemplate {
+                    vault,
+                    resync: self.resync.as_deref(),
+                    window: self.window,
+                }),
             ),
         };
         if result.is_ok() {
@@ -1,9 +1,12 @@
 use clap::ArgMatches;
 use log::debug;
-use crate::actions::{ItemType, UnlockingAction};
+use crate::actions::{unlock_totp_vault, ItemType, UnlockingAction};
 use crate::store;
-use crate::vault::entities::Error;
-use crate::vault::vault_trait::Vault;
+use crate::ui::input::ask_export_password;
+use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
+use crate::vault::keepass_vault::KeepassVault;
+use crate::vault::vault_trait::{TotpVault, Vault};
+use std::path::Path;
 
 pub struct ExportAction {
     pub file_path: String,
@@ -38,6 +41,123 @@ impl ExportAction {
     }
 }
 
+/// Copies the matching entries into a new KeePass file with a password of its
+/// own, for sharing a few of them without leaving secrets on disk in plaintext.
+pub struct ExportKdbxAction {
+    pub file_path: String,
+    pub pattern: Option<String>,
+    pub keyfile: Option<String>,
+    pub with_otp: bool,
+}
+
+impl ExportKdbxAction {
+    pub fn new(matches: &ArgMatches) -> ExportKdbxAction {
+        ExportKdbxAction {
+            file_path: matches.get_one::<String>("file_path").expect("required").to_string(),
+            pattern: matches.get_one::<String>("PATTERN").cloned(),
+            keyfile: matches.get_one::<String>("keyfile").cloned(),
+            with_otp: matches.get_one::<bool>("with-otp").is_some_and(|v| *v),
+        }
+    }
+}
+
+/// The entries `export --kdbx` copies.
+#[derive(Default)]
+struct KdbxSelection {
+    credentials: Vec<Credential>,
+    payments: Vec<PaymentCard>,
+    notes: Vec<Note>,
+    totps: Vec<Totp>,
+}
+
+impl KdbxSelection {
+    /// The credentials whose service or username, the cards whose name or
+    /// name on card, and the notes whose title contains `pattern`, ignoring
+    /// case. Everything without a pattern.
+    fn matching(vault: &dyn Vault, pattern: Option<&str>) -> KdbxSelection {
+        let pattern = pattern.map(str::to_lowercase);
+        let matches = |text: &str| pattern.as_ref().is_none_or(|p| text.to_lowercase().contains(p));
+        KdbxSelection {
+            credentials: vault.grep(pattern.as_deref()),
+            payments: vault
+                .find_payments()
+                .into_iter()
+                .filter(|card| matches(card.name()) || matches(card.name_on_card()))
+                .collect(),
+            notes: vault.find_notes().into_iter().filter(|note| matches(note.title())).collect(),
+            totps: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.credentials.is_empty() && self.payments.is_empty() && self.notes.is_empty()
+    }
+
+    /// The authorizers of the TOTP vault linked to the selected credentials.
+    fn add_linked_totps(&mut self, totp_vault: &dyn Vault) {
+        let linked: Vec<&uuid::Uuid> = self.credentials.iter().filter_map(Credential::totp_id).collect();
+        self.totps = totp_vault
+            .find_totp(None)
+            .into_iter()
+            .filter(|totp| linked.contains(&totp.id()))
+            .collect();
+    }
+}
+
+/// Writes `selection` to a new KeePass file, keeping the UUIDs of the
+/// entries. Credentials whose authorizer is not copied lose the link to it.
+fn write_kdbx(
+    file_path: &str,
+    password: &str,
+    keyfile: Option<&str>,
+    selection: KdbxSelection,
+) -> Result<String, Error> {
+    let credentials: Vec<Credential> = selection
+        .credentials
+        .into_iter()
+        .map(|credential| match credential.totp_id() {
+            Some(id) if !selection.totps.iter().any(|totp| totp.id() == id) => {
+                credential.with_totp_id(None)
+            }
+            _ => credential,
+        })
+        .collect();
+    let mut out = KeepassVault::new(file_path, password, keyfile)?;
+    out.save_to_folder(None, &credentials, &selection.payments, &selection.notes)?;
+    if !selection.totps.is_empty() {
+        out.save_totps(&selection.totps)?;
+    }
+    Ok(format!(
+        "Exported {} credentials, {} payment cards, {} notes and {} authorizers to {}",
+        credentials.len(),
+        selection.payments.len(),
+        selection.notes.len(),
+        selection.totps.len(),
+        file_path
+    ))
+}
+
+impl UnlockingAction for ExportKdbxAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        if Path::new(&self.file_path).exists() {
+            return Err(Error::new(&format!(
+                "'{}' already exists, choose another file name",
+                self.file_path
+            )));
+        }
+        let mut selection = KdbxSelection::matching(vault.as_ref(), self.pattern.as_deref());
+        if selection.is_empty() {
+            return Ok(Some("No matching entries found".to_string()));
+        }
+        if self.with_otp && selection.credentials.iter().any(|c| c.totp_id().is_some()) {
+            selection.add_linked_totps(unlock_totp_vault()?.as_ref());
+        }
+        let password = ask_export_password(self.keyfile.is_some());
+        let summary = write_kdbx(&self.file_path, &password, self.keyfile.as_deref(), selection)?;
+        Ok(Some(summary))
+    }
+}
+
 impl UnlockingAction for ExportAction {
     fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
         let count = self.export_csv(vault)?;
@@ -57,3 +177,70 @@ impl UnlockingAction for ExportAction {
         Ok(Some(format!("Exported {} entries", count)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::entities::Expiry;
+    use crate::vault::vault_trait::{NoteVault, PasswordVault, PaymentVault};
+
+    #[test]
+    fn exports_matching_entries_with_their_uuids() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let totp = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let other = Totp::new(None, url, "GitLab:alice", "GitLab", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let mut totp_vault = KeepassVault::new(&path("totp.kdbx"), "master-pw", None).unwrap();
+        totp_vault.save_totps(&[totp.clone(), other]).unwrap();
+
+        let mut vault = KeepassVault::new(&path("vault.kdbx"), "master-pw", None).unwrap();
+        let github = Credential::new(None, "pw", "github.com", "alice", None, None)
+            .with_totp_id(Some(*totp.id()))
+            .with_tags(vec!["shared".to_string()]);
+        let gitlab = Credential::new(None, "pw", "gitlab.com", "alice", None, None)
+            .with_totp_id(Some(uuid::Uuid::new_v4()));
+        let mail = Credential::new(None, "pw", "mail.com", "bob", None, None);
+        vault.save_credentials(&vec![github.clone(), gitlab.clone(), mail]).unwrap();
+        let card = PaymentCard::new(None, "Git card", "Alice", "4111111111111111", "123", Expiry { month: 7, year: 2027 }, None, None, None);
+        vault.save_payment(card.clone()).unwrap();
+        vault.save_note(&Note::new(None, "Wifi", "hunter2", None)).unwrap();
+
+        let mut selection = KdbxSelection::matching(&vault, Some("GIT"));
+        selection.add_linked_totps(&totp_vault);
+        let summary = write_kdbx(&path("out.kdbx"), "export-pw", None, selection).unwrap();
+        assert_eq!(
+            summary,
+            format!("Exported 2 credentials, 1 payment cards, 0 notes and 1 authorizers to {}", path("out.kdbx"))
+        );
+
+        let out = KeepassVault::open("export-pw", &path("out.kdbx"), None).unwrap();
+        let credentials = out.grep(None);
+        let exported = credentials.iter().find(|c| c.uuid() == github.uuid()).unwrap();
+        assert_eq!(exported.totp_id(), Some(totp.id()));
+        assert_eq!(exported.tags(), ["shared".to_string()]);
+        let unlinked = credentials.iter().find(|c| c.uuid() == gitlab.uuid()).unwrap();
+        assert_eq!(unlinked.totp_id(), None);
+        assert_eq!(out.find_payments()[0].id(), card.id());
+        assert_eq!(out.find_totp(None).len(), 1);
+        assert_eq!(out.find_totp(None)[0].id(), totp.id());
+        assert!(KeepassVault::open("master-pw", &path("out.kdbx"), None).is_err());
+    }
+
+    #[test]
+    fn exports_under_a_key_file_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("share.key"), b"some random key file content").unwrap();
+        let mut vault = KeepassVault::new(&path("vault.kdbx"), "master-pw", None).unwrap();
+        vault.save_note(&Note::new(None, "Wifi", "hunter2", None)).unwrap();
+
+        let selection = KdbxSelection::matching(&vault, None);
+        write_kdbx(&path("out.kdbx"), "", Some(&path("share.key")), selection).unwrap();
+
+        let keyfile = Some(path("share.key"));
+        let out = KeepassVault::open("", &path("out.kdbx"), keyfile.clone()).unwrap();
+        assert_eq!(out.find_notes()[0].title(), "Wifi");
+        assert!(KeepassVault::open("pw", &path("out.kdbx"), keyfile).is_err());
+    }
+}
@@ -1,23 +1,38 @@
-use crate::actions::{copy_to_clipboard_timed, Action};
-use crate::crypto;
+use crate::actions::{
+    copy_to_clipboard_timed, generate_password, parse_password_rules, password_policy,
+    policy_overrides_from_args, Action,
+};
+use crate::crypto::PolicyOverrides;
+use crate::store;
 use crate::vault::entities::Error;
 use clap::ArgMatches;
 
 pub struct GeneratePasswordAction {
     pub stdout_only: bool,
+    pub policy: PolicyOverrides,
+    pub save_defaults: bool,
+    pub rules: Option<String>,
 }
 
 impl GeneratePasswordAction {
     pub fn new(matches: &ArgMatches) -> GeneratePasswordAction {
         GeneratePasswordAction {
             stdout_only: matches.get_one::<bool>("out").map_or(false, |v| *v),
+            policy: policy_overrides_from_args(matches),
+            save_defaults: matches.get_one::<bool>("save-defaults").is_some_and(|v| *v),
+            rules: matches.get_one::<String>("rules").cloned(),
         }
     }
 }
 
 impl Action for GeneratePasswordAction {
     fn run(&self) -> Result<String, Error> {
-        let password = crypto::generate();
+        if self.save_defaults {
+            let path = store::save_password_policy(&password_policy(&self.policy)?)?;
+            eprintln!("Password generator defaults saved to {}", path);
+        }
+        let rules = parse_password_rules(self.rules.as_deref())?;
+        let password = generate_password(&self.policy, rules.as_ref())?;
         if self.stdout_only {
             Ok(password)
         } else {
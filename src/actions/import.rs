@@ -1,12 +1,28 @@
-use crate::actions::UnlockingAction;
+use crate::actions::{unlock, unlock_totp_vault, ItemType, OnConflict, UnlockingAction};
 use crate::completion_cache;
+use crate::importers::{self, ImportedEntries, InvalidRow, SkippedField};
 use crate::store;
-use crate::vault::entities::Error;
+use crate::ui::input::ask_password;
+use crate::ui::output::{
+    show_credentials_table, show_notes_table, show_payment_cards_table, show_totp_table,
+};
+use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
+use crate::vault::keepass_vault::KeepassVault;
 use crate::vault::vault_trait::Vault;
 use clap::ArgMatches;
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+use zeroize::Zeroize;
 
+/// Imports the logins of a CSV export: passlane's own, or that of a browser
+/// or another password manager. With `-p` or `-n`, imports the payment cards
+/// or secure notes of passlane's own export.
 pub struct ImportCsvAction {
     pub file_path: String,
+    pub item_type: ItemType,
+    pub format: Option<String>,
+    pub on_conflict: OnConflict,
 }
 
 impl ImportCsvAction {
@@ -16,25 +32,606 @@ impl ImportCsvAction {
                 .get_one::<String>("FILE_PATH")
                 .expect("required")
                 .to_string(),
+            item_type: ItemType::new_from_args(matches),
+            format: matches.get_one::<String>("format").cloned(),
+            on_conflict: matches
+                .get_one::<String>("on-conflict")
+                .and_then(|mode| mode.parse().ok())
+                .unwrap_or(OnConflict::Skip),
         }
     }
 }
 
-fn push_from_csv(vault: &mut Box<dyn Vault>, file_path: &str) -> Result<i64, Error> {
-    let creds = store::read_from_csv(file_path)?;
-    vault.save_credentials(&creds)?;
-    let num_imported = creds.len();
-    Ok(num_imported.try_into().unwrap())
+/// Imports the logins, cards and notes of another password manager's export.
+pub struct ImportAction {
+    pub file_path: String,
+    pub format: Option<String>,
+    pub on_conflict: OnConflict,
+    pub dry_run: bool,
+}
+
+impl ImportAction {
+    pub fn new(matches: &ArgMatches) -> ImportAction {
+        ImportAction {
+            file_path: matches
+                .get_one::<String>("FILE_PATH")
+                .expect("required")
+                .to_string(),
+            format: matches.get_one::<String>("format").cloned(),
+            on_conflict: matches
+                .get_one::<String>("on-conflict")
+                .and_then(|mode| mode.parse().ok())
+                .unwrap_or(OnConflict::Skip),
+            dry_run: matches.get_one::<bool>("dry-run").is_some_and(|v| *v),
+        }
+    }
+}
+
+/// Copies the entries of another KeePass file into the vault, unlocking it
+/// with its own password and key file.
+pub struct ImportKdbxAction {
+    pub file_path: String,
+    pub keyfile: Option<String>,
+    pub group: Option<String>,
+    pub new_uuids: bool,
+    pub on_conflict: OnConflict,
+    pub dry_run: bool,
+}
+
+impl ImportKdbxAction {
+    pub fn new(matches: &ArgMatches) -> ImportKdbxAction {
+        ImportKdbxAction {
+            file_path: matches
+                .get_one::<String>("FILE_PATH")
+                .expect("required")
+                .to_string(),
+            keyfile: matches.get_one::<String>("keyfile").cloned(),
+            group: matches.get_one::<String>("group").cloned(),
+            new_uuids: matches.get_one::<bool>("new-uuids").is_some_and(|v| *v),
+            on_conflict: matches
+                .get_one::<String>("on-conflict")
+                .and_then(|mode| mode.parse().ok())
+                .unwrap_or(OnConflict::Skip),
+            dry_run: matches.get_one::<bool>("dry-run").is_some_and(|v| *v),
+        }
+    }
+
+    fn open_source(&self) -> Result<KeepassVault, Error> {
+        for path in std::iter::once(&self.file_path).chain(self.keyfile.as_ref()) {
+            if !Path::new(path).is_file() {
+                return Err(Error::new(&format!("Cannot read {}: no such file", path)));
+            }
+        }
+        let mut password = ask_password(&format!("Please enter the password of {}", self.file_path), None);
+        let source = KeepassVault::open(&password, &self.file_path, self.keyfile.clone());
+        password.zeroize();
+        source
+    }
+}
+
+/// The changes an import makes to the vault.
+#[derive(Default)]
+pub(crate) struct ImportPlan {
+    pub new: Vec<Credential>,
+    pub updates: Vec<Credential>,
+    pub skipped: usize,
+    pub duplicated: usize,
+}
+
+impl ImportPlan {
+    /// Sorts `incoming` into new entries and updates. An incoming credential
+    /// conflicts with an existing one for the same account, or with one
+    /// earlier in the same import.
+    pub fn new(existing: &[Credential], incoming: Vec<Credential>, on_conflict: OnConflict) -> ImportPlan {
+        let mut plan = ImportPlan::default();
+        for credential in incoming {
+            let conflict = existing
+                .iter()
+                .chain(plan.updates.iter())
+                .chain(plan.new.iter())
+                .find(|other| other.is_same_account(&credential))
+                .cloned();
+            match (conflict, on_conflict) {
+                (None, _) => plan.new.push(credential),
+                (Some(_), OnConflict::Skip) => plan.skipped += 1,
+                (Some(_), OnConflict::Duplicate) => {
+                    plan.duplicated += 1;
+                    plan.new.push(credential);
+                }
+                (Some(other), OnConflict::Update) => {
+                    let updated = credential.replacing(&other);
+                    if let Some(i) = plan.new.iter().position(|c| c.uuid() == other.uuid()) {
+                        plan.new[i] = updated;
+                    } else if let Some(i) = plan.updates.iter().position(|c| c.uuid() == other.uuid()) {
+                        plan.updates[i] = updated;
+                    } else {
+                        plan.updates.push(updated);
+                    }
+                }
+            }
+        }
+        plan
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Imported {} new entries, updated {}, skipped {}, duplicated {}",
+            self.new.len() - self.duplicated,
+            self.updates.len(),
+            self.skipped,
+            self.duplicated
+        )
+    }
+}
+
+/// Splits the imported authorizers into those not in the TOTP vault yet and
+/// the UUIDs of those that already are, by the UUID of the imported one.
+fn sort_imported_totps(existing: &[Totp], totps: Vec<Totp>) -> (Vec<Totp>, HashMap<Uuid, Uuid>) {
+    let mut new: Vec<Totp> = Vec::new();
+    let mut already_saved = HashMap::new();
+    for totp in totps {
+        match existing.iter().chain(new.iter()).find(|other| other.is_same_authorizer(&totp)) {
+            Some(other) => {
+                already_saved.insert(*totp.id(), *other.id());
+            }
+            None => new.push(totp),
+        }
+    }
+    (new, already_saved)
+}
+
+/// Saves `entries` and returns a summary per entry type. The authorizers of
+/// skipped credentials are left out, and so are all authorizers without a
+/// TOTP vault to unlock. The TOTP vault is only unlocked when there are
+/// authorizers to save, and written after the vault so that a failed save
+/// leaves no authorizers behind.
+fn save_imported(
+    vault: &mut Box<dyn Vault>,
+    unlock_totps: Option<impl FnOnce() -> Result<Box<dyn Vault>, Error>>,
+    entries: ImportedEntries,
+    on_conflict: OnConflict,
+) -> Result<String, Error> {
+    let entries = if unlock_totps.is_some() { entries } else { without_totps(entries) };
+    let folders = entries.folders();
+    let folder_of: HashMap<Uuid, Option<String>> = entries
+        .credentials
+        .iter()
+        .map(|c| (*c.entry.uuid(), c.folder.clone()))
+        .collect();
+    let incoming: Vec<Credential> = entries.credentials.into_iter().map(|c| c.entry).collect();
+    let incoming_links: Vec<Uuid> = incoming.iter().filter_map(|c| c.totp_id().copied()).collect();
+    let mut plan = ImportPlan::new(&vault.grep(None), incoming, on_conflict);
+
+    let linked: Vec<Uuid> = plan
+        .new
+        .iter()
+        .chain(plan.updates.iter())
+        .filter_map(|c| c.totp_id().copied())
+        .collect();
+    let totps: Vec<Totp> = entries
+        .totps
+        .into_iter()
+        .filter(|totp| linked.contains(totp.id()) || !incoming_links.contains(totp.id()))
+        .collect();
+    let mut totp_summary = String::new();
+    let mut new_totps = None;
+    if !totps.is_empty() {
+        let unlock_totps = unlock_totps.expect("authorizers are left out without a TOTP vault");
+        let totp_vault = unlock_totps()?;
+        let count = totps.len();
+        let (new, already_saved) = sort_imported_totps(&totp_vault.find_totp(None), totps);
+        for credential in plan.new.iter_mut().chain(plan.updates.iter_mut()) {
+            if let Some(existing) = credential.totp_id().and_then(|id| already_saved.get(id)) {
+                *credential = credential.clone().with_totp_id(Some(*existing));
+            }
+        }
+        totp_summary = format!(
+            "\nAuthorizers: imported {}, skipped {} already in the TOTP vault",
+            new.len(),
+            count - new.len()
+        );
+        new_totps = Some((totp_vault, new));
+    }
+
+    let existing_payments = vault.find_payments();
+    let (payments, skipped_payments): (Vec<_>, Vec<_>) = entries
+        .payments
+        .into_iter()
+        .partition(|p| !existing_payments.iter().any(|e| e.number() == p.entry.number()));
+    let existing_notes = vault.find_notes();
+    let (notes, skipped_notes): (Vec<_>, Vec<_>) = entries.notes.into_iter().partition(|n| {
+        !existing_notes
+            .iter()
+            .any(|e| e.title() == n.entry.title() && e.content() == n.entry.content())
+    });
+
+    for folder in &folders {
+        let credentials: Vec<Credential> = plan
+            .new
+            .iter()
+            .filter(|c| folder_of.get(c.uuid()).is_some_and(|f| f == folder))
+            .cloned()
+            .collect();
+        let folder_payments: Vec<PaymentCard> = payments
+            .iter()
+            .filter(|p| p.folder == *folder)
+            .map(|p| p.entry.clone())
+            .collect();
+        let folder_notes: Vec<Note> = notes
+            .iter()
+            .filter(|n| n.folder == *folder)
+            .map(|n| n.entry.clone())
+            .collect();
+        if !credentials.is_empty() || !folder_payments.is_empty() || !folder_notes.is_empty() {
+            vault.save_to_folder(folder.as_deref(), &credentials, &folder_payments, &folder_notes)?;
+        }
+    }
+    for credential in &plan.updates {
+        vault.update_credential(credential.clone())?;
+    }
+    if let Some((mut totp_vault, new)) = new_totps.filter(|(_, new)| !new.is_empty()) {
+        totp_vault.save_totps(&new)?;
+    }
+
+    let mut summary = format!(
+        "Credentials: {}\nPayment cards: imported {}, skipped {} already in the vault\nNotes: imported {}, skipped {} already in the vault{}",
+        plan.summary(),
+        payments.len(),
+        skipped_payments.len(),
+        notes.len(),
+        skipped_notes.len(),
+        totp_summary
+    );
+    summary.push_str(&skipped_fields_report(&entries.skipped));
+    summary.push_str(&invalid_rows_report(&entries.invalid_rows));
+    Ok(summary)
+}
+
+fn skipped_fields_report(skipped: &[SkippedField]) -> String {
+    if skipped.is_empty() {
+        return String::new();
+    }
+    let mut report = String::from("\nSkipped fields:");
+    for field in skipped {
+        report.push_str(&format!("\n  {}: {}", field.entry, field.field));
+    }
+    report
+}
+
+fn invalid_rows_report(rows: &[InvalidRow]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let mut report = String::from("\nRows not imported:");
+    for row in rows {
+        report.push_str(&format!("\n  line {}: {}", row.line, row.message));
+    }
+    report
+}
+
+/// Shows what an import would save, without unlocking the vault. Conflicts
+/// with entries already in the vault are not checked.
+fn preview(entries: &ImportedEntries) -> String {
+    let group_name = |folder: &Option<String>| folder.as_deref().unwrap_or("(no folder)").to_string();
+    let credentials: Vec<Credential> = entries.credentials.iter().map(|c| c.entry.clone()).collect();
+    let payments: Vec<PaymentCard> = entries.payments.iter().map(|p| p.entry.clone()).collect();
+    let notes: Vec<Note> = entries.notes.iter().map(|n| n.entry.clone()).collect();
+    if !credentials.is_empty() {
+        println!("Credentials:");
+        show_credentials_table(&credentials, false, false);
+    }
+    if !payments.is_empty() {
+        println!("Payment cards:");
+        show_payment_cards_table(&payments, false, false);
+    }
+    if !notes.is_empty() {
+        println!("Notes:");
+        show_notes_table(&notes, false, false);
+    }
+    if !entries.totps.is_empty() {
+        println!("Authorizers:");
+        show_totp_table(&entries.totps, false);
+    }
+    format!(
+        "Would import {} credentials, {} payment cards, {} notes and {} authorizers into the groups {}. Nothing was saved.{}",
+        credentials.len(),
+        payments.len(),
+        notes.len(),
+        entries.totps.len(),
+        entries.folders().iter().map(group_name).collect::<Vec<_>>().join(", "),
+        skipped_fields_report(&entries.skipped) + &invalid_rows_report(&entries.invalid_rows)
+    )
+}
+
+fn read_export(file_path: &str) -> Result<Vec<u8>, Error> {
+    std::fs::read(file_path).map_err(|e| Error::new(&format!("Cannot read {}: {}", file_path, e)))
+}
+
+/// Leaves the authorizers out of `entries` when there is no TOTP vault to
+/// save them in, listing them as skipped.
+fn without_totps(mut entries: ImportedEntries) -> ImportedEntries {
+    for totp in std::mem::take(&mut entries.totps) {
+        entries.skipped.push(SkippedField::new(totp.label(), "one time password (no TOTP vault configured)"));
+    }
+    for credential in &mut entries.credentials {
+        credential.entry = credential.entry.clone().with_totp_id(None);
+    }
+    entries
+}
+
+/// `entries` as they can be saved: without authorizers when no TOTP vault is
+/// configured.
+fn saveable(entries: ImportedEntries) -> ImportedEntries {
+    if store::has_totp_vault_path() { entries } else { without_totps(entries) }
+}
+
+/// Unlocks the vault and saves `entries` in it, and their authorizers in the
+/// TOTP vault when one is configured.
+fn import_entries(entries: ImportedEntries, on_conflict: OnConflict) -> Result<Option<String>, Error> {
+    let mut vault = unlock()?;
+    let unlock_totps = store::has_totp_vault_path().then_some(unlock_totp_vault);
+    let summary = save_imported(&mut vault, unlock_totps, entries, on_conflict)?;
+    completion_cache::update_cache(&vault);
+    Ok(Some(summary))
+}
+
+impl UnlockingAction for ImportAction {
+    /// Reads the export before the vault is unlocked.
+    fn execute(&self) -> Result<Option<String>, Error> {
+        let content = read_export(&self.file_path)?;
+        let entries =
+            importers::parse_export(&content, self.format.as_deref(), importers::importers())?;
+        if self.dry_run {
+            return Ok(Some(preview(&saveable(entries))));
+        }
+        import_entries(entries, self.on_conflict)
+    }
+}
+
+impl UnlockingAction for ImportKdbxAction {
+    /// Reads the other file before the vault is unlocked.
+    fn execute(&self) -> Result<Option<String>, Error> {
+        let source = self.open_source()?;
+        let entries = importers::kdbx::read_kdbx(&source, self.group.as_deref(), self.new_uuids);
+        if self.dry_run {
+            return Ok(Some(preview(&saveable(entries))));
+        }
+        import_entries(entries, self.on_conflict)
+    }
 }
 
 impl UnlockingAction for ImportCsvAction {
-    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
-        let result = push_from_csv(vault, &self.file_path)
-            .map(|count| format!("Imported {} entries", count))
-            .map(Some);
-        if result.is_ok() {
-            completion_cache::update_cache(vault);
-        }
-        result
+    /// Reads the CSV before the vault is unlocked. A CSV whose header no
+    /// importer recognizes is read in passlane's layout.
+    fn execute(&self) -> Result<Option<String>, Error> {
+        let content = read_export(&self.file_path)?;
+        let importers = importers::csv::importers();
+        let format = match (&self.format, &self.item_type) {
+            (_, ItemType::Payment) => Some("passlane-cards"),
+            (_, ItemType::Note) => Some("passlane-notes"),
+            (Some(format), _) => Some(format.as_str()),
+            (None, _) if importers.iter().any(|importer| importer.detect(&content)) => None,
+            (None, _) => Some("passlane"),
+        };
+        let entries = importers::parse_export(&content, format, importers)?;
+        import_entries(entries, self.on_conflict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(service: &str, username: &str, password: &str) -> Credential {
+        Credential::new(None, password, service, username, None, None)
+    }
+
+    fn existing() -> Vec<Credential> {
+        vec![credential("github.com", "alice", "old")]
+    }
+
+    fn incoming() -> Vec<Credential> {
+        vec![
+            credential("https://github.com/login", "Alice", "new"),
+            credential("gitlab.com", "alice", "other"),
+            credential("gitlab.com", "alice", "again"),
+        ]
+    }
+
+    #[test]
+    fn skip_keeps_existing_entries() {
+        let plan = ImportPlan::new(&existing(), incoming(), OnConflict::Skip);
+        assert_eq!(plan.new.len(), 1);
+        assert_eq!(plan.new[0].password(), "other");
+        assert!(plan.updates.is_empty());
+        assert_eq!(plan.skipped, 2);
+        assert_eq!(plan.summary(), "Imported 1 new entries, updated 0, skipped 2, duplicated 0");
+    }
+
+    #[test]
+    fn update_replaces_existing_entries() {
+        let existing = existing();
+        let plan = ImportPlan::new(&existing, incoming(), OnConflict::Update);
+        assert_eq!(plan.updates.len(), 1);
+        assert_eq!(plan.updates[0].uuid(), existing[0].uuid());
+        assert_eq!(plan.updates[0].password(), "new");
+        assert_eq!(plan.new.len(), 1);
+        assert_eq!(plan.new[0].password(), "again");
+        assert_eq!(plan.summary(), "Imported 1 new entries, updated 1, skipped 0, duplicated 0");
+    }
+
+    #[test]
+    fn duplicate_creates_every_entry() {
+        let plan = ImportPlan::new(&existing(), incoming(), OnConflict::Duplicate);
+        assert_eq!(plan.new.len(), 3);
+        assert_eq!(plan.duplicated, 2);
+        assert_eq!(plan.summary(), "Imported 1 new entries, updated 0, skipped 0, duplicated 2");
+    }
+
+    #[test]
+    fn saves_imported_entries_and_links_existing_authorizers() {
+        use crate::importers::Imported;
+        use crate::vault::vault_trait::TotpVault;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let mut totp_vault = KeepassVault::new(&path("totp.kdbx"), "master-pw", None).unwrap();
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let existing = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        totp_vault.save_totp(&existing).unwrap();
+        let existing = totp_vault.find_totp(None).remove(0);
+
+        let imported = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let entries = ImportedEntries {
+            credentials: vec![Imported {
+                entry: credential("github.com", "alice", "pw").with_totp_id(Some(*imported.id())),
+                folder: Some("Work".to_string()),
+            }],
+            notes: vec![Imported { entry: Note::new(None, "Wifi", "hunter2", None), folder: None }],
+            totps: vec![imported],
+            ..ImportedEntries::default()
+        };
+        let mut vault: Box<dyn Vault> =
+            Box::new(KeepassVault::new(&path("vault.kdbx"), "master-pw", None).unwrap());
+        let totp_path = path("totp.kdbx");
+        let unlock_totps = || -> Result<Box<dyn Vault>, Error> {
+            Ok(Box::new(KeepassVault::open("master-pw", &totp_path, None)?))
+        };
+        let summary = save_imported(&mut vault, Some(unlock_totps), entries, OnConflict::Skip).unwrap();
+
+        assert_eq!(
+            summary,
+            "Credentials: Imported 1 new entries, updated 0, skipped 0, duplicated 0\n\
+             Payment cards: imported 0, skipped 0 already in the vault\n\
+             Notes: imported 1, skipped 0 already in the vault\n\
+             Authorizers: imported 0, skipped 1 already in the TOTP vault"
+        );
+        assert_eq!(vault.grep(None)[0].totp_id(), Some(existing.id()));
+        assert_eq!(vault.find_notes().len(), 1);
+    }
+
+    #[test]
+    fn imports_an_export_without_a_totp_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let mut vault: Box<dyn Vault> =
+            Box::new(KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap());
+        let entries = importers::parse_export(
+            include_bytes!("../importers/fixtures/bitwarden.json"),
+            None,
+            importers::importers(),
+        )
+        .unwrap();
+        let summary = save_imported(&mut vault, None::<fn() -> _>, entries, OnConflict::Skip).unwrap();
+
+        assert!(summary.contains("GitHub:alice: one time password (no TOTP vault configured)"), "{}", summary);
+        assert!(!summary.contains("Authorizers:"));
+        assert!(vault.grep(None).iter().all(|c| c.totp_id().is_none()));
+    }
+
+    #[test]
+    fn leaves_no_authorizers_behind_when_the_vault_save_fails() {
+        use crate::importers::Imported;
+        use crate::vault::vault_trait::TotpVault;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        KeepassVault::new(&path("totp.kdbx"), "master-pw", None).unwrap();
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let totp = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let entries = ImportedEntries {
+            credentials: vec![Imported {
+                entry: credential("github.com", "alice\u{1}", "pw").with_totp_id(Some(*totp.id())),
+                folder: None,
+            }],
+            totps: vec![totp],
+            ..ImportedEntries::default()
+        };
+        let mut vault: Box<dyn Vault> =
+            Box::new(KeepassVault::new(&path("vault.kdbx"), "master-pw", None).unwrap());
+        let totp_path = path("totp.kdbx");
+        let unlock_totps = || -> Result<Box<dyn Vault>, Error> {
+            Ok(Box::new(KeepassVault::open("master-pw", &totp_path, None)?))
+        };
+        assert!(save_imported(&mut vault, Some(unlock_totps), entries, OnConflict::Skip).is_err());
+        assert!(KeepassVault::open("master-pw", &totp_path, None).unwrap().find_totp(None).is_empty());
+    }
+
+    #[test]
+    fn imports_another_kdbx_file_once() {
+        use crate::vault::vault_trait::{NoteVault, PasswordVault, TotpVault};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let mut source = KeepassVault::new(&path("shared.kdbx"), "shared-pw", None).unwrap();
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let linked = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let url = "otpauth://totp/VPN:alice?secret=KRSXG5CTMVRXEZLU&issuer=VPN";
+        let unlinked = Totp::new(None, url, "VPN:alice", "VPN", "KRSXG5CTMVRXEZLU", "SHA1", 30, 6, None);
+        source.save_totps(&[linked.clone(), unlinked]).unwrap();
+        source
+            .save_credentials(&vec![credential("github.com", "alice", "pw").with_totp_id(Some(*linked.id()))])
+            .unwrap();
+        source.save_note(&Note::new(None, "Wifi", "hunter2", None)).unwrap();
+
+        KeepassVault::new(&path("totp.kdbx"), "master-pw", None).unwrap();
+        let mut vault: Box<dyn Vault> =
+            Box::new(KeepassVault::new(&path("vault.kdbx"), "master-pw", None).unwrap());
+        let totp_path = path("totp.kdbx");
+        let unlock_totps = || -> Result<Box<dyn Vault>, Error> {
+            Ok(Box::new(KeepassVault::open("master-pw", &totp_path, None)?))
+        };
+        let entries = importers::kdbx::read_kdbx(&source, Some("Partner"), false);
+        let summary = save_imported(&mut vault, Some(unlock_totps), entries, OnConflict::Skip).unwrap();
+        assert_eq!(
+            summary,
+            "Credentials: Imported 1 new entries, updated 0, skipped 0, duplicated 0\n\
+             Payment cards: imported 0, skipped 0 already in the vault\n\
+             Notes: imported 1, skipped 0 already in the vault\n\
+             Authorizers: imported 2, skipped 0 already in the TOTP vault"
+        );
+        assert_eq!(vault.grep(None)[0].totp_id(), Some(linked.id()));
+
+        let entries = importers::kdbx::read_kdbx(&source, Some("Partner"), true);
+        let summary = save_imported(&mut vault, Some(unlock_totps), entries, OnConflict::Skip).unwrap();
+        assert_eq!(
+            summary,
+            "Credentials: Imported 0 new entries, updated 0, skipped 1, duplicated 0\n\
+             Payment cards: imported 0, skipped 0 already in the vault\n\
+             Notes: imported 0, skipped 1 already in the vault\n\
+             Authorizers: imported 0, skipped 1 already in the TOTP vault"
+        );
+        assert_eq!(KeepassVault::open("master-pw", &totp_path, None).unwrap().find_totp(None).len(), 2);
+    }
+
+    #[test]
+    fn leaves_out_authorizers_without_a_totp_vault() {
+        use crate::importers::Imported;
+
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let totp = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        let entries = without_totps(ImportedEntries {
+            credentials: vec![Imported {
+                entry: credential("github.com", "alice", "pw").with_totp_id(Some(*totp.id())),
+                folder: None,
+            }],
+            totps: vec![totp],
+            ..ImportedEntries::default()
+        });
+        assert!(entries.totps.is_empty());
+        assert_eq!(entries.credentials[0].entry.totp_id(), None);
+        assert_eq!(
+            entries.skipped,
+            vec![SkippedField::new("GitHub:alice", "one time password (no TOTP vault configured)")]
+        );
+    }
+
+    #[test]
+    fn reimporting_an_export_matches_by_uuid() {
+        let existing = existing();
+        let exported = vec![Credential::new(Some(existing[0].uuid()), "old", "renamed.com", "bob", None, None)];
+        let plan = ImportPlan::new(&existing, exported, OnConflict::Skip);
+        assert!(plan.new.is_empty());
+        assert_eq!(plan.skipped, 1);
     }
 }
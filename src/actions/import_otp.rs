@@ -0,0 +1,146 @@
+use crate::actions::{unlock_totp_vault, UnlockingAction};
+use crate::otp_import::{self, OtpExportFormat};
+use crate::otp_migration;
+use crate::ui::input::{ask_confirm, ask_password};
+use crate::ui::output::show_totp_table;
+use crate::vault::entities::{Error, Totp};
+use crate::vault::vault_trait::Vault;
+use clap::ArgMatches;
+
+/// Imports the accounts of a Google Authenticator export into the TOTP vault.
+pub struct ImportMigrationAction {
+    pub source: String,
+    pub yes: bool,
+}
+
+impl ImportMigrationAction {
+    pub fn new(matches: &ArgMatches) -> ImportMigrationAction {
+        ImportMigrationAction {
+            source: matches
+                .get_one::<String>("SOURCE")
+                .expect("required")
+                .to_string(),
+            yes: matches.get_one::<bool>("yes").is_some_and(|v| *v),
+        }
+    }
+}
+
+/// Imports the authorizers of an Aegis, 2FAS or andOTP export.
+pub struct ImportOtpAction {
+    pub file_path: String,
+    pub format: Option<OtpExportFormat>,
+    pub yes: bool,
+}
+
+impl ImportOtpAction {
+    pub fn new(matches: &ArgMatches) -> ImportOtpAction {
+        ImportOtpAction {
+            file_path: matches
+                .get_one::<String>("FILE_PATH")
+                .expect("required")
+                .to_string(),
+            format: matches
+                .get_one::<String>("format")
+                .and_then(|format| format.parse().ok()),
+            yes: matches.get_one::<bool>("yes").is_some_and(|v| *v),
+        }
+    }
+}
+
+/// Saves the authorizers that are not in the vault yet, and returns how many
+/// were saved and how many were skipped as duplicates. An authorizer is a
+/// duplicate of one with the same issuer, label and secret.
+fn save_new_totps(vault: &mut Box<dyn Vault>, totps: Vec<Totp>) -> Result<(usize, usize), Error> {
+    let existing = vault.find_totp(None);
+    let mut new: Vec<Totp> = Vec::new();
+    let mut skipped = 0;
+    for totp in totps {
+        if existing.iter().chain(new.iter()).any(|other| other.is_same_authorizer(&totp)) {
+            skipped += 1;
+        } else {
+            new.push(totp);
+        }
+    }
+    if !new.is_empty() {
+        vault.save_totps(&new)?;
+    }
+    Ok((new.len(), skipped))
+}
+
+/// Previews `totps`, asks for confirmation unless `yes`, and then unlocks the
+/// TOTP vault and saves them.
+fn import_totps(totps: Vec<Totp>, yes: bool) -> Result<Option<String>, Error> {
+    if totps.is_empty() {
+        return Ok(Some("The export contains no accounts".to_string()));
+    }
+    show_totp_table(&totps, false);
+    if !yes && !ask_confirm(&format!("Import {} authorizers?", totps.len()), true) {
+        return Ok(Some("Nothing imported".to_string()));
+    }
+    let (saved, skipped) = save_new_totps(&mut unlock_totp_vault()?, totps)?;
+    Ok(Some(format!(
+        "Imported {} authorizers, skipped {} already in the vault",
+        saved, skipped
+    )))
+}
+
+impl UnlockingAction for ImportMigrationAction {
+    /// Decodes and previews the accounts before the vault is unlocked.
+    fn execute(&self) -> Result<Option<String>, Error> {
+        import_totps(otp_migration::read_migration(&self.source)?, self.yes)
+    }
+
+    fn is_totp_vault(&self) -> bool {
+        true
+    }
+}
+
+impl UnlockingAction for ImportOtpAction {
+    /// Reads and previews the export before the vault is unlocked.
+    fn execute(&self) -> Result<Option<String>, Error> {
+        let content = std::fs::read_to_string(&self.file_path)
+            .map_err(|e| Error::new(&format!("Cannot read {}: {}", self.file_path, e)))?;
+        let password = (self.format.is_none_or(|f| f == OtpExportFormat::Aegis)
+            && otp_import::needs_password(&content))
+        .then(|| ask_password("Password of the Aegis vault", None));
+        import_totps(
+            otp_import::parse_export(&content, self.format, password.as_deref())?,
+            self.yes,
+        )
+    }
+
+    fn is_totp_vault(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::keepass_vault::KeepassVault;
+
+    fn totp(label: &str, secret: &str) -> Totp {
+        let url = format!("otpauth://totp/{}?secret={}&issuer=x", label, secret);
+        Totp::new(None, &url, label, "x", secret, "SHA1", 30, 6, None)
+    }
+
+    #[test]
+    fn skips_authorizers_already_in_the_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("totp.kdbx");
+        let mut vault: Box<dyn Vault> =
+            Box::new(KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap());
+        vault.save_totp(&totp("old", "JBSWY3DPEHPK3PXP")).unwrap();
+
+        let imported = vec![
+            totp("old", "jbswy3dpehpk3pxp"),
+            totp("renamed", "JBSWY3DPEHPK3PXP"),
+            totp("new", "GEZDGNBVGY3TQOJQ"),
+            totp("new", "GEZDGNBVGY3TQOJQ"),
+        ];
+        assert_eq!(save_new_totps(&mut vault, imported).unwrap(), (2, 2));
+        let mut labels: Vec<String> = vault.find_totp(None).iter().map(|t| t.label().to_string()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["new", "old", "renamed"]);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::actions::{unlock_totp_vault, UnlockingAction};
+use crate::ui::input::ask_index;
+use crate::ui::output::{show_credentials_table, show_totp_table};
+use crate::vault::entities::{Error, Totp};
+use crate::vault::vault_trait::Vault;
+use clap::ArgMatches;
+use uuid::Uuid;
+
+/// Links a credential to an authorizer of the one time passwords vault, so that
+/// `show` copies the code after the password.
+pub struct LinkOtpAction {
+    pub credential: String,
+    pub authorizer: Option<String>,
+    pub remove: bool,
+}
+
+impl LinkOtpAction {
+    pub fn new(matches: &ArgMatches) -> LinkOtpAction {
+        LinkOtpAction {
+            credential: matches
+                .get_one::<String>("CREDENTIAL")
+                .expect("required")
+                .to_string(),
+            authorizer: matches.get_one::<String>("AUTHORIZER").cloned(),
+            remove: matches.get_one::<bool>("remove").is_some_and(|v| *v),
+        }
+    }
+}
+
+fn choose<T: Clone>(
+    mut matches: Vec<T>,
+    what: &str,
+    show_table: impl Fn(&[T]),
+) -> Result<Option<T>, Error> {
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop()),
+        _ => {
+            show_table(&matches);
+            let index = ask_index(
+                &format!("To link one of these {}, please enter a row number from the table above", what),
+                matches.len() as i16 - 1,
+                Some("Press q to exit without linking"),
+            )
+            .map_err(|message| Error { message })?;
+            Ok(matches.get(index).cloned())
+        }
+    }
+}
+
+/// The authorizers matching `search`: the one with that UUID, or else those
+/// whose label or issuer match it as a regular expression.
+fn find_authorizers(vault: &dyn Vault, search: &str) -> Vec<Totp> {
+    match Uuid::parse_str(search) {
+        Ok(id) => vault
+            .find_totp(None)
+            .into_iter()
+            .filter(|totp| *totp.id() == id)
+            .collect(),
+        Err(_) => vault.find_totp(Some(search)),
+    }
+}
+
+impl UnlockingAction for LinkOtpAction {
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let credential =
+            match choose(vault.grep(Some(&self.credential)), "credentials", |matches| {
+                show_credentials_table(matches, false, false)
+            })? {
+                Some(credential) => credential,
+                None => return Ok(Some("No matching credentials found".to_string())),
+            };
+        let service = credential.service().to_string();
+
+        if self.remove {
+            if credential.totp_id().is_none() {
+                return Ok(Some(format!("{} is not linked to an authorizer", service)));
+            }
+            vault.update_credential(credential.with_totp_id(None))?;
+            return Ok(Some(format!("Unlinked {} from its authorizer", service)));
+        }
+
+        let search = self
+            .authorizer
+            .as_deref()
+            .ok_or_else(|| Error::new("No authorizer given to link to"))?;
+        let totp_vault = unlock_totp_vault()?;
+        let matches = find_authorizers(totp_vault.as_ref(), search);
+        let totp = match choose(matches, "authorizers", |matches| show_totp_table(matches, false))? {
+            Some(totp) => totp,
+            None => return Ok(Some("No matching authorizers found".to_string())),
+        };
+        vault.update_credential(credential.with_totp_id(Some(*totp.id())))?;
+        Ok(Some(format!("Linked {} to authorizer {}", service, totp.label())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::keepass_vault::KeepassVault;
+    use crate::vault::vault_trait::TotpVault;
+
+    fn totp(label: &str) -> Totp {
+        let url = format!("otpauth://totp/{}?secret=JBSWY3DPEHPK3PXP&issuer={}", label, label);
+        Totp::new(None, &url, label, label, "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None)
+    }
+
+    #[test]
+    fn finds_authorizers_by_uuid_or_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("totp.kdbx");
+        let mut vault = KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap();
+        vault.save_totps(&[totp("GitHub"), totp("GitLab")]).unwrap();
+        let github = vault.find_totp(Some("GitHub")).remove(0);
+
+        let by_id = find_authorizers(&vault, &github.id().to_string());
+        assert_eq!(by_id.iter().map(|t| t.label()).collect::<Vec<_>>(), vec!["GitHub"]);
+        assert_eq!(find_authorizers(&vault, "Git").len(), 2);
+        assert!(find_authorizers(&vault, &Uuid::new_v4().to_string()).is_empty());
+    }
+}
@@ -1,8 +1,13 @@
-use crate::actions::{ItemType, UnlockingAction};
+use crate::actions::{otp_clock, unlock_totp_vault, ItemType, UnlockingAction};
+use crate::crypto::otp::Clock;
+use crate::crypto::strength::{estimate_strength, Strength};
+use crate::store;
 use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
 use crate::vault::vault_trait::Vault;
 use clap::ArgMatches;
 use serde::Serialize;
+use std::io::{ErrorKind, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Serialize)]
 pub struct ListOutput<T: Serialize> {
@@ -37,6 +42,77 @@ pub struct TotpCodeEntry {
     pub valid_for_seconds: u64,
 }
 
+impl TotpCodeEntry {
+    /// The current code of `totp`, leaving an HOTP counter as it is.
+    fn current(totp: &Totp, clock: &Clock) -> Result<Self, Error> {
+        let code = totp.code_with(clock)?;
+        Ok(TotpCodeEntry {
+            label: totp.label().to_string(),
+            issuer: totp.issuer().to_string(),
+            code: code.value,
+            valid_for_seconds: code.valid_for_seconds,
+        })
+    }
+}
+
+/// The current codes of the time-based authorizers in `totps`. Listing must not
+/// use up HOTP codes, so those are skipped with a note to use `show -o`.
+fn time_based_codes(totps: Vec<Totp>, clock: &Clock) -> Result<Vec<TotpCodeEntry>, Error> {
+    let (hotps, totps): (Vec<Totp>, Vec<Totp>) = totps.into_iter().partition(Totp::is_hotp);
+    if !hotps.is_empty() {
+        eprintln!("Skipping {} HOTP authorizers, use show -o to get their codes", hotps.len());
+    }
+    totps.iter().map(|totp| TotpCodeEntry::current(totp, clock)).collect()
+}
+
+/// A line of `list -o --code --watch`: the current code and the one after it.
+#[derive(Serialize)]
+pub struct TotpStreamEntry {
+    #[serde(flatten)]
+    pub code: TotpCodeEntry,
+    pub next_code: String,
+}
+
+/// The NDJSON lines of the authorizers whose code changed since `last`, the
+/// time step each was last reported at. Updates `last`.
+fn rolled_over_lines(totps: &[Totp], last: &mut [Option<u64>], now: u64) -> Result<Vec<String>, Error> {
+    let mut lines = Vec::new();
+    for (totp, last) in totps.iter().zip(last.iter_mut()) {
+        let step = now / totp.period().max(1);
+        if *last == Some(step) {
+            continue;
+        }
+        *last = Some(step);
+        let code = totp.code_at(now)?;
+        let entry = TotpStreamEntry {
+            code: TotpCodeEntry {
+                label: totp.label().to_string(),
+                issuer: totp.issuer().to_string(),
+                code: code.value,
+                valid_for_seconds: code.valid_for_seconds,
+            },
+            next_code: totp.code_at(now + totp.period())?.value,
+        };
+        lines.push(
+            serde_json::to_string(&entry)
+                .map_err(|e| Error::new(&format!("JSON serialization error: {}", e)))?,
+        );
+    }
+    Ok(lines)
+}
+
+/// A credential with the optional extras of `list --json`: the estimated
+/// strength of its password and the current code of its linked authorizer.
+#[derive(Serialize)]
+pub struct CredentialEntry {
+    #[serde(flatten)]
+    pub credential: Credential,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strength: Option<Strength>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otp: Option<TotpCodeEntry>,
+}
+
 pub struct ListAction {
     pub item_type: ItemType,
     pub search_pattern: Option<String>,
@@ -44,6 +120,11 @@ pub struct ListAction {
     pub verbose: bool,
     pub is_totp: bool,
     pub code: bool,
+    pub strength: bool,
+    pub linked_code: bool,
+    pub watch: bool,
+    pub at: Option<u64>,
+    pub offset: Option<i64>,
 }
 
 impl ListAction {
@@ -55,12 +136,20 @@ impl ListAction {
             verbose: matches.get_one::<bool>("verbose").map_or(false, |v| *v),
             is_totp: matches.get_one::<bool>("otp").map_or(false, |v| *v),
             code: matches.get_one::<bool>("code").map_or(false, |v| *v),
+            strength: matches.get_one::<bool>("strength").is_some_and(|v| *v),
+            linked_code: matches.get_one::<bool>("linked-code").is_some_and(|v| *v),
+            watch: matches.get_one::<bool>("watch").is_some_and(|v| *v),
+            at: matches.get_one::<u64>("at").copied(),
+            offset: matches.get_one::<i64>("offset").copied(),
         }
     }
 
     fn list_credentials(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
         let entries = vault.grep(self.search_pattern.as_deref());
-        if self.json_output {
+        if self.json_output && (self.strength || self.linked_code) {
+            let output = ListOutput::new("credentials", self.credential_entries(entries)?);
+            Ok(Some(output.to_json()?))
+        } else if self.json_output {
             let output = ListOutput::new("credentials", entries);
             Ok(Some(output.to_json()?))
         } else {
@@ -68,6 +157,51 @@ impl ListAction {
         }
     }
 
+    fn credential_entries(&self, credentials: Vec<Credential>) -> Result<Vec<CredentialEntry>, Error> {
+        // The TOTP vault is only unlocked when some credential is linked.
+        let totp_vault = if self.linked_code
+            && store::has_totp_vault_path()
+            && credentials.iter().any(|c| c.totp_id().is_some())
+        {
+            Some(unlock_totp_vault()?)
+        } else {
+            None
+        };
+        // A listing does not use up HOTP codes, so those authorizers are left out.
+        let (hotps, totps): (Vec<Totp>, Vec<Totp>) = totp_vault
+            .map(|v| v.find_totp(None))
+            .unwrap_or_default()
+            .into_iter()
+            .partition(Totp::is_hotp);
+        let clock = otp_clock(None, None)?;
+        let mut skipped_hotps = 0;
+        let mut entries = Vec::with_capacity(credentials.len());
+        for credential in credentials {
+            let strength = self.strength.then(|| {
+                estimate_strength(
+                    credential.password(),
+                    &[credential.service(), credential.username()],
+                )
+            });
+            let linked = credential
+                .totp_id()
+                .and_then(|id| totps.iter().find(|totp| totp.id() == id));
+            if credential.totp_id().is_some_and(|id| hotps.iter().any(|hotp| hotp.id() == id)) {
+                skipped_hotps += 1;
+            }
+            let otp = linked.map(|totp| TotpCodeEntry::current(totp, &clock)).transpose()?;
+            entries.push(CredentialEntry {
+                credential,
+                strength,
+                otp,
+            });
+        }
+        if skipped_hotps > 0 {
+            eprintln!("Skipping the codes of {} HOTP authorizers", skipped_hotps);
+        }
+        Ok(entries)
+    }
+
     fn list_payments(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
         let entries = vault.find_payments();
         if self.json_output {
@@ -90,8 +224,11 @@ impl ListAction {
 
     fn list_totp(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
         let entries = vault.find_totp(self.search_pattern.as_deref());
+        if self.watch {
+            return Self::stream_totp_codes(entries, otp_clock(None, self.offset)?);
+        }
         if self.code {
-            return self.list_totp_codes(&entries);
+            return self.list_totp_codes(entries);
         }
         if self.json_output {
             let output = ListOutput::new("totp", entries);
@@ -101,17 +238,8 @@ impl ListAction {
         }
     }
 
-    fn list_totp_codes(&self, entries: &[Totp]) -> Result<Option<String>, Error> {
-        let mut codes = Vec::with_capacity(entries.len());
-        for entry in entries {
-            let code = entry.get_code()?;
-            codes.push(TotpCodeEntry {
-                label: entry.label().to_string(),
-                issuer: entry.issuer().to_string(),
-                code: code.value,
-                valid_for_seconds: code.valid_for_seconds,
-            });
-        }
+    fn list_totp_codes(&self, entries: Vec<Totp>) -> Result<Option<String>, Error> {
+        let codes = time_based_codes(entries, &otp_clock(self.at, self.offset)?)?;
         if self.json_output {
             let output = ListOutput::new("totp_codes", codes);
             Ok(Some(output.to_json()?))
@@ -120,6 +248,35 @@ impl ListAction {
         }
     }
 
+    /// Writes an NDJSON line per authorizer whenever its code rolls over, until
+    /// stdout is closed. The vault stays unlocked for the whole session.
+    fn stream_totp_codes(entries: Vec<Totp>, clock: Clock) -> Result<Option<String>, Error> {
+        // An HOTP code never rolls over, and showing it would use it up.
+        let (hotps, totps): (Vec<Totp>, Vec<Totp>) = entries.into_iter().partition(Totp::is_hotp);
+        if !hotps.is_empty() {
+            eprintln!("Skipping {} HOTP authorizers", hotps.len());
+        }
+        if totps.is_empty() {
+            return Err(Error::new("No matching time-based authorizers found"));
+        }
+        let mut last = vec![None; totps.len()];
+        let mut stdout = std::io::stdout().lock();
+        loop {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            let lines = rolled_over_lines(&totps, &mut last, clock.now()?)?;
+            let written = lines
+                .iter()
+                .try_for_each(|line| writeln!(stdout, "{}", line))
+                .and_then(|_| stdout.flush());
+            match written {
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(None),
+                written => written?,
+            }
+            // Sleep until the next full second, when a code may roll over.
+            std::thread::sleep(Duration::from_secs(now.as_secs() + 1) - now);
+        }
+    }
+
     fn format_totp_codes_plain(entries: &[TotpCodeEntry]) -> String {
         let count = entries.len();
         if count == 0 {
@@ -206,6 +363,9 @@ impl ListAction {
             lines.push(String::new());
             lines.push(format!("Label: {}", entry.label()));
             lines.push(format!("Issuer: {}", entry.issuer()));
+            if let Some(counter) = entry.counter() {
+                lines.push(format!("Counter: {}", counter));
+            }
             if verbose {
                 lines.push(format!("Secret: {}", entry.secret()));
             }
@@ -355,6 +515,36 @@ mod tests {
         assert_eq!(parsed["entries"].as_array().unwrap().len(), 0);
     }
 
+    #[test]
+    fn test_credential_entry_includes_only_requested_extras() {
+        let cred = Credential::new(Some(&Uuid::nil()), "pass", "github.com", "user", None, None)
+            .with_totp_id(Some(Uuid::nil()));
+        let entry = CredentialEntry {
+            credential: cred.clone(),
+            strength: None,
+            otp: Some(TotpCodeEntry {
+                label: "GitHub:user".to_string(),
+                issuer: "GitHub".to_string(),
+                code: "123456".to_string(),
+                valid_for_seconds: 20,
+            }),
+        };
+        let parsed: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(parsed["service"], "github.com");
+        assert_eq!(parsed["totp_id"], Uuid::nil().to_string());
+        assert_eq!(parsed["otp"]["code"], "123456");
+        assert!(parsed.get("strength").is_none());
+
+        let plain = CredentialEntry {
+            credential: cred.with_totp_id(None),
+            strength: None,
+            otp: None,
+        };
+        let parsed: serde_json::Value = serde_json::to_value(&plain).unwrap();
+        assert!(parsed.get("otp").is_none());
+        assert!(parsed.get("totp_id").is_none());
+    }
+
     #[test]
     fn test_format_credentials_plain_empty() {
         let result = ListAction::format_credentials_plain(&[], false);
@@ -473,6 +663,45 @@ mod tests {
         assert_eq!(result, "Found 0 TOTP entries.");
     }
 
+    #[test]
+    fn test_stream_lines_only_for_rolled_over_codes() {
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let totp = |label: &str, period: u64| {
+            let url = format!("otpauth://totp/{}?secret={}&digits=8&period={}", label, secret, period);
+            Totp::new(None, &url, label, "Test", secret, "SHA1", period, 8, None)
+        };
+        let totps = vec![totp("fast", 30), totp("slow", 60)];
+        let mut last = vec![None; 2];
+
+        let lines = rolled_over_lines(&totps, &mut last, 59).unwrap();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["label"], "fast");
+        assert_eq!(first["issuer"], "Test");
+        // RFC 6238 SHA1 vectors for T = 59 and T = 89.
+        assert_eq!(first["code"], "94287082");
+        assert_eq!(first["next_code"], "37359152");
+        assert_eq!(first["valid_for_seconds"], 1);
+        assert!(first.get("secret").is_none());
+
+        assert!(rolled_over_lines(&totps, &mut last, 59).unwrap().is_empty());
+        let lines = rolled_over_lines(&totps, &mut last, 60).unwrap();
+        assert_eq!(lines.len(), 2);
+        let lines = rolled_over_lines(&totps, &mut last, 90).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("\"fast\""));
+        assert!(!lines[0].contains('\n'));
+    }
+
+    #[test]
+    fn test_listed_codes_skip_hotp_authorizers() {
+        let totp = Totp::new(None, "otpauth://totp/a?secret=GEZDGNBV&issuer=a", "a", "a", "GEZDGNBV", "SHA1", 30, 6, None);
+        let hotp = Totp::new(None, "otpauth://hotp/b?secret=GEZDGNBV&issuer=b&counter=3", "b", "b", "GEZDGNBV", "SHA1", 30, 6, None);
+        let codes = time_based_codes(vec![hotp, totp], &otp_clock(Some(1_700_000_000), None).unwrap()).unwrap();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].label, "a");
+    }
+
     #[test]
     fn test_totp_codes_json_envelope() {
         let entries = vec![
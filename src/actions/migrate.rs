@@ -0,0 +1,36 @@
+use crate::actions::UnlockingAction;
+use crate::ui::output::show_altered_text_table;
+use crate::vault::entities::Error;
+use crate::vault::vault_trait::Vault;
+use clap::ArgMatches;
+
+pub struct MigrateAction {
+    pub is_totp: bool,
+}
+
+impl MigrateAction {
+    pub fn new(matches: &ArgMatches) -> MigrateAction {
+        MigrateAction {
+            is_totp: matches.get_one::<bool>("otp").is_some_and(|v| *v),
+        }
+    }
+}
+
+impl UnlockingAction for MigrateAction {
+    fn is_totp_vault(&self) -> bool {
+        self.is_totp
+    }
+
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        let altered = vault.find_altered_text();
+        if altered.is_empty() {
+            return Ok(Some("No entries with altered text found.".to_string()));
+        }
+        show_altered_text_table(&altered);
+        Ok(Some(format!(
+            "Found {} fields whose text differs from what was entered or shown. \
+             Entries added with earlier versions were filtered before saving, so their original input cannot be recovered.",
+            altered.len()
+        )))
+    }
+}
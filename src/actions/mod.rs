@@ -1,5 +1,8 @@
 pub mod add;
+pub mod audit;
 pub mod change_password;
+pub mod clock_offset;
+pub mod codes;
 pub mod completions;
 pub mod delete;
 pub mod edit;
@@ -7,17 +10,25 @@ pub mod export;
 pub mod generate;
 pub mod help;
 pub mod import;
+pub mod import_otp;
 pub mod init;
+pub mod link;
 pub mod list;
 pub mod lock;
+pub mod migrate;
 pub mod show;
 pub mod unlock;
+pub mod watch;
 
+use crate::crypto::otp::Clock;
+use crate::crypto::{self, PasswordPolicy, PolicyOverrides};
 use crate::keychain;
+use crate::password_rules::PasswordRules;
+use crate::qr::QrCode;
 use crate::store;
 
 use crate::ui::input::{ask_master_password, ask_totp_master_password};
-use crate::vault::entities::Error;
+use crate::vault::entities::{Error, Totp};
 use crate::vault::keepass_vault::KeepassVault;
 use crate::vault::vault_trait::Vault;
 use clap::ArgMatches;
@@ -59,6 +70,72 @@ where
     }
 }
 
+/// Reads the password generator options shared by `gen` and `add`.
+pub(crate) fn policy_overrides_from_args(matches: &ArgMatches) -> PolicyOverrides {
+    let flag = |name: &str| matches.get_one::<bool>(name).is_some_and(|v| *v);
+    // Of a pair such as --lower and --no-lower, the last one given wins.
+    let toggle = |on: bool, off: &str| {
+        if on {
+            Some(true)
+        } else {
+            flag(off).then_some(false)
+        }
+    };
+    PolicyOverrides {
+        length: matches.get_one::<usize>("length").copied(),
+        lowercase: toggle(flag("lower"), "no-lower"),
+        uppercase: toggle(flag("upper"), "no-upper"),
+        digits: toggle(flag("digits"), "no-digits"),
+        symbols: toggle(matches.contains_id("symbols"), "no-symbols"),
+        symbol_set: matches.get_one::<String>("symbols").cloned(),
+        min_per_class: matches.get_one::<usize>("min").copied(),
+        exclude_ambiguous: toggle(flag("no-ambiguous"), "ambiguous"),
+        passphrase: flag("passphrase"),
+        words: matches.get_one::<usize>("words").copied(),
+        separator: matches.get_one::<String>("separator").cloned(),
+        capitalize: toggle(flag("capitalize"), "no-capitalize"),
+        with_digit: toggle(flag("with-digit"), "without-digit"),
+        with_symbol: toggle(flag("with-symbol"), "without-symbol"),
+    }
+}
+
+/// Parses password rules given with `--rules` or stored on a credential. An
+/// empty value means no rules.
+pub(crate) fn parse_password_rules(rules: Option<&str>) -> Result<Option<PasswordRules>, Error> {
+    rules
+        .filter(|rules| !rules.trim().is_empty())
+        .map(|rules| rules.parse())
+        .transpose()
+}
+
+/// The configured password policy with command line overrides applied.
+pub(crate) fn password_policy(overrides: &PolicyOverrides) -> Result<PasswordPolicy, Error> {
+    let policy = store::load_password_policy()?.with_overrides(overrides);
+    policy.validate()?;
+    Ok(policy)
+}
+
+/// Generates a password, following a site's `rules` when given, or a passphrase
+/// when `--passphrase` was given. The passphrase entropy goes to stderr so it
+/// stays out of piped output.
+pub(crate) fn generate_password(
+    overrides: &PolicyOverrides,
+    rules: Option<&PasswordRules>,
+) -> Result<String, Error> {
+    let policy = password_policy(overrides)?;
+    if let Some(rules) = rules {
+        crypto::generate_for_rules(rules, &policy)
+    } else if overrides.passphrase {
+        eprintln!(
+            "Passphrase entropy: {:.1} bits",
+            crypto::passphrase_entropy(&policy)
+        );
+        Ok(crypto::generate_passphrase(&policy))
+    } else {
+        Ok(crypto::generate(&policy))
+    }
+}
+
 pub trait Action {
     fn run(&self) -> Result<String, Error> {
         Ok("Success".to_string())
@@ -81,7 +158,7 @@ fn unlock() -> Result<Box<dyn Vault>, Error> {
     vault
 }
 
-fn unlock_totp_vault() -> Result<Box<dyn Vault>, Error> {
+pub(crate) fn unlock_totp_vault() -> Result<Box<dyn Vault>, Error> {
     let stored_password = keychain::get_totp_master_password();
     let mut master_pwd = stored_password.unwrap_or_else(|_| ask_totp_master_password());
     let filepath = store::get_totp_vault_path();
@@ -102,6 +179,45 @@ fn get_vault(
     Ok(Box::new(vault))
 }
 
+/// The clock for generating time-based codes: the fixed time `at`, or the
+/// system clock moved by `offset`, which defaults to the offset saved with
+/// `otp clock-offset`.
+pub(crate) fn otp_clock(at: Option<u64>, offset: Option<i64>) -> Result<Clock, Error> {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => store::load_clock_offset()?,
+    };
+    Ok(Clock { at, offset })
+}
+
+/// `totps` without the HOTP authorizers when `--at` or `--offset` is given.
+/// Their codes do not depend on the time, and generating one for a test
+/// would use it up.
+pub(crate) fn skip_hotps_for_clock_override(
+    totps: Vec<Totp>,
+    at: Option<u64>,
+    offset: Option<i64>,
+) -> Vec<Totp> {
+    if at.is_none() && offset.is_none() {
+        return totps;
+    }
+    let (hotps, totps): (Vec<Totp>, Vec<Totp>) = totps.into_iter().partition(Totp::is_hotp);
+    if !hotps.is_empty() {
+        eprintln!("Skipping {} HOTP authorizers, --at and --offset apply to time-based codes only", hotps.len());
+    }
+    totps
+}
+
+/// Saves an HOTP authorizer's next counter value once its current code has
+/// been generated, so that the same code is never shown twice. TOTP
+/// authorizers are left alone.
+pub(crate) fn advance_hotp_counter(vault: &mut Box<dyn Vault>, totp: &Totp) -> Result<(), Error> {
+    if totp.is_hotp() {
+        vault.save_hotp_counter(&totp.advanced())?;
+    }
+    Ok(())
+}
+
 pub fn copy_to_clipboard(value: &str) {
     if let Err(e) = Clipboard::new().and_then(|mut ctx| ctx.set_text(value)) {
         eprintln!("Failed to copy to clipboard: {}", e);
@@ -161,6 +277,52 @@ pub fn copy_to_clipboard_timed(value: &str, timeout_secs: u64) {
     }
 }
 
+/// Shows `value` as a QR code, then waits `timeout_secs` seconds (or until a
+/// key is pressed) and clears the screen, including the scrollback.
+/// This function **blocks** for the timeout duration.
+pub fn show_qr_timed(value: &str, timeout_secs: u64) -> Result<(), Error> {
+    use crossterm::cursor::MoveTo;
+    use crossterm::event::{self, Event, KeyEventKind};
+    use crossterm::terminal::{self, Clear, ClearType};
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    let qr = QrCode::encode(value.as_bytes())?;
+    print!("{}", qr.to_terminal_string());
+    println!(
+        "The screen will be cleared in {} seconds. Press any key to clear it now.",
+        timeout_secs
+    );
+    std::io::stdout().flush()?;
+
+    // Without a terminal to read keys from, just wait for the timeout.
+    if terminal::enable_raw_mode().is_ok() {
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !event::poll(remaining).unwrap_or(false) {
+                break;
+            }
+            if let Ok(Event::Key(key)) = event::read()
+                && key.kind == KeyEventKind::Press
+            {
+                break;
+            }
+        }
+        terminal::disable_raw_mode().ok();
+    } else {
+        std::thread::sleep(Duration::from_secs(timeout_secs));
+    }
+
+    crossterm::execute!(
+        std::io::stdout(),
+        Clear(ClearType::All),
+        Clear(ClearType::Purge),
+        MoveTo(0, 0)
+    )?;
+    Ok(())
+}
+
 pub trait UnlockingAction {
     fn execute(&self) -> Result<Option<String>, Error> {
         if self.is_totp_vault() {
@@ -182,6 +344,30 @@ pub trait UnlockingAction {
     }
 }
 
+/// What to do with a new credential for an account that is already in the vault.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnConflict {
+    Skip,
+    Update,
+    Duplicate,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "skip" => Ok(OnConflict::Skip),
+            "update" => Ok(OnConflict::Update),
+            "duplicate" => Ok(OnConflict::Duplicate),
+            _ => Err(Error::new(&format!(
+                "Unknown conflict mode '{}', expected skip, update or duplicate",
+                value
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ItemType {
     Credential,
@@ -1,9 +1,12 @@
 use crate::actions::{
-    copy_to_clipboard, copy_to_clipboard_timed, handle_matches, ItemType, MatchHandlerTemplate,
+    advance_hotp_counter, copy_to_clipboard, copy_to_clipboard_timed, handle_matches, otp_clock,
+    show_qr_timed, skip_hotps_for_clock_override, unlock_totp_vault, ItemType, MatchHandlerTemplate,
     UnlockingAction,
 };
+use crate::crypto::otp::Clock;
 
-use crate::ui::input::{ask_index, ask_with_options};
+use crate::store;
+use crate::ui::input::{ask_confirm, ask_index, ask_with_options};
 use crate::ui::output::{
     show_card, show_credentials_table, show_note, show_notes_table, show_payment_cards_table,
     show_totp_table,
@@ -11,6 +14,7 @@ use crate::ui::output::{
 use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
 use crate::vault::vault_trait::Vault;
 use clap::ArgMatches;
+use chrono::{DateTime, Utc};
 use log::debug;
 use std::io::{self, Read, Write};
 use std::sync::mpsc;
@@ -21,6 +25,74 @@ struct ShowCredentialsTemplate {
     verbose: bool,
     stdout_only: bool,
     plain: bool,
+    qr: bool,
+}
+
+impl ShowCredentialsTemplate {
+    fn reveal_password(&self, credential: &Credential) -> Result<Option<String>, Error> {
+        let password = credential.password();
+        if self.qr {
+            show_qr_timed(password, 20)?;
+        } else if self.stdout_only {
+            println!("{}", password);
+        } else if let Some((mut vault, totp)) = linked_totp(credential)? {
+            return copy_password_then_code(password, &mut vault, &totp);
+        } else {
+            println!("Password copied to clipboard! Clipboard will be cleared in 20 seconds.");
+            copy_to_clipboard_timed(password, 20);
+        }
+        Ok(None)
+    }
+}
+
+/// An authorizer together with the unlocked vault holding it.
+type LinkedTotp = (Box<dyn Vault>, Totp);
+
+/// The authorizer linked to `credential`, or `None` when there is no link or
+/// the authorizer is gone.
+fn linked_totp(credential: &Credential) -> Result<Option<LinkedTotp>, Error> {
+    let Some(totp_id) = credential.totp_id() else {
+        return Ok(None);
+    };
+    if !store::has_totp_vault_path() {
+        return Ok(None);
+    }
+    let vault = unlock_totp_vault()?;
+    let totp = vault.find_totp(None).into_iter().find(|totp| totp.id() == totp_id);
+    if totp.is_none() {
+        eprintln!("The linked authorizer is no longer in the one time passwords vault.");
+    }
+    Ok(totp.map(|totp| (vault, totp)))
+}
+
+/// Copies the password, and once the user has pasted it, the current code of
+/// the linked authorizer. The clipboard is cleared 20 seconds after the last copy.
+fn copy_password_then_code(
+    password: &str,
+    vault: &mut Box<dyn Vault>,
+    totp: &Totp,
+) -> Result<Option<String>, Error> {
+    copy_to_clipboard(password);
+    if !ask_confirm("Password copied to clipboard! Copy the one time code next?", true) {
+        println!("Clipboard will be cleared in 20 seconds.");
+        copy_to_clipboard_timed(password, 20);
+        return Ok(None);
+    }
+    let code = otp_clock(None, None)
+        .and_then(|clock| totp.code_with(&clock))
+        .and_then(|code| advance_hotp_counter(vault, totp).map(|_| code));
+    let code = match code {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Failed to generate the code: {}", e);
+            println!("Clipboard will be cleared in 20 seconds.");
+            copy_to_clipboard_timed(password, 20);
+            return Err(e);
+        }
+    };
+    println!("Code copied to clipboard! Clipboard will be cleared in 20 seconds.");
+    copy_to_clipboard_timed(&code.value, 20);
+    Ok(None)
 }
 
 impl MatchHandlerTemplate for ShowCredentialsTemplate {
@@ -32,14 +104,7 @@ impl MatchHandlerTemplate for ShowCredentialsTemplate {
 
     fn handle_one_match(&mut self, the_match: Self::ItemType) -> Result<Option<String>, Error> {
         show_credentials_table(&vec![the_match.clone()], self.verbose, self.plain);
-        if self.stdout_only {
-            println!("{}", the_match.password());
-            Ok(None)
-        } else {
-            println!("Password copied to clipboard! Clipboard will be cleared in 20 seconds.");
-            copy_to_clipboard_timed(the_match.password(), 20);
-            Ok(None)
-        }
+        self.reveal_password(&the_match)
     }
 
     fn handle_many_matches(
@@ -48,7 +113,9 @@ impl MatchHandlerTemplate for ShowCredentialsTemplate {
     ) -> Result<Option<String>, Error> {
         show_credentials_table(&matches, self.verbose, self.plain);
 
-        let prompt = if self.stdout_only {
+        let prompt = if self.qr {
+            "To show one of these passwords as a QR code, please enter a row number from the table above"
+        } else if self.stdout_only {
             "To print one of these passwords, please enter a row number from the table above"
         } else {
             "To copy one of these passwords to clipboard, please enter a row number from the table above"
@@ -59,16 +126,7 @@ impl MatchHandlerTemplate for ShowCredentialsTemplate {
             matches.len() as i16 - 1,
             Some("Press q to exit without copying the password"),
         ) {
-            Ok(index) => {
-                if self.stdout_only {
-                    println!("{}", matches[index].password());
-                    Ok(None)
-                } else {
-                    println!("Password copied to clipboard! Clipboard will be cleared in 20 seconds.");
-                    copy_to_clipboard_timed(matches[index].password(), 20);
-                    Ok(None)
-                }
-            }
+            Ok(index) => self.reveal_password(&matches[index]),
             Err(message) => {
                 Err(Error { message })
             }
@@ -168,11 +226,43 @@ impl MatchHandlerTemplate for ShowNotesTemplate {
     }
 }
 
-struct ShowTotpTemplate {
+struct ShowTotpTemplate<'a> {
+    vault: &'a mut Box<dyn Vault>,
     plain: bool,
+    qr: bool,
+    clock: Clock,
+    window: Option<u64>,
 }
 
-impl MatchHandlerTemplate for ShowTotpTemplate {
+/// The codes of the `window` periods before and after the current one, with
+/// the time each period starts.
+fn window_codes(totp: &Totp, clock: &Clock, window: u64) -> Result<String, Error> {
+    if totp.is_hotp() {
+        return Err(Error::new("--window needs a time-based authorizer"));
+    }
+    let period = totp.period().max(1);
+    let step = (clock.now()? / period) as i64;
+    let window = window as i64;
+    let mut lines = Vec::new();
+    for offset in -window..=window {
+        let Some(start) = (step + offset).checked_mul(period as i64).filter(|start| *start >= 0) else {
+            continue;
+        };
+        let code = totp.code_at(start as u64)?;
+        let time = DateTime::<Utc>::from_timestamp(start, 0)
+            .map_or(start.to_string(), |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+        lines.push(format!(
+            "{:>+4}  {}  {}{}",
+            offset,
+            time,
+            code.value,
+            if offset == 0 { "  <- current" } else { "" }
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+impl<'a> MatchHandlerTemplate for ShowTotpTemplate<'a> {
     type ItemType = Totp;
 
     fn pre_handle_matches(&self, matches: &Vec<Self::ItemType>) {
@@ -182,7 +272,7 @@ impl MatchHandlerTemplate for ShowTotpTemplate {
 
     fn handle_one_match(&mut self, the_match: Self::ItemType) -> Result<Option<String>, Error> {
         debug!("found totp: {}", the_match);
-        Self::show_code(the_match)
+        self.show_code(the_match)
     }
 
     fn handle_many_matches(
@@ -195,7 +285,7 @@ impl MatchHandlerTemplate for ShowTotpTemplate {
             Some("Press q to exit without showing the code"),
         ) {
             Ok(index) => {
-                Self::show_code(matches[index].clone())
+                self.show_code(matches[index].clone())
             }
             Err(message) => {
                 Err(Error { message })
@@ -204,8 +294,24 @@ impl MatchHandlerTemplate for ShowTotpTemplate {
     }
 }
 
-impl ShowTotpTemplate {
-    fn show_code(the_match: Totp) -> Result<Option<String>, Error> {
+impl<'a> ShowTotpTemplate<'a> {
+    fn show_code(&mut self, the_match: Totp) -> Result<Option<String>, Error> {
+        if self.qr {
+            // The otpauth URL enrolls the authorizer in a phone app.
+            show_qr_timed(the_match.url(), 20)?;
+            return Ok(None);
+        }
+        if let Some(window) = self.window {
+            return window_codes(&the_match, &self.clock, window).map(Some);
+        }
+        if the_match.is_hotp() {
+            return self.show_hotp_code(&the_match);
+        }
+        if let Some(at) = self.clock.at {
+            // A code for a fixed time does not change, so there is no countdown.
+            let code = the_match.code_at(at)?;
+            return Ok(Some(format!("Code {} (valid for {} more seconds at that time)", code.value, code.valid_for_seconds)));
+        }
         let (tx, rx) = mpsc::channel();
         let (tx_counter, rx_counter) = mpsc::channel();
 
@@ -242,7 +348,7 @@ impl ShowTotpTemplate {
         });
 
         loop {
-            let code = the_match.get_code();
+            let code = the_match.code_with(&self.clock);
 
             match code {
                 Ok(code) => {
@@ -271,6 +377,18 @@ impl ShowTotpTemplate {
         }
         Ok(None)
     }
+
+    /// An HOTP code is valid until used, so it is shown once without a countdown.
+    fn show_hotp_code(&mut self, the_match: &Totp) -> Result<Option<String>, Error> {
+        let code = the_match.get_code()?;
+        advance_hotp_counter(self.vault, the_match)?;
+        copy_to_clipboard(&code.value);
+        Ok(Some(format!(
+            "Code {} for counter {} (also copied to clipboard).",
+            code.value,
+            the_match.counter().unwrap_or(0)
+        )))
+    }
 }
 
 pub struct ShowAction {
@@ -281,6 +399,10 @@ pub struct ShowAction {
     pub stdout_only: bool,
     pub plain: bool,
     pub once: bool,
+    pub qr: bool,
+    pub at: Option<u64>,
+    pub offset: Option<i64>,
+    pub window: Option<u64>,
 }
 
 impl ShowAction {
@@ -293,16 +415,20 @@ impl ShowAction {
             stdout_only: matches.get_one::<bool>("out").map_or(false, |v| *v),
             plain: matches.get_one::<bool>("plain").map_or(false, |v| *v),
             once: matches.get_one::<bool>("once").map_or(false, |v| *v),
+            qr: matches.get_one::<bool>("qr").is_some_and(|v| *v),
+            at: matches.get_one::<u64>("at").copied(),
+            offset: matches.get_one::<i64>("offset").copied(),
+            window: matches.get_one::<u64>("window").copied(),
         }
     }
 
     /// One-shot TOTP code retrieval: print the single matching code to stdout
     /// and return. Errors (non-zero exit) on zero or multiple matches. No
     /// clipboard, no countdown, no keyboard wait.
-    fn show_totp_once(&self, matches: Vec<Totp>) -> Result<Option<String>, Error> {
+    fn show_totp_once(&self, matches: Vec<Totp>, clock: &Clock) -> Result<Option<String>, Error> {
         match matches.len() {
             1 => {
-                let code = matches[0].get_code()?;
+                let code = matches[0].code_with(clock)?;
                 Ok(Some(code.value))
             }
             0 => Err(Error {
@@ -347,6 +473,7 @@ impl UnlockingAction for ShowAction {
                         verbose: self.verbose,
                         stdout_only: self.stdout_only,
                         plain: self.plain,
+                        qr: self.qr,
                     }),
                 )
             }
@@ -365,11 +492,24 @@ impl UnlockingAction for ShowAction {
                 }),
             ),
             ItemType::Totp => {
-                let matches = vault.find_totp(self.grep.as_deref());
+                let matches =
+                    skip_hotps_for_clock_override(vault.find_totp(self.grep.as_deref()), self.at, self.offset);
+                let clock = otp_clock(self.at, self.offset)?;
                 if self.once {
-                    self.show_totp_once(matches)
+                    let code = self.show_totp_once(matches.clone(), &clock)?;
+                    advance_hotp_counter(vault, &matches[0])?;
+                    Ok(code)
                 } else {
-                    handle_matches(matches, &mut Box::new(ShowTotpTemplate { plain: self.plain }))
+                    handle_matches(
+                        matches,
+                        &mut Box::new(ShowTotpTemplate {
+                            vault,
+                            plain: self.plain,
+                            qr: self.qr,
+                            clock,
+                            window: self.window,
+                        }),
+                    )
                 }
             }
         }
@@ -404,12 +544,16 @@ mod tests {
             stdout_only: false,
             plain: false,
             once: true,
+            qr: false,
+            at: None,
+            offset: None,
+            window: None,
         }
     }
 
     #[test]
     fn test_show_totp_once_single_match_prints_code() {
-        let result = once_action().show_totp_once(vec![totp("a@test.com")]);
+        let result = once_action().show_totp_once(vec![totp("a@test.com")], &Clock::default());
         let code = result.expect("expected a code").expect("expected Some(code)");
         // A current TOTP code is a non-empty numeric string.
         assert!(!code.is_empty());
@@ -418,16 +562,56 @@ mod tests {
 
     #[test]
     fn test_show_totp_once_no_match_errors() {
-        let result = once_action().show_totp_once(vec![]);
+        let result = once_action().show_totp_once(vec![], &Clock::default());
         assert!(result.is_err());
     }
 
     #[test]
     fn test_show_totp_once_multiple_matches_errors_with_labels() {
         let result =
-            once_action().show_totp_once(vec![totp("a@test.com"), totp("b@test.com")]);
+            once_action().show_totp_once(vec![totp("a@test.com"), totp("b@test.com")], &Clock::default());
         let err = result.expect_err("expected an error on multiple matches");
         assert!(err.message.contains("a@test.com"));
         assert!(err.message.contains("b@test.com"));
     }
+
+    fn rfc_6238_totp() -> Totp {
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let url = format!("otpauth://totp/x?secret={}&issuer=x&digits=8", secret);
+        Totp::new(None, &url, "x", "x", secret, "SHA1", 30, 8, None)
+    }
+
+    #[test]
+    fn test_show_totp_once_at_a_fixed_time() {
+        let clock = Clock { at: Some(1111111109), offset: 0 };
+        let code = once_action().show_totp_once(vec![rfc_6238_totp()], &clock).unwrap();
+        assert_eq!(code.as_deref(), Some("07081804"));
+    }
+
+    #[test]
+    fn test_window_codes_around_the_current_period() {
+        let clock = Clock { at: Some(1111111109), offset: 0 };
+        let lines = window_codes(&rfc_6238_totp(), &clock, 1).unwrap();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("  -1  2005-03-18 01:57:30 UTC  "));
+        assert_eq!(lines[1], "  +0  2005-03-18 01:58:00 UTC  07081804  <- current");
+        // RFC 6238 uses T = 1111111111 for the next period.
+        assert_eq!(lines[2], "  +1  2005-03-18 01:58:30 UTC  14050471");
+
+        let start = Clock { at: Some(10), offset: 0 };
+        assert_eq!(window_codes(&rfc_6238_totp(), &start, 2).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn test_clock_override_skips_hotp_authorizers() {
+        let totp = Totp::new(None, "otpauth://totp/a?secret=GEZDGNBV&issuer=a", "a", "a", "GEZDGNBV", "SHA1", 30, 6, None);
+        let hotp = Totp::new(None, "otpauth://hotp/b?secret=GEZDGNBV&issuer=b&counter=3", "b", "b", "GEZDGNBV", "SHA1", 30, 6, None);
+        let both = vec![totp, hotp];
+        assert_eq!(skip_hotps_for_clock_override(both.clone(), None, None).len(), 2);
+        let at = skip_hotps_for_clock_override(both.clone(), Some(1_700_000_000), None);
+        assert_eq!(at.len(), 1);
+        assert!(!at[0].is_hotp());
+        assert_eq!(skip_hotps_for_clock_override(both, None, Some(-5)).len(), 1);
+    }
 }
@@ -0,0 +1,336 @@
+use crate::actions::{copy_to_clipboard_timed, otp_clock, UnlockingAction};
+use crate::crypto::otp::Clock;
+use crate::vault::entities::{Error, Totp};
+use crate::vault::vault_trait::Vault;
+use clap::ArgMatches;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::Write;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const PROGRESS_WIDTH: usize = 20;
+const CLIPBOARD_TIMEOUT_SECS: u64 = 20;
+
+/// Shows the codes of all matching authorizers in a full-screen view that
+/// refreshes every second.
+pub struct WatchOtpAction {
+    pub grep: Option<String>,
+}
+
+impl WatchOtpAction {
+    pub fn new(matches: &ArgMatches) -> WatchOtpAction {
+        WatchOtpAction {
+            grep: matches.get_one::<String>("REGEXP").cloned(),
+        }
+    }
+}
+
+/// What the dashboard should do after a key press.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Redraw,
+    Copy,
+    Quit,
+}
+
+/// The state of the dashboard: the authorizers, the filter typed after `/`
+/// and the selected row.
+struct Dashboard {
+    totps: Vec<Totp>,
+    filter: String,
+    editing: bool,
+    selected: usize,
+}
+
+impl Dashboard {
+    fn new(totps: Vec<Totp>) -> Self {
+        Dashboard {
+            totps,
+            filter: String::new(),
+            editing: false,
+            selected: 0,
+        }
+    }
+
+    /// The authorizers whose label or issuer contain the filter, ignoring case.
+    fn visible(&self) -> Vec<&Totp> {
+        let filter = self.filter.to_lowercase();
+        self.totps
+            .iter()
+            .filter(|totp| {
+                totp.label().to_lowercase().contains(&filter)
+                    || totp.issuer().to_lowercase().contains(&filter)
+            })
+            .collect()
+    }
+
+    fn selected_totp(&self) -> Option<&Totp> {
+        self.visible().get(self.selected).copied()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Command {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Command::Quit;
+        }
+        let command = match (self.editing, key.code) {
+            (true, KeyCode::Char(c)) => {
+                self.filter.push(c);
+                Command::Redraw
+            }
+            (true, KeyCode::Backspace) => {
+                self.filter.pop();
+                Command::Redraw
+            }
+            (true, KeyCode::Esc) => {
+                self.filter.clear();
+                self.editing = false;
+                Command::Redraw
+            }
+            (_, KeyCode::Enter) if self.editing => {
+                self.editing = false;
+                Command::Redraw
+            }
+            (false, KeyCode::Char('q')) => Command::Quit,
+            (false, KeyCode::Char('/')) => {
+                self.editing = true;
+                Command::Redraw
+            }
+            (false, KeyCode::Esc) => {
+                self.filter.clear();
+                Command::Redraw
+            }
+            (_, KeyCode::Up) | (false, KeyCode::Char('k')) => {
+                self.selected = self.selected.saturating_sub(1);
+                Command::Redraw
+            }
+            (_, KeyCode::Down) | (false, KeyCode::Char('j')) => {
+                self.selected += 1;
+                Command::Redraw
+            }
+            (false, KeyCode::Enter) | (false, KeyCode::Char('c')) => Command::Copy,
+            _ => Command::Redraw,
+        };
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+        command
+    }
+
+    /// The lines of the view at `now` seconds since the epoch.
+    fn lines(&self, now: u64) -> Result<Vec<String>, Error> {
+        let visible = self.visible();
+        let title = if self.editing {
+            format!("Filter: {}_", self.filter)
+        } else if self.filter.is_empty() {
+            "One time passwords".to_string()
+        } else {
+            format!("One time passwords matching '{}'", self.filter)
+        };
+        let mut lines = vec![title, String::new()];
+        let issuer_width = visible.iter().map(|t| t.issuer().chars().count()).max().unwrap_or(0);
+        let label_width = visible.iter().map(|t| t.label().chars().count()).max().unwrap_or(0);
+        for (index, totp) in visible.iter().enumerate() {
+            let current = totp.code_at(now)?;
+            let next = totp.code_at(now + totp.period())?;
+            lines.push(format!(
+                "{} {:issuer_width$}  {:label_width$}  {}  next {}  {} {:>2}s",
+                if index == self.selected { ">" } else { " " },
+                totp.issuer(),
+                totp.label(),
+                current.value,
+                next.value,
+                progress_bar(current.valid_for_seconds, totp.period()),
+                current.valid_for_seconds,
+            ));
+        }
+        if visible.is_empty() {
+            lines.push("  No matching authorizers".to_string());
+        }
+        lines.push(String::new());
+        lines.push(if self.editing {
+            "Type to filter, Enter to keep the filter, Esc to clear it".to_string()
+        } else {
+            "↑/↓ select, Enter copy, / filter, q quit".to_string()
+        });
+        Ok(lines)
+    }
+}
+
+/// A bar whose filled part shrinks as the code's period runs out.
+fn progress_bar(remaining: u64, period: u64) -> String {
+    let filled = (remaining * PROGRESS_WIDTH as u64).div_ceil(period.max(1)) as usize;
+    let filled = filled.min(PROGRESS_WIDTH);
+    format!("[{}{}]", "█".repeat(filled), "░".repeat(PROGRESS_WIDTH - filled))
+}
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it
+/// when dropped, also when the dashboard returns with an error.
+struct RawScreen;
+
+impl RawScreen {
+    fn enter() -> Result<Self, Error> {
+        terminal::enable_raw_mode()
+            .map_err(|_| Error::new("otp watch needs an interactive terminal"))?;
+        let screen = RawScreen;
+        execute!(std::io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(screen)
+    }
+}
+
+impl Drop for RawScreen {
+    fn drop(&mut self) {
+        execute!(std::io::stdout(), Show, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+fn draw(lines: &[String], status: &str) -> Result<(), Error> {
+    let (width, height) = terminal::size()?;
+    let mut stdout = std::io::stdout();
+    queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+    for (row, line) in lines.iter().chain([status.to_string()].iter()).enumerate() {
+        if row >= height as usize {
+            break;
+        }
+        let line: String = line.chars().take(width as usize).collect();
+        queue!(stdout, MoveTo(0, row as u16), Print(line))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn run_dashboard(dashboard: &mut Dashboard, clock: &Clock) -> Result<Vec<JoinHandle<()>>, Error> {
+    let _screen = RawScreen::enter()?;
+    let mut copies = Vec::new();
+    let mut status = String::new();
+    loop {
+        draw(&dashboard.lines(clock.now()?)?, &status)?;
+        // Redraw at least every second so the codes and bars stay current.
+        if !event::poll(Duration::from_millis(1000))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match dashboard.handle_key(key) {
+            Command::Quit => return Ok(copies),
+            Command::Copy => {
+                if let Some(totp) = dashboard.selected_totp() {
+                    let code = totp.code_at(clock.now()?)?.value;
+                    status = format!(
+                        "Copied the code of {}. Clipboard will be cleared in {} seconds.",
+                        totp.label(),
+                        CLIPBOARD_TIMEOUT_SECS
+                    );
+                    copies.push(thread::spawn(move || {
+                        copy_to_clipboard_timed(&code, CLIPBOARD_TIMEOUT_SECS)
+                    }));
+                }
+            }
+            Command::Redraw => {}
+        }
+    }
+}
+
+impl UnlockingAction for WatchOtpAction {
+    fn is_totp_vault(&self) -> bool {
+        true
+    }
+
+    fn run_with_vault(&self, vault: &mut Box<dyn Vault>) -> Result<Option<String>, Error> {
+        // HOTP codes are used up when shown, so they have no place in a live view.
+        let (hotps, totps): (Vec<Totp>, Vec<Totp>) =
+            vault.find_totp(self.grep.as_deref()).into_iter().partition(Totp::is_hotp);
+        if totps.is_empty() {
+            return Ok(Some("No matching time-based authorizers found".to_string()));
+        }
+        let copies = run_dashboard(&mut Dashboard::new(totps), &otp_clock(None, None)?)?;
+        if copies.iter().any(|copy| !copy.is_finished()) {
+            println!("Waiting to clear the clipboard. Press Ctrl+C to clear it now.");
+        }
+        for copy in copies {
+            copy.join().ok();
+        }
+        Ok((!hotps.is_empty()).then(|| {
+            format!("{} HOTP authorizers were not shown, use show -o for those", hotps.len())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totp(issuer: &str, label: &str) -> Totp {
+        let url = format!("otpauth://totp/{}?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", label);
+        Totp::new(None, &url, label, issuer, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", "SHA1", 30, 8, None)
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn dashboard() -> Dashboard {
+        Dashboard::new(vec![
+            totp("GitHub", "alice"),
+            totp("GitLab", "bob"),
+            totp("Heroku", "alice"),
+        ])
+    }
+
+    #[test]
+    fn filters_as_you_type() {
+        let mut dashboard = dashboard();
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('/'))), Command::Redraw);
+        for c in "GIT".chars() {
+            dashboard.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(dashboard.visible().len(), 2);
+        // While filtering, q is part of the filter and does not quit.
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('q'))), Command::Redraw);
+        assert!(dashboard.visible().is_empty());
+        dashboard.handle_key(key(KeyCode::Backspace));
+        dashboard.handle_key(key(KeyCode::Char('l')));
+        dashboard.handle_key(key(KeyCode::Enter));
+        assert_eq!(dashboard.selected_totp().unwrap().issuer(), "GitLab");
+        dashboard.handle_key(key(KeyCode::Esc));
+        assert_eq!(dashboard.visible().len(), 3);
+    }
+
+    #[test]
+    fn selects_copies_and_quits() {
+        let mut dashboard = dashboard();
+        dashboard.handle_key(key(KeyCode::Down));
+        dashboard.handle_key(key(KeyCode::Down));
+        dashboard.handle_key(key(KeyCode::Down));
+        assert_eq!(dashboard.selected_totp().unwrap().issuer(), "Heroku");
+        dashboard.handle_key(key(KeyCode::Char('k')));
+        assert_eq!(dashboard.selected_totp().unwrap().issuer(), "GitLab");
+        assert_eq!(dashboard.handle_key(key(KeyCode::Enter)), Command::Copy);
+        assert_eq!(dashboard.handle_key(key(KeyCode::Char('q'))), Command::Quit);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(dashboard.handle_key(ctrl_c), Command::Quit);
+    }
+
+    #[test]
+    fn shows_current_and_next_code() {
+        // RFC 6238 SHA1 test vectors for T = 59 and T = 89 (the next period).
+        let lines = dashboard().lines(59).unwrap();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[2].starts_with("> GitHub  alice  94287082  next 37359152  ["));
+        assert!(lines[2].ends_with(" 1s"));
+        assert!(lines[3].starts_with("  GitLab  bob  "));
+    }
+
+    #[test]
+    fn progress_bar_shrinks_with_the_period() {
+        assert_eq!(progress_bar(30, 30), format!("[{}]", "█".repeat(20)));
+        assert_eq!(progress_bar(15, 30), format!("[{}{}]", "█".repeat(10), "░".repeat(10)));
+        assert_eq!(progress_bar(1, 30), format!("[█{}]", "░".repeat(19)));
+    }
+}
@@ -0,0 +1,214 @@
+//! Offline lookups in a downloaded copy of the Have I Been Pwned "Pwned
+//! Passwords" SHA-1 dataset. Nothing is sent over the network.
+//!
+//! Two layouts are supported:
+//! - a single file of `HASH:COUNT` lines sorted by hash, as in
+//!   `pwned-passwords-sha1-ordered-by-hash-v8.txt`, searched with a binary
+//!   search over byte offsets so that the whole file is never read, and
+//! - a directory of range files named by the first five hex digits of the hash
+//!   (`00000.txt` .. `FFFFF.txt`) holding `SUFFIX:COUNT` lines, as written by
+//!   the PwnedPasswordsDownloader.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::vault::entities::Error;
+
+const HASH_LENGTH: usize = 40;
+const PREFIX_LENGTH: usize = 5;
+
+pub enum BreachDatabase {
+    SortedFile(PathBuf),
+    RangeDirectory(PathBuf),
+}
+
+impl BreachDatabase {
+    /// Opens the dataset at `path`, a sorted hash file or a range directory.
+    pub fn open(path: &str) -> Result<BreachDatabase, Error> {
+        let path = PathBuf::from(path);
+        let metadata = std::fs::metadata(&path).map_err(|e| {
+            Error::new(&format!("Cannot open breach dataset {}: {}", path.display(), e))
+        })?;
+        if metadata.is_dir() {
+            if range_file(&path, "00000").is_none() {
+                return Err(Error::new(&format!(
+                    "{} is not a Pwned Passwords range directory: 00000.txt not found",
+                    path.display()
+                )));
+            }
+            Ok(BreachDatabase::RangeDirectory(path))
+        } else {
+            let mut reader = BufReader::new(File::open(&path)?);
+            let first = next_line(&mut reader)?.unwrap_or_default();
+            if parse_line(&first, HASH_LENGTH).is_none() {
+                return Err(Error::new(&format!(
+                    "{} is not a Pwned Passwords SHA-1 file: expected lines of HASH:COUNT",
+                    path.display()
+                )));
+            }
+            Ok(BreachDatabase::SortedFile(path))
+        }
+    }
+
+    /// How many times `password` appears in the dataset, or `None` if it does not.
+    pub fn lookup(&self, password: &str) -> Result<Option<u64>, Error> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        match self {
+            BreachDatabase::SortedFile(path) => search_sorted_file(path, &hash),
+            BreachDatabase::RangeDirectory(dir) => search_range_directory(dir, &hash),
+        }
+    }
+}
+
+/// Parses a `HASH:COUNT` line whose hash has `hash_length` hex digits. A line
+/// without a count counts as one occurrence.
+fn parse_line(line: &str, hash_length: usize) -> Option<(String, u64)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (hash, count) = match line.split_once(':') {
+        Some((hash, count)) => (hash, count.trim().parse().ok()?),
+        None => (line, 1),
+    };
+    (hash.len() == hash_length && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| (hash.to_uppercase(), count))
+}
+
+fn next_line(reader: &mut impl BufRead) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    let read = reader.read_line(&mut line)?;
+    Ok((read > 0 && !line.trim().is_empty()).then_some(line))
+}
+
+/// Binary search over byte offsets: each probe reads the first whole line that
+/// starts at or after the middle offset.
+fn search_sorted_file(path: &Path, hash: &str) -> Result<Option<u64>, Error> {
+    let file = File::open(path)?;
+    let mut low = 0;
+    let mut high = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let mut start = middle;
+        if middle > 0 {
+            reader.seek(SeekFrom::Start(middle - 1))?;
+            let mut skipped = Vec::new();
+            start = middle - 1 + reader.read_until(b'\n', &mut skipped)? as u64;
+        } else {
+            reader.seek(SeekFrom::Start(0))?;
+        }
+        let Some(line) = next_line(&mut reader)? else {
+            high = middle;
+            continue;
+        };
+        let (line_hash, count) = parse_line(&line, HASH_LENGTH).ok_or_else(|| {
+            Error::new(&format!(
+                "Invalid line at byte {} of {}",
+                start,
+                path.display()
+            ))
+        })?;
+        match line_hash.as_str().cmp(hash) {
+            Ordering::Equal => return Ok(Some(count)),
+            Ordering::Less => low = start + line.len() as u64,
+            Ordering::Greater => high = middle,
+        }
+    }
+    Ok(None)
+}
+
+fn range_file(dir: &Path, prefix: &str) -> Option<PathBuf> {
+    [prefix.to_uppercase(), prefix.to_lowercase()]
+        .iter()
+        .map(|name| dir.join(format!("{}.txt", name)))
+        .find(|path| path.is_file())
+}
+
+fn search_range_directory(dir: &Path, hash: &str) -> Result<Option<u64>, Error> {
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+    let path = range_file(dir, prefix).ok_or_else(|| {
+        Error::new(&format!(
+            "The breach dataset in {} has no range file {}.txt",
+            dir.display(),
+            prefix
+        ))
+    })?;
+    for line in BufReader::new(File::open(&path)?).lines() {
+        if let Some((line_suffix, count)) = parse_line(&line?, HASH_LENGTH - PREFIX_LENGTH)
+            && line_suffix == suffix
+        {
+            return Ok(Some(count));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha1(password: &str) -> String {
+        hex::encode_upper(Sha1::digest(password.as_bytes()))
+    }
+
+    fn write_sorted_file(dir: &Path, passwords: &[(&str, u64)], newline: &str) -> String {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .map(|(password, count)| format!("{}:{}", sha1(password), count))
+            .collect();
+        lines.sort();
+        let path = dir.join("pwned.txt");
+        std::fs::write(&path, lines.join(newline) + newline).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn finds_every_line_of_a_sorted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let passwords: Vec<(String, u64)> = (0..200).map(|i| (format!("password{}", i), i + 1)).collect();
+        let refs: Vec<(&str, u64)> = passwords.iter().map(|(p, c)| (p.as_str(), *c)).collect();
+        for newline in ["\n", "\r\n"] {
+            let db = BreachDatabase::open(&write_sorted_file(dir.path(), &refs, newline)).unwrap();
+            for (password, count) in &refs {
+                assert_eq!(db.lookup(password).unwrap(), Some(*count), "{}", password);
+            }
+            assert_eq!(db.lookup("not in the file").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn single_line_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = BreachDatabase::open(&write_sorted_file(dir.path(), &[("hunter2", 17)], "\n")).unwrap();
+        assert_eq!(db.lookup("hunter2").unwrap(), Some(17));
+        assert_eq!(db.lookup("hunter3").unwrap(), None);
+    }
+
+    #[test]
+    fn looks_up_range_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let hash = sha1("password");
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+        std::fs::write(dir.path().join("00000.txt"), "0005AD76BD555C1D6D771DE417A4B87E4B4:10\r\n").unwrap();
+        std::fs::write(
+            dir.path().join(format!("{}.txt", prefix)),
+            format!("003D68EB55068C33ACE09247EE4C639306B:3\r\n{}:9545824\r\n", suffix),
+        )
+        .unwrap();
+        let db = BreachDatabase::open(&dir.path().display().to_string()).unwrap();
+        assert_eq!(db.lookup("password").unwrap(), Some(9545824));
+        assert!(db.lookup("something else").is_err());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "just some text\n").unwrap();
+        assert!(BreachDatabase::open(&path.display().to_string()).is_err());
+        assert!(BreachDatabase::open(&dir.path().display().to_string()).is_err());
+        assert!(BreachDatabase::open("/nonexistent/pwned.txt").is_err());
+    }
+}
@@ -0,0 +1,603 @@
+pub mod breach;
+pub mod otp;
+pub mod strength;
+
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::password_rules::{longest_run, CharClass, PasswordRules};
+use crate::vault::entities::Error;
+
+const LOW_CASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UP_CASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const NUMBERS: &str = "0123456789";
+pub const SPECIAL: &str = "£$&()*+[]@#^-_!?:;,.{}<>~%/\\|\"'`´^¨=§";
+
+const AMBIGUOUS: &str = "0O1lI";
+
+const DEFAULT_LENGTH: usize = 15;
+const MAX_LENGTH: usize = 1024;
+
+/// The EFF long word list: 7776 words, one per line after its five dice rolls.
+const EFF_WORDLIST: &str = include_str!("eff_large_wordlist.txt");
+
+const DEFAULT_WORDS: usize = 6;
+const MAX_WORDS: usize = 64;
+
+/// Rules for generating passwords and for deciding whether a password is
+/// acceptable. Defaults are read from `~/.passlane/.password_policy`, and any
+/// field missing from that file keeps its built-in value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    /// Characters used for the symbol class.
+    pub symbol_set: String,
+    /// Minimum number of characters from each enabled class.
+    pub min_per_class: usize,
+    /// Leave out characters that are easily confused: `0O1lI`.
+    pub exclude_ambiguous: bool,
+    pub passphrase: PassphrasePolicy,
+}
+
+/// Rules for diceware passphrases built from the EFF long word list.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    /// Start every word with an uppercase letter.
+    pub capitalize: bool,
+    /// Append a random digit to one randomly chosen word.
+    pub digit: bool,
+    /// Append a character from the symbol set to one randomly chosen word.
+    pub symbol: bool,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        PassphrasePolicy {
+            words: DEFAULT_WORDS,
+            separator: "-".to_string(),
+            capitalize: false,
+            digit: false,
+            symbol: false,
+        }
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            length: DEFAULT_LENGTH,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            symbol_set: SPECIAL.to_string(),
+            min_per_class: 1,
+            exclude_ambiguous: false,
+            passphrase: PassphrasePolicy::default(),
+        }
+    }
+}
+
+/// Policy settings given on the command line, overriding the configured
+/// defaults. `None` keeps the configured value, so a flag can turn an option
+/// on or off for a single run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PolicyOverrides {
+    pub length: Option<usize>,
+    pub lowercase: Option<bool>,
+    pub uppercase: Option<bool>,
+    pub digits: Option<bool>,
+    pub symbols: Option<bool>,
+    pub symbol_set: Option<String>,
+    pub min_per_class: Option<usize>,
+    pub exclude_ambiguous: Option<bool>,
+    /// Generate a passphrase instead of a password.
+    pub passphrase: bool,
+    pub words: Option<usize>,
+    pub separator: Option<String>,
+    pub capitalize: Option<bool>,
+    pub with_digit: Option<bool>,
+    pub with_symbol: Option<bool>,
+}
+
+impl PasswordPolicy {
+    pub fn with_overrides(&self, overrides: &PolicyOverrides) -> PasswordPolicy {
+        PasswordPolicy {
+            length: overrides.length.unwrap_or(self.length),
+            lowercase: overrides.lowercase.unwrap_or(self.lowercase),
+            uppercase: overrides.uppercase.unwrap_or(self.uppercase),
+            digits: overrides.digits.unwrap_or(self.digits),
+            symbols: overrides.symbols.unwrap_or(self.symbols),
+            symbol_set: overrides
+                .symbol_set
+                .clone()
+                .unwrap_or_else(|| self.symbol_set.clone()),
+            min_per_class: overrides.min_per_class.unwrap_or(self.min_per_class),
+            exclude_ambiguous: overrides.exclude_ambiguous.unwrap_or(self.exclude_ambiguous),
+            passphrase: PassphrasePolicy {
+                words: overrides.words.unwrap_or(self.passphrase.words),
+                separator: overrides
+                    .separator
+                    .clone()
+                    .unwrap_or_else(|| self.passphrase.separator.clone()),
+                capitalize: overrides.capitalize.unwrap_or(self.passphrase.capitalize),
+                digit: overrides.with_digit.unwrap_or(self.passphrase.digit),
+                symbol: overrides.with_symbol.unwrap_or(self.passphrase.symbol),
+            },
+        }
+    }
+
+    /// The character sets of the enabled classes, with ambiguous characters
+    /// removed if the policy asks for it.
+    fn classes(&self) -> Vec<String> {
+        [
+            (self.lowercase, LOW_CASE),
+            (self.uppercase, UP_CASE),
+            (self.digits, NUMBERS),
+            (self.symbols, self.symbol_set.as_str()),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, charset)| {
+            let mut seen = Vec::new();
+            charset
+                .chars()
+                .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+                .filter(|c| {
+                    let first = !seen.contains(c);
+                    seen.push(*c);
+                    first
+                })
+                .collect()
+        })
+        .collect()
+    }
+
+    /// Checks that passwords satisfying this policy can actually be generated.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(c) = self
+            .symbol_set
+            .chars()
+            .find(|c| c.is_ascii_alphanumeric() || c.is_whitespace() || c.is_control())
+        {
+            return Err(Error::new(&format!(
+                "The symbol set cannot contain letters, digits, whitespace or control characters (found {:?})",
+                c
+            )));
+        }
+        let classes = self.classes();
+        if classes.is_empty() {
+            return Err(Error::new(
+                "The password policy must enable at least one character class",
+            ));
+        }
+        if classes.iter().any(|charset| charset.is_empty()) {
+            return Err(Error::new(
+                "The symbol set is empty, so symbols cannot be included",
+            ));
+        }
+        let required = (classes.len() * self.min_per_class).max(1);
+        if self.length < required {
+            return Err(Error::new(&format!(
+                "A length of {} is too short: the policy requires at least {} of each of the {} enabled character classes",
+                self.length,
+                self.min_per_class,
+                classes.len()
+            )));
+        }
+        if self.length > MAX_LENGTH {
+            return Err(Error::new(&format!(
+                "The password length cannot exceed {}",
+                MAX_LENGTH
+            )));
+        }
+        self.validate_passphrase()
+    }
+
+    fn validate_passphrase(&self) -> Result<(), Error> {
+        let passphrase = &self.passphrase;
+        if passphrase.words == 0 || passphrase.words > MAX_WORDS {
+            return Err(Error::new(&format!(
+                "A passphrase must have between 1 and {} words",
+                MAX_WORDS
+            )));
+        }
+        if passphrase.separator.chars().any(|c| c.is_control()) {
+            return Err(Error::new(
+                "The passphrase separator cannot contain control characters",
+            ));
+        }
+        if passphrase.symbol && self.symbol_set.is_empty() {
+            return Err(Error::new(
+                "The symbol set is empty, so no symbol can be added to the passphrase",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Generates a password following `policy`, which must have passed
+/// [`PasswordPolicy::validate`].
+pub fn generate(policy: &PasswordPolicy) -> String {
+    let mut rng = thread_rng();
+    let classes = policy.classes();
+
+    // Start with the minimum number of characters from each class so the result
+    // always passes validate_password, then fill the rest from randomly chosen classes.
+    let mut chars: Vec<char> = classes
+        .iter()
+        .flat_map(|c| std::iter::repeat_n(c, policy.min_per_class))
+        .map(|c| random_char(&mut rng, c))
+        .collect();
+    while chars.len() < policy.length {
+        let class = &classes[rng.gen_range(0..classes.len())];
+        chars.push(random_char(&mut rng, class));
+    }
+    // Shuffle so the guaranteed class characters don't sit at fixed positions.
+    chars.shuffle(&mut rng);
+    chars.into_iter().collect()
+}
+
+/// Generates a password that satisfies a site's `rules`. The policy's length is
+/// used when it falls within the lengths the rules accept.
+pub fn generate_for_rules(rules: &PasswordRules, policy: &PasswordPolicy) -> Result<String, Error> {
+    let charsets = |classes: &[CharClass]| -> String {
+        let mut chars: Vec<char> = classes.iter().flat_map(|c| c.generator_chars().chars().collect::<Vec<_>>()).collect();
+        chars.sort_unstable();
+        chars.dedup();
+        chars.into_iter().collect()
+    };
+    let required: Vec<String> = rules.required.iter().map(|classes| charsets(classes)).collect();
+    let allowed = charsets(&rules.allowed_classes());
+    if required.iter().any(|charset| charset.is_empty()) || allowed.is_empty() {
+        return Err(Error::new("The password rules leave no characters to generate from"));
+    }
+    let length = policy
+        .length
+        .max(rules.min_length.unwrap_or(0))
+        .min(rules.max_length.unwrap_or(usize::MAX));
+    if length < required.len() {
+        return Err(Error::new(&format!(
+            "The password rules require {} different kinds of characters in at most {} characters",
+            required.len(),
+            length
+        )));
+    }
+
+    let mut rng = thread_rng();
+    // Retry until the result has no long runs of one character; with a single
+    // allowed character and a run limit this can never succeed.
+    for _ in 0..1000 {
+        let mut chars: Vec<char> = required.iter().map(|c| random_char(&mut rng, c)).collect();
+        while chars.len() < length {
+            chars.push(random_char(&mut rng, &allowed));
+        }
+        chars.shuffle(&mut rng);
+        let password: String = chars.into_iter().collect();
+        if rules.max_consecutive.is_none_or(|max| longest_run(&password) <= max) {
+            return Ok(password);
+        }
+    }
+    Err(Error::new(
+        "Unable to generate a password that satisfies the max-consecutive rule",
+    ))
+}
+
+fn wordlist() -> Vec<&'static str> {
+    EFF_WORDLIST
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .collect()
+}
+
+/// Generates a diceware passphrase following `policy.passphrase`. The symbol, if
+/// any, comes from the policy's symbol set.
+pub fn generate_passphrase(policy: &PasswordPolicy) -> String {
+    let mut rng = thread_rng();
+    let options = &policy.passphrase;
+    let words = wordlist();
+    let mut chosen: Vec<String> = (0..options.words)
+        .map(|_| {
+            let word = *words.choose(&mut rng).expect("the word list is not empty");
+            if options.capitalize {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+    if options.digit {
+        let index = rng.gen_range(0..chosen.len());
+        chosen[index].push(random_char(&mut rng, NUMBERS));
+    }
+    if options.symbol {
+        let index = rng.gen_range(0..chosen.len());
+        chosen[index].push(random_char(&mut rng, &policy.symbol_set));
+    }
+    chosen.join(&options.separator)
+}
+
+/// Entropy in bits of passphrases from [`generate_passphrase`], assuming the
+/// attacker knows the word list and the policy.
+pub fn passphrase_entropy(policy: &PasswordPolicy) -> f64 {
+    let options = &policy.passphrase;
+    let words = options.words as f64;
+    let mut bits = words * (wordlist().len() as f64).log2();
+    // Each insertion picks a character and the word it is appended to.
+    if options.digit {
+        bits += (NUMBERS.len() as f64).log2() + words.log2();
+    }
+    if options.symbol {
+        bits += (policy.symbol_set.chars().count() as f64).log2() + words.log2();
+    }
+    bits
+}
+
+/// Checks that `value` is at least as long as the policy requires and contains
+/// the minimum number of characters from each enabled class. Other characters
+/// are allowed, so passwords made elsewhere still pass.
+pub fn validate_password(value: &str, policy: &PasswordPolicy) -> bool {
+    value.chars().count() >= policy.length
+        && policy.classes().iter().all(|charset| {
+            value.chars().filter(|c| charset.contains(*c)).count() >= policy.min_per_class
+        })
+}
+
+fn random_char(rng: &mut impl Rng, charset: &str) -> char {
+    // Index over chars, not bytes — SPECIAL contains multi-byte characters.
+    let count = charset.chars().count();
+    charset
+        .chars()
+        .nth(rng.gen_range(0..count))
+        .expect("index is within the charset's character count")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alphabets_are_complete() {
+        assert_eq!(LOW_CASE, "abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(UP_CASE, "ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        assert_eq!(LOW_CASE.chars().count(), 26);
+        assert_eq!(UP_CASE.chars().count(), 26);
+    }
+
+    #[test]
+    fn generated_password_has_correct_length() {
+        assert_eq!(
+            generate(&PasswordPolicy::default()).chars().count(),
+            DEFAULT_LENGTH
+        );
+    }
+
+    #[test]
+    fn generated_password_always_validates() {
+        let policy = PasswordPolicy::default();
+        for _ in 0..100 {
+            let password = generate(&policy);
+            assert!(
+                validate_password(&password, &policy),
+                "generated password failed validation: {}",
+                password
+            );
+        }
+    }
+
+    #[test]
+    fn random_char_reaches_last_character() {
+        use rand::{rngs::StdRng, SeedableRng};
+        // Seeded so the run is deterministic; 2000 draws cover all 26 chars.
+        let mut rng = StdRng::seed_from_u64(42);
+        assert!((0..2000).any(|_| random_char(&mut rng, LOW_CASE) == 'z'));
+    }
+
+    #[test]
+    fn random_char_handles_multibyte_charsets() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..500 {
+            let c = random_char(&mut rng, SPECIAL);
+            assert!(SPECIAL.contains(c));
+        }
+    }
+
+    #[test]
+    fn generated_password_follows_custom_policy() {
+        let policy = PasswordPolicy {
+            length: 8,
+            uppercase: false,
+            symbol_set: "-_!".to_string(),
+            min_per_class: 2,
+            exclude_ambiguous: true,
+            ..PasswordPolicy::default()
+        };
+        policy.validate().unwrap();
+        for _ in 0..200 {
+            let password = generate(&policy);
+            assert_eq!(password.chars().count(), 8);
+            assert!(password.chars().all(|c| "-_!".contains(c)
+                || (c.is_ascii_lowercase() && c != 'l')
+                || ('2'..='9').contains(&c)));
+            assert!(password.chars().filter(|c| "-_!".contains(*c)).count() >= 2);
+            assert!(validate_password(&password, &policy), "{}", password);
+        }
+    }
+
+    #[test]
+    fn validate_password_checks_the_active_policy() {
+        let policy = PasswordPolicy {
+            length: 6,
+            symbols: false,
+            min_per_class: 2,
+            ..PasswordPolicy::default()
+        };
+        assert!(validate_password("abCD12", &policy));
+        assert!(!validate_password("abCD1", &policy), "too short");
+        assert!(!validate_password("abcD12", &policy), "one uppercase");
+        assert!(validate_password("abCD12!", &policy), "other characters are allowed");
+        assert!(validate_password("abCD12€ ", &policy));
+        assert!(validate_password("abCD12!", &PasswordPolicy::default().with_overrides(
+            &PolicyOverrides { length: Some(6), min_per_class: Some(1), ..Default::default() }
+        )));
+    }
+
+    #[test]
+    fn overrides_turn_options_on_and_off() {
+        let saved = PasswordPolicy {
+            symbols: false,
+            exclude_ambiguous: true,
+            passphrase: PassphrasePolicy { capitalize: true, ..PassphrasePolicy::default() },
+            ..PasswordPolicy::default()
+        };
+        let policy = saved.with_overrides(&PolicyOverrides {
+            symbols: Some(true),
+            lowercase: Some(false),
+            exclude_ambiguous: Some(false),
+            capitalize: Some(false),
+            with_digit: Some(true),
+            ..Default::default()
+        });
+        assert!(policy.symbols);
+        assert!(!policy.lowercase);
+        assert!(policy.uppercase && policy.digits);
+        assert!(!policy.exclude_ambiguous);
+        assert!(!policy.passphrase.capitalize);
+        assert!(policy.passphrase.digit);
+        assert_eq!(saved.with_overrides(&PolicyOverrides::default()), saved);
+    }
+
+    #[test]
+    fn invalid_policies_are_rejected() {
+        let base = PasswordPolicy::default();
+        let no_classes = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: false,
+            ..base.clone()
+        };
+        assert!(no_classes.validate().is_err());
+        assert!(PasswordPolicy { length: 3, ..base.clone() }.validate().is_err());
+        assert!(PasswordPolicy { length: 9, min_per_class: 3, ..base.clone() }.validate().is_err());
+        assert!(PasswordPolicy { symbol_set: String::new(), ..base.clone() }.validate().is_err());
+        assert!(PasswordPolicy { symbol_set: "!a".to_string(), ..base.clone() }.validate().is_err());
+        assert!(PasswordPolicy { length: 4096, ..base.clone() }.validate().is_err());
+        let no_symbols = PasswordPolicy { symbols: false, symbol_set: String::new(), length: 3, ..base };
+        assert!(no_symbols.validate().is_ok());
+    }
+
+    #[test]
+    fn policy_config_fills_missing_fields_with_defaults() {
+        let policy: PasswordPolicy =
+            serde_json::from_str(r#"{"length": 24, "symbol_set": "!#"}"#).unwrap();
+        assert_eq!(policy.length, 24);
+        assert_eq!(policy.symbol_set, "!#");
+        assert!(policy.lowercase && policy.symbols);
+        assert!(serde_json::from_str::<PasswordPolicy>(r#"{"lenght": 24}"#).is_err());
+    }
+
+    #[test]
+    fn wordlist_is_the_eff_long_list() {
+        let words = wordlist();
+        assert_eq!(words.len(), 7776);
+        assert_eq!(words.first(), Some(&"abacus"));
+        assert_eq!(words.last(), Some(&"zoom"));
+    }
+
+    #[test]
+    fn passphrase_follows_policy() {
+        let policy = PasswordPolicy {
+            symbol_set: "!".to_string(),
+            passphrase: PassphrasePolicy {
+                words: 5,
+                separator: " ".to_string(),
+                capitalize: true,
+                digit: true,
+                symbol: true,
+            },
+            ..PasswordPolicy::default()
+        };
+        policy.validate().unwrap();
+        let words = wordlist();
+        for _ in 0..50 {
+            let passphrase = generate_passphrase(&policy);
+            let parts: Vec<&str> = passphrase.split(' ').collect();
+            assert_eq!(parts.len(), 5);
+            assert!(parts.iter().all(|p| p.chars().next().unwrap().is_ascii_uppercase()));
+            assert_eq!(passphrase.chars().filter(|c| c.is_ascii_digit()).count(), 1);
+            assert_eq!(passphrase.matches('!').count(), 1);
+            for part in parts {
+                let word = part.trim_end_matches(|c: char| c == '!' || c.is_ascii_digit());
+                assert!(words.contains(&word.to_lowercase().as_str()), "{}", part);
+            }
+        }
+    }
+
+    #[test]
+    fn passphrase_entropy_counts_words_and_insertions() {
+        let mut policy = PasswordPolicy::default();
+        let per_word = 7776f64.log2();
+        assert!((passphrase_entropy(&policy) - 6.0 * per_word).abs() < 1e-9);
+        policy.passphrase.words = 4;
+        policy.passphrase.digit = true;
+        policy.symbol_set = "!#".to_string();
+        policy.passphrase.symbol = true;
+        let expected = 4.0 * per_word + 10f64.log2() + 2.0 + 1.0 + 2.0;
+        assert!((passphrase_entropy(&policy) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_passphrase_policies_are_rejected() {
+        let mut policy = PasswordPolicy::default();
+        policy.passphrase.words = 0;
+        assert!(policy.validate().is_err());
+        policy.passphrase.words = 65;
+        assert!(policy.validate().is_err());
+        policy.passphrase.words = 6;
+        policy.passphrase.separator = "\n".to_string();
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn generated_password_satisfies_rules() {
+        let rules: PasswordRules =
+            "minlength: 20; maxlength: 24; max-consecutive: 1; required: upper; required: digit, [-_]; allowed: lower"
+                .parse()
+                .unwrap();
+        for _ in 0..100 {
+            let password = generate_for_rules(&rules, &PasswordPolicy::default()).unwrap();
+            assert_eq!(password.chars().count(), 20);
+            assert!(rules.accepts(&password), "{}", password);
+        }
+        let short: PasswordRules = "maxlength: 8".parse().unwrap();
+        let password = generate_for_rules(&short, &PasswordPolicy::default()).unwrap();
+        assert_eq!(password.chars().count(), 8);
+        assert!(password.chars().all(|c| ('!'..='~').contains(&c)));
+    }
+
+    #[test]
+    fn impossible_rules_are_reported() {
+        let policy = PasswordPolicy::default();
+        let too_short: PasswordRules =
+            "maxlength: 2; required: upper; required: lower; required: digit".parse().unwrap();
+        assert!(generate_for_rules(&too_short, &policy).is_err());
+        let one_char: PasswordRules = "max-consecutive: 1; allowed: [x]".parse().unwrap();
+        assert!(generate_for_rules(&one_char, &policy).is_err());
+    }
+}
@@ -0,0 +1,1027 @@
+//! Password strength estimation in the style of zxcvbn. A password is split
+//! into the cheapest sequence of guessable patterns (dictionary words, keyboard
+//! walks, repeats, sequences, dates), with random characters in between, and
+//! the guesses needed for each pattern are multiplied together.
+
+use chrono::{Datelike, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Ranked word lists, most common first, under `[name]` section headers.
+const DICTIONARY: &str = include_str!("strength_dictionary.txt");
+
+/// Characters past this are assumed random, keeping the search quadratic.
+const MAX_ANALYZED_CHARS: usize = 100;
+const MAX_WORD_LENGTH: usize = 30;
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+const MIN_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_GUESSES_MULTI_CHAR: f64 = 50.0;
+/// Penalizes splitting a password into many short patterns.
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 10000.0;
+const MIN_YEAR_SPACE: i32 = 20;
+const MAX_SEQUENCE_DELTA: i32 = 5;
+/// Offline attack against a slow password hash.
+const GUESSES_PER_SECOND: f64 = 1e4;
+
+const L33T_TABLE: &[(char, &[char])] = &[
+    ('4', &['a']),
+    ('@', &['a']),
+    ('8', &['b']),
+    ('(', &['c']),
+    ('{', &['c']),
+    ('[', &['c']),
+    ('<', &['c']),
+    ('3', &['e']),
+    ('6', &['g']),
+    ('9', &['g']),
+    ('1', &['i', 'l']),
+    ('!', &['i']),
+    ('|', &['i', 'l']),
+    ('7', &['l', 't']),
+    ('0', &['o']),
+    ('$', &['s']),
+    ('5', &['s']),
+    ('+', &['t']),
+    ('%', &['x']),
+    ('2', &['z']),
+];
+
+const QWERTY: [&str; 4] = [
+    "`~ 1! 2@ 3# 4$ 5% 6^ 7& 8* 9( 0) -_ =+",
+    "qQ wW eE rR tT yY uU iI oO pP [{ ]} \\|",
+    "aA sS dD fF gG hH jJ kK lL ;: '\"",
+    "zZ xX cC vV bB nN mM ,< .> /?",
+];
+const KEYPAD: [&str; 5] = ["_ / * -", "7 8 9 +", "4 5 6", "1 2 3", "_ 0 ."];
+
+/// The kind of pattern a part of the password was recognized as.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "pattern", rename_all = "snake_case")]
+pub enum Pattern {
+    Dictionary {
+        dictionary: &'static str,
+        word: String,
+        rank: usize,
+        reversed: bool,
+        l33t: bool,
+    },
+    Spatial {
+        graph: &'static str,
+        turns: usize,
+        shifted: usize,
+    },
+    Repeat {
+        base: String,
+        count: usize,
+    },
+    Sequence {
+        ascending: bool,
+    },
+    Date {
+        year: i32,
+    },
+    Bruteforce,
+}
+
+/// A part of the password, `start..=end` in characters, and the guesses an
+/// attacker needs to find it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub token: String,
+    pub pattern: Pattern,
+    pub guesses: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable).
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_time_seconds: f64,
+    pub crack_time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+impl Strength {
+    pub fn score_label(&self) -> &'static str {
+        match self.score {
+            0 => "very weak",
+            1 => "weak",
+            2 => "fair",
+            3 => "strong",
+            _ => "very strong",
+        }
+    }
+}
+
+/// Estimates how many guesses an attacker needs to find `password`. Words in
+/// `user_inputs`, such as the service and username, count as the most common
+/// dictionary words.
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> Strength {
+    analyze(password, user_inputs).0
+}
+
+/// Estimates the strength of `password` and returns the patterns it was split into.
+fn analyze(password: &str, user_inputs: &[&str]) -> (Strength, Vec<Match>) {
+    let chars: Vec<char> = password.chars().collect();
+    let analyzed = &chars[..chars.len().min(MAX_ANALYZED_CHARS)];
+    let unanalyzed = (chars.len() - analyzed.len()) as f64;
+
+    let user_dictionary: HashMap<String, usize> = user_inputs
+        .iter()
+        .flat_map(|input| {
+            let lower = input.to_lowercase();
+            let mut words: Vec<String> = lower
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| w.chars().count() >= 3)
+                .map(|w| w.to_string())
+                .collect();
+            words.push(lower);
+            words
+        })
+        .enumerate()
+        .map(|(i, w)| (w, i + 1))
+        .collect();
+
+    let matches = omnimatch(analyzed, &user_dictionary);
+    let (guesses, sequence) = most_guessable_sequence(analyzed, &matches);
+    let guesses_log10 = guesses.log10() + unanalyzed * BRUTEFORCE_CARDINALITY.log10();
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    let crack_time_seconds = 10f64.powf(guesses_log10 - GUESSES_PER_SECOND.log10());
+    let warning = if score < 3 { warning_for(&sequence) } else { None };
+    let strength = Strength {
+        score,
+        guesses_log10,
+        crack_time_seconds,
+        crack_time: display_time(crack_time_seconds),
+        warning,
+    };
+    (strength, sequence)
+}
+
+fn display_time(seconds: f64) -> String {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = MINUTE * 60.0;
+    const DAY: f64 = HOUR * 24.0;
+    const MONTH: f64 = DAY * 31.0;
+    const YEAR: f64 = MONTH * 12.0;
+    const CENTURY: f64 = YEAR * 100.0;
+    let (amount, unit) = match seconds {
+        s if s < 1.0 => return "less than a second".to_string(),
+        s if s < MINUTE => (s, "second"),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s if s < MONTH => (s / DAY, "day"),
+        s if s < YEAR => (s / MONTH, "month"),
+        s if s < CENTURY => (s / YEAR, "year"),
+        _ => return "centuries".to_string(),
+    };
+    let amount = amount.round() as u64;
+    format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+fn warning_for(sequence: &[Match]) -> Option<String> {
+    let longest = sequence
+        .iter()
+        .filter(|m| m.pattern != Pattern::Bruteforce)
+        .max_by_key(|m| m.end - m.start);
+    let Some(m) = longest else {
+        return Some("Use a longer password; length beats complexity".to_string());
+    };
+    let warning = match &m.pattern {
+        Pattern::Dictionary {
+            dictionary, rank, ..
+        } => match *dictionary {
+            "passwords" if sequence.len() == 1 && *rank <= 10 => {
+                "This is a top-10 common password"
+            }
+            "passwords" if sequence.len() == 1 && *rank <= 100 => {
+                "This is a top-100 common password"
+            }
+            "passwords" if sequence.len() == 1 => "This is a very common password",
+            "passwords" => "This is similar to a commonly used password",
+            "user_inputs" => "Passwords containing the service or username are easy to guess",
+            "english" => "A word by itself is easy to guess",
+            _ => "Names and surnames by themselves are easy to guess",
+        },
+        Pattern::Spatial { turns: 1, .. } => "Straight rows of keys are easy to guess",
+        Pattern::Spatial { .. } => "Short keyboard patterns are easy to guess",
+        Pattern::Repeat { base, .. } if base.chars().count() == 1 => {
+            "Repeats like \"aaa\" are easy to guess"
+        }
+        Pattern::Repeat { .. } => {
+            "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\""
+        }
+        Pattern::Sequence { .. } => "Sequences like abc or 6543 are easy to guess",
+        Pattern::Date { .. } => "Dates and years are easy to guess",
+        Pattern::Bruteforce => return None,
+    };
+    Some(warning.to_string())
+}
+
+fn dictionaries() -> &'static HashMap<&'static str, (&'static str, usize)> {
+    static RANKED: OnceLock<HashMap<&'static str, (&'static str, usize)>> = OnceLock::new();
+    RANKED.get_or_init(|| {
+        let mut ranked = HashMap::new();
+        let mut section = "";
+        let mut rank = 0;
+        for line in DICTIONARY.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name;
+                rank = 0;
+                continue;
+            }
+            rank += 1;
+            // Keep the best rank when a word is in several lists.
+            let entry = ranked.entry(line).or_insert((section, rank));
+            if rank < entry.1 {
+                *entry = (section, rank);
+            }
+        }
+        ranked
+    })
+}
+
+fn omnimatch(chars: &[char], user_dictionary: &HashMap<String, usize>) -> Vec<Match> {
+    let mut matches = dictionary_matches(chars, user_dictionary);
+    matches.extend(reverse_dictionary_matches(chars, user_dictionary));
+    matches.extend(l33t_matches(chars, user_dictionary));
+    matches.extend(spatial_matches(chars));
+    matches.extend(repeat_matches(chars, user_dictionary));
+    matches.extend(sequence_matches(chars));
+    matches.extend(date_matches(chars));
+    matches
+}
+
+fn token(chars: &[char], start: usize, end: usize) -> String {
+    chars[start..=end].iter().collect()
+}
+
+fn lookup(word: &str, user_dictionary: &HashMap<String, usize>) -> Option<(&'static str, usize)> {
+    let user = user_dictionary.get(word).map(|rank| ("user_inputs", *rank));
+    let ranked = dictionaries().get(word).copied();
+    match (user, ranked) {
+        (Some(u), Some(r)) => Some(if u.1 <= r.1 { u } else { r }),
+        (u, r) => u.or(r),
+    }
+}
+
+fn n_choose_k(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (1..=k).fold(1.0, |acc, i| acc * (n - k + i) as f64 / i as f64)
+}
+
+/// How many ways a word can be capitalized like `token` is.
+fn uppercase_variations(token: &str) -> f64 {
+    let chars: Vec<char> = token.chars().collect();
+    if !chars.iter().any(|c| c.is_uppercase()) {
+        return 1.0;
+    }
+    let first_upper = chars[0].is_uppercase() && !chars[1..].iter().any(|c| c.is_uppercase());
+    let last_upper = chars[chars.len() - 1].is_uppercase()
+        && !chars[..chars.len() - 1].iter().any(|c| c.is_uppercase());
+    let all_upper = !chars.iter().any(|c| c.is_lowercase());
+    if first_upper || last_upper || all_upper {
+        return 2.0;
+    }
+    let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+    let lower = chars.iter().filter(|c| c.is_lowercase()).count();
+    (1..=upper.min(lower))
+        .map(|i| n_choose_k(upper + lower, i))
+        .sum()
+}
+
+fn dictionary_matches(chars: &[char], user_dictionary: &HashMap<String, usize>) -> Vec<Match> {
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for end in start..chars.len().min(start + MAX_WORD_LENGTH) {
+            let word = token(&lower, start, end);
+            if let Some((dictionary, rank)) = lookup(&word, user_dictionary) {
+                let original = token(chars, start, end);
+                let guesses = rank as f64 * uppercase_variations(&original);
+                matches.push(Match {
+                    start,
+                    end,
+                    token: original,
+                    pattern: Pattern::Dictionary {
+                        dictionary,
+                        word,
+                        rank,
+                        reversed: false,
+                        l33t: false,
+                    },
+                    guesses,
+                });
+            }
+        }
+    }
+    matches
+}
+
+fn reverse_dictionary_matches(
+    chars: &[char],
+    user_dictionary: &HashMap<String, usize>,
+) -> Vec<Match> {
+    let reversed: Vec<char> = chars.iter().rev().copied().collect();
+    let n = chars.len();
+    dictionary_matches(&reversed, user_dictionary)
+        .into_iter()
+        // Palindromes are already found forwards.
+        .filter(|m| m.end > m.start)
+        .map(|m| {
+            let (start, end) = (n - 1 - m.end, n - 1 - m.start);
+            let Pattern::Dictionary {
+                dictionary,
+                word,
+                rank,
+                ..
+            } = m.pattern
+            else {
+                unreachable!("dictionary_matches only returns dictionary matches")
+            };
+            Match {
+                start,
+                end,
+                token: token(chars, start, end),
+                pattern: Pattern::Dictionary {
+                    dictionary,
+                    word,
+                    rank,
+                    reversed: true,
+                    l33t: false,
+                },
+                guesses: m.guesses * 2.0,
+            }
+        })
+        .collect()
+}
+
+fn l33t_substitutes(c: char) -> Option<&'static [char]> {
+    L33T_TABLE
+        .iter()
+        .find(|(from, _)| *from == c)
+        .map(|(_, to)| *to)
+}
+
+/// Extra guesses for the substitutions made in `original` to get `word`.
+fn l33t_variations(original: &[char], word: &[char]) -> f64 {
+    let subbed: HashSet<(char, char)> = original
+        .iter()
+        .zip(word)
+        .filter(|(o, w)| o.to_lowercase().next() != Some(**w))
+        .map(|(o, w)| (*o, *w))
+        .collect();
+    subbed
+        .iter()
+        .map(|(from, to)| {
+            let s = original.iter().filter(|c| *c == from).count();
+            let u = original
+                .iter()
+                .filter(|c| c.to_lowercase().next() == Some(*to))
+                .count();
+            if u == 0 {
+                2.0
+            } else {
+                (1..=s.min(u)).map(|i| n_choose_k(s + u, i)).sum()
+            }
+        })
+        .product()
+}
+
+fn l33t_matches(chars: &[char], user_dictionary: &HashMap<String, usize>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for end in (start + 1)..chars.len().min(start + MAX_WORD_LENGTH) {
+            let slice = &chars[start..=end];
+            if !slice.iter().any(|c| l33t_substitutes(*c).is_some()) {
+                continue;
+            }
+            // Every combination of substitutions, e.g. '1' as 'i' or 'l'.
+            let mut candidates: Vec<Vec<char>> = vec![Vec::new()];
+            for c in slice {
+                let options: Vec<char> = match l33t_substitutes(*c) {
+                    Some(to) => to.to_vec(),
+                    None => vec![c.to_lowercase().next().unwrap_or(*c)],
+                };
+                candidates = candidates
+                    .into_iter()
+                    .flat_map(|prefix| {
+                        options.iter().map(move |o| {
+                            let mut next = prefix.clone();
+                            next.push(*o);
+                            next
+                        })
+                    })
+                    .take(64)
+                    .collect();
+            }
+            for candidate in candidates {
+                let word: String = candidate.iter().collect();
+                if let Some((dictionary, rank)) = lookup(&word, user_dictionary) {
+                    let original = token(chars, start, end);
+                    let guesses = rank as f64
+                        * uppercase_variations(&original)
+                        * l33t_variations(slice, &candidate);
+                    matches.push(Match {
+                        start,
+                        end,
+                        token: original,
+                        pattern: Pattern::Dictionary {
+                            dictionary,
+                            word,
+                            rank,
+                            reversed: false,
+                            l33t: true,
+                        },
+                        guesses,
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+struct KeyboardGraph {
+    name: &'static str,
+    /// Position of each character and whether it needs shift.
+    keys: HashMap<char, (i32, i32, bool)>,
+    positions: HashMap<(i32, i32), ()>,
+    /// Offsets of the neighbouring keys, in a fixed order so that a change of
+    /// offset is a turn.
+    neighbours: &'static [(i32, i32)],
+}
+
+impl KeyboardGraph {
+    fn new(
+        name: &'static str,
+        rows: &[&str],
+        first_column: &[i32],
+        neighbours: &'static [(i32, i32)],
+    ) -> Self {
+        let mut keys = HashMap::new();
+        let mut positions = HashMap::new();
+        for (row, line) in rows.iter().enumerate() {
+            for (column, key) in line.split(' ').enumerate() {
+                if key == "_" {
+                    continue;
+                }
+                let position = (row as i32, first_column[row] + column as i32);
+                positions.insert(position, ());
+                for (i, c) in key.chars().enumerate() {
+                    keys.insert(c, (position.0, position.1, i == 1));
+                }
+            }
+        }
+        KeyboardGraph {
+            name,
+            keys,
+            positions,
+            neighbours,
+        }
+    }
+
+    fn direction(&self, from: char, to: char) -> Option<usize> {
+        let (r1, c1, _) = self.keys.get(&from)?;
+        let (r2, c2, _) = self.keys.get(&to)?;
+        self.neighbours
+            .iter()
+            .position(|(dr, dc)| r1 + dr == *r2 && c1 + dc == *c2)
+    }
+
+    fn average_degree(&self) -> f64 {
+        let total: usize = self
+            .positions
+            .keys()
+            .map(|(r, c)| {
+                self.neighbours
+                    .iter()
+                    .filter(|(dr, dc)| self.positions.contains_key(&(r + dr, c + dc)))
+                    .count()
+            })
+            .sum();
+        total as f64 / self.positions.len() as f64
+    }
+}
+
+fn keyboard_graphs() -> &'static [KeyboardGraph; 2] {
+    static GRAPHS: OnceLock<[KeyboardGraph; 2]> = OnceLock::new();
+    GRAPHS.get_or_init(|| {
+        [
+            // Rows are staggered: a key touches two keys above and two below.
+            KeyboardGraph::new(
+                "qwerty",
+                &QWERTY,
+                &[0, 1, 1, 1],
+                &[(0, -1), (-1, 0), (-1, 1), (0, 1), (1, 0), (1, -1)],
+            ),
+            KeyboardGraph::new(
+                "keypad",
+                &KEYPAD,
+                &[0, 0, 0, 0, 0],
+                &[
+                    (0, -1),
+                    (-1, -1),
+                    (-1, 0),
+                    (-1, 1),
+                    (0, 1),
+                    (1, 1),
+                    (1, 0),
+                    (1, -1),
+                ],
+            ),
+        ]
+    })
+}
+
+fn spatial_guesses(graph: &KeyboardGraph, length: usize, turns: usize, shifted: usize) -> f64 {
+    let starts = graph.keys.len() as f64;
+    let degree = graph.average_degree();
+    let mut guesses = 0.0;
+    for i in 2..=length {
+        for j in 1..=turns.min(i - 1) {
+            guesses += n_choose_k(i - 1, j - 1) * starts * degree.powi(j as i32);
+        }
+    }
+    if shifted > 0 {
+        let unshifted = length - shifted;
+        guesses *= if unshifted == 0 {
+            2.0
+        } else {
+            (1..=shifted.min(unshifted))
+                .map(|i| n_choose_k(shifted + unshifted, i))
+                .sum()
+        };
+    }
+    guesses
+}
+
+fn spatial_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for graph in keyboard_graphs() {
+        let mut start = 0;
+        while start + 2 < chars.len() {
+            let mut end = start;
+            let mut turns = 0;
+            let mut last_direction = None;
+            while end + 1 < chars.len() {
+                let Some(direction) = graph.direction(chars[end], chars[end + 1]) else {
+                    break;
+                };
+                if last_direction != Some(direction) {
+                    turns += 1;
+                    last_direction = Some(direction);
+                }
+                end += 1;
+            }
+            if end - start >= 2 {
+                let shifted = chars[start..=end]
+                    .iter()
+                    .filter(|c| graph.keys.get(c).is_some_and(|k| k.2))
+                    .count();
+                let length = end - start + 1;
+                matches.push(Match {
+                    start,
+                    end,
+                    token: token(chars, start, end),
+                    pattern: Pattern::Spatial {
+                        graph: graph.name,
+                        turns,
+                        shifted,
+                    },
+                    guesses: spatial_guesses(graph, length, turns, shifted),
+                });
+                start = end;
+            } else {
+                start += 1;
+            }
+        }
+    }
+    matches
+}
+
+fn repeat_matches(chars: &[char], user_dictionary: &HashMap<String, usize>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        // The longest run of a repeated block starting here, shortest block first.
+        let mut best: Option<(usize, usize)> = None;
+        for block in 1..=(chars.len() - start) / 2 {
+            let base = &chars[start..start + block];
+            let mut count = 1;
+            while start + (count + 1) * block <= chars.len()
+                && &chars[start + count * block..start + (count + 1) * block] == base
+            {
+                count += 1;
+            }
+            if count >= 2 && best.is_none_or(|(b, c)| block * count > b * c) {
+                best = Some((block, count));
+            }
+        }
+        match best {
+            Some((block, count)) => {
+                let base = &chars[start..start + block];
+                let base_matches = omnimatch(base, user_dictionary);
+                let (base_guesses, _) = most_guessable_sequence(base, &base_matches);
+                let end = start + block * count - 1;
+                matches.push(Match {
+                    start,
+                    end,
+                    token: token(chars, start, end),
+                    pattern: Pattern::Repeat {
+                        base: base.iter().collect(),
+                        count,
+                    },
+                    guesses: base_guesses * count as f64,
+                });
+                start = end + 1;
+            }
+            None => start += 1,
+        }
+    }
+    matches
+}
+
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        let mut end = start + 1;
+        if delta != 0 && delta.abs() <= MAX_SEQUENCE_DELTA {
+            while end + 1 < chars.len() && chars[end + 1] as i32 - chars[end] as i32 == delta {
+                end += 1;
+            }
+        }
+        if end - start >= 2 {
+            let first = chars[start];
+            let base = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let ascending = delta > 0;
+            matches.push(Match {
+                start,
+                end,
+                token: token(chars, start, end),
+                pattern: Pattern::Sequence { ascending },
+                guesses: base * (end - start + 1) as f64 * if ascending { 1.0 } else { 2.0 },
+            });
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+fn year_space(year: i32) -> f64 {
+    (year - Utc::now().year()).abs().max(MIN_YEAR_SPACE) as f64
+}
+
+fn date_guesses(year: i32, separator: bool) -> f64 {
+    365.0 * year_space(year) * if separator { 4.0 } else { 1.0 }
+}
+
+fn full_year(year: i32, digits: usize) -> i32 {
+    match (digits, year) {
+        (4, y) => y,
+        (_, y) if y > 50 => 1900 + y,
+        (_, y) => 2000 + y,
+    }
+}
+
+fn valid_date(parts: &[(i32, usize)]) -> Option<i32> {
+    let [a, b, c] = parts else {
+        return None;
+    };
+    // Day-month-year, month-day-year and year-month-day orders.
+    let candidates = [(a, b, c), (b, a, c), (c, b, a)];
+    candidates.iter().find_map(|(day, month, year)| {
+        let year_ok = year.1 == 2 || (year.1 == 4 && (1000..=2050).contains(&year.0));
+        (day.1 <= 2 && month.1 <= 2 && (1..=31).contains(&day.0) && (1..=12).contains(&month.0) && year_ok)
+            .then(|| full_year(year.0, year.1))
+    })
+}
+
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let number = |s: &[char]| -> i32 { s.iter().collect::<String>().parse().unwrap_or(-1) };
+    for start in 0..chars.len() {
+        for end in (start + 3)..chars.len().min(start + 10) {
+            let slice = &chars[start..=end];
+            let add = |matches: &mut Vec<Match>, year: i32, guesses: f64| {
+                matches.push(Match {
+                    start,
+                    end,
+                    token: token(chars, start, end),
+                    pattern: Pattern::Date { year },
+                    guesses,
+                });
+            };
+            if slice.iter().all(|c| c.is_ascii_digit()) {
+                if slice.len() == 4 && (1900..=2050).contains(&number(slice)) {
+                    add(&mut matches, number(slice), year_space(number(slice)));
+                    continue;
+                }
+                if slice.len() > 8 {
+                    continue;
+                }
+                // Try every split into three parts.
+                let n = slice.len();
+                let date = (1..n - 1).find_map(|i| {
+                    (i + 1..n).find_map(|j| {
+                        let parts = [
+                            (number(&slice[..i]), i),
+                            (number(&slice[i..j]), j - i),
+                            (number(&slice[j..]), n - j),
+                        ];
+                        parts
+                            .iter()
+                            .all(|(_, len)| *len == 1 || *len == 2 || *len == 4)
+                            .then(|| valid_date(&parts))
+                            .flatten()
+                    })
+                });
+                if let Some(year) = date {
+                    add(&mut matches, year, date_guesses(year, false));
+                }
+            } else {
+                let Some(separator) = slice.iter().find(|c| !c.is_ascii_digit()) else {
+                    continue;
+                };
+                if !"/\\-._ ".contains(*separator) {
+                    continue;
+                }
+                let parts: Vec<&[char]> = slice.split(|c| c == separator).collect();
+                if parts.len() != 3
+                    || parts
+                        .iter()
+                        .any(|p| p.is_empty() || !p.iter().all(|c| c.is_ascii_digit()))
+                {
+                    continue;
+                }
+                let parts: Vec<(i32, usize)> = parts.iter().map(|p| (number(p), p.len())).collect();
+                if let Some(year) = valid_date(&parts) {
+                    add(&mut matches, year, date_guesses(year, true));
+                }
+            }
+        }
+    }
+    matches
+}
+
+fn bruteforce_match(chars: &[char], start: usize, end: usize) -> Match {
+    let length = (end - start + 1) as i32;
+    let minimum = if length == 1 {
+        MIN_GUESSES_SINGLE_CHAR + 1.0
+    } else {
+        MIN_GUESSES_MULTI_CHAR + 1.0
+    };
+    Match {
+        start,
+        end,
+        token: token(chars, start, end),
+        pattern: Pattern::Bruteforce,
+        guesses: BRUTEFORCE_CARDINALITY.powi(length).max(minimum),
+    }
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).map(|i| i as f64).product()
+}
+
+/// Finds the sequence of non-overlapping matches covering the password that
+/// needs the fewest guesses, filling gaps with bruteforce matches.
+fn most_guessable_sequence(chars: &[char], matches: &[Match]) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (1.0, Vec::new());
+    }
+    // best[k][l]: cheapest way to cover chars[..=k] with l matches, as
+    // (product of guesses, last match).
+    let mut best: Vec<HashMap<usize, (f64, Match)>> = vec![HashMap::new(); n];
+    let score = |l: usize, product: f64| {
+        factorial(l) * product + MIN_GUESSES_BEFORE_GROWING_SEQUENCE.powi(l as i32 - 1)
+    };
+    let mut by_end: Vec<Vec<&Match>> = vec![Vec::new(); n];
+    for m in matches {
+        by_end[m.end].push(m);
+    }
+
+    for k in 0..n {
+        let mut candidates: Vec<(usize, f64, Match)> = Vec::new();
+        let guesses = |m: &Match| {
+            let minimum = if m.end == m.start {
+                MIN_GUESSES_SINGLE_CHAR
+            } else {
+                MIN_GUESSES_MULTI_CHAR
+            };
+            m.guesses.max(minimum)
+        };
+        for m in &by_end[k] {
+            if m.start == 0 {
+                candidates.push((1, guesses(m), (*m).clone()));
+            } else {
+                for (l, (product, _)) in &best[m.start - 1] {
+                    candidates.push((l + 1, product * guesses(m), (*m).clone()));
+                }
+            }
+        }
+        candidates.push((1, bruteforce_match(chars, 0, k).guesses, bruteforce_match(chars, 0, k)));
+        for start in 1..=k {
+            let bruteforce = bruteforce_match(chars, start, k);
+            for (l, (product, last)) in &best[start - 1] {
+                // Two bruteforce matches in a row are never better than one.
+                if last.pattern == Pattern::Bruteforce {
+                    continue;
+                }
+                candidates.push((l + 1, product * bruteforce.guesses, bruteforce.clone()));
+            }
+        }
+        for (l, product, m) in candidates {
+            let better = best[k]
+                .get(&l)
+                .is_none_or(|(current, _)| product < *current);
+            // Skip candidates beaten by a sequence with fewer matches.
+            let dominated = best[k]
+                .iter()
+                .any(|(other_l, (other, _))| *other_l < l && score(*other_l, *other) <= score(l, product));
+            if better && !dominated {
+                best[k].insert(l, (product, m));
+            }
+        }
+    }
+
+    let (mut l, guesses) = best[n - 1]
+        .iter()
+        .map(|(l, (product, _))| (*l, score(*l, *product)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("every position has a bruteforce candidate");
+    let mut sequence = Vec::new();
+    let mut k = n as isize - 1;
+    while k >= 0 {
+        let (_, m) = best[k as usize][&l].clone();
+        k = m.start as isize - 1;
+        l -= 1;
+        sequence.push(m);
+    }
+    sequence.reverse();
+    (guesses, sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(password: &str) -> Vec<Pattern> {
+        analyze(password, &[])
+            .1
+            .into_iter()
+            .map(|m| m.pattern)
+            .collect()
+    }
+
+    #[test]
+    fn common_passwords_score_zero() {
+        for password in ["password", "123456", "qwerty", "iloveyou", "Password1"] {
+            let (strength, sequence) = analyze(password, &[]);
+            assert_eq!(strength.score, 0, "{} {:?}", password, sequence);
+        }
+    }
+
+    #[test]
+    fn random_passwords_score_four() {
+        for password in ["kX9#mQ2$vL7!pR4z", "cT8%wN3@hF6^jB1q"] {
+            let strength = estimate_strength(password, &[]);
+            assert_eq!(strength.score, 4, "{}", password);
+            assert_eq!(strength.crack_time, "centuries");
+            assert_eq!(strength.warning, None);
+        }
+    }
+
+    #[test]
+    fn passphrases_are_strong() {
+        let strength = estimate_strength("correct-horse-battery-staple", &[]);
+        assert!(strength.score >= 3, "{:?}", strength);
+    }
+
+    #[test]
+    fn recognizes_dictionary_words_and_capitalization() {
+        let (_, sequence) = analyze("Monkey", &[]);
+        assert!(matches!(
+            &sequence[..],
+            [Match { pattern: Pattern::Dictionary { word, dictionary: "passwords", .. }, .. }] if word == "monkey"
+        ));
+        let (_, lower) = analyze("monkey", &[]);
+        assert_eq!(sequence[0].guesses, lower[0].guesses * 2.0);
+    }
+
+    #[test]
+    fn recognizes_reversed_and_l33t_words() {
+        assert!(patterns("drowssap").iter().any(|p| matches!(p, Pattern::Dictionary { reversed: true, .. })));
+        let (strength, sequence) = analyze("p4$$w0rd", &[]);
+        assert!(matches!(
+            &sequence[..],
+            [Match { pattern: Pattern::Dictionary { word, l33t: true, .. }, .. }] if word == "password"
+        ));
+        assert!(strength.score <= 1);
+    }
+
+    #[test]
+    fn recognizes_keyboard_walks() {
+        assert!(matches!(
+            patterns("zxcvbnm,./")[..],
+            [Pattern::Spatial { graph: "qwerty", turns: 1, .. }] | [Pattern::Dictionary { .. }, ..]
+        ));
+        assert!(matches!(
+            patterns("1qaz2wsx3edc")[..],
+            [Pattern::Spatial { .. }, ..] | [Pattern::Dictionary { .. }, ..]
+        ));
+        assert!(patterns("qwertgfds").iter().any(|p| matches!(p, Pattern::Spatial { turns, .. } if *turns >= 2)));
+        // A common password too, so check the keypad matcher itself.
+        let keypad: Vec<char> = "78963214".chars().collect();
+        assert!(spatial_matches(&keypad).iter().any(|m| m.end == 7
+            && matches!(m.pattern, Pattern::Spatial { graph: "keypad", turns: 4, .. })));
+        assert!(estimate_strength("sdfgh;lkj", &[]).score <= 2);
+    }
+
+    #[test]
+    fn recognizes_repeats() {
+        assert_eq!(
+            patterns("aaaaaaaaaa"),
+            vec![Pattern::Repeat { base: "a".to_string(), count: 10 }]
+        );
+        assert!(patterns("xK7#xK7#xK7#").iter().any(|p| matches!(p, Pattern::Repeat { count: 3, .. })));
+        assert!(estimate_strength("aaaaaaaaaa", &[]).score == 0);
+    }
+
+    #[test]
+    fn recognizes_sequences() {
+        assert!(patterns("abcdefghij").contains(&Pattern::Sequence { ascending: true }));
+        assert!(patterns("x97531").contains(&Pattern::Sequence { ascending: false }));
+        assert_eq!(estimate_strength("abcdefghij", &[]).score, 0);
+    }
+
+    #[test]
+    fn recognizes_dates_and_years() {
+        assert!(patterns("13.05.1987").contains(&Pattern::Date { year: 1987 }));
+        assert!(patterns("19870513").contains(&Pattern::Date { year: 1987 }));
+        assert!(patterns("xq1999").contains(&Pattern::Date { year: 1999 }));
+        assert!(estimate_strength("13.05.1987", &[]).score <= 1);
+    }
+
+    #[test]
+    fn user_inputs_count_as_common_words() {
+        let without = estimate_strength("paylanemars", &[]);
+        let (with, sequence) = analyze("paylanemars", &["paylane.com", "mars"]);
+        assert!(with.guesses_log10 < without.guesses_log10);
+        assert!(sequence.iter().any(|m| matches!(m.pattern, Pattern::Dictionary { dictionary: "user_inputs", .. })));
+    }
+
+    #[test]
+    fn sequence_covers_the_whole_password() {
+        for password in ["", "a", "Tr0ub4dor&3", "hello world 2024!", "ÄÖÜ€😀x"] {
+            let (_, sequence) = analyze(password, &[]);
+            let covered: String = sequence.iter().map(|m| m.token.as_str()).collect();
+            assert_eq!(covered, password);
+        }
+    }
+
+    #[test]
+    fn long_passwords_are_analyzed_in_bounded_time() {
+        let password = "a1B!".repeat(100);
+        let strength = estimate_strength(&password, &[]);
+        assert!(strength.guesses_log10.is_finite());
+    }
+
+    #[test]
+    fn crack_time_is_displayed_in_words() {
+        assert_eq!(display_time(0.5), "less than a second");
+        assert_eq!(display_time(1.0), "1 second");
+        assert_eq!(display_time(7200.0), "2 hours");
+        assert_eq!(display_time(1e12), "centuries");
+    }
+}
@@ -0,0 +1,354 @@
+//! Reads the unencrypted JSON export of Bitwarden (`bitwarden_export_*.json`).
+//! Logins become credentials, with their one time password going to the TOTP
+//! vault, cards become payment cards and secure notes become notes. Folders,
+//! or collections in an organization export, become groups.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::importers::{parse_totp, Imported, ImportedEntries, Importer, SkippedField};
+use crate::vault::entities::{Credential, Error, Expiry, Note, PaymentCard};
+
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+const CARD: u8 = 3;
+const IDENTITY: u8 = 4;
+
+#[derive(Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    collections: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    #[serde(rename = "type")]
+    item_type: u8,
+    name: String,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    collection_ids: Option<Vec<String>>,
+    revision_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    fields: Vec<BitwardenField>,
+    password_history: Option<Vec<Value>>,
+    login: Option<BitwardenLogin>,
+    card: Option<BitwardenCard>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenField {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenLogin {
+    #[serde(default)]
+    uris: Vec<BitwardenUri>,
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    fido2_credentials: Option<Vec<Value>>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenCard {
+    cardholder_name: Option<String>,
+    number: Option<String>,
+    exp_month: Option<String>,
+    exp_year: Option<String>,
+    code: Option<String>,
+}
+
+/// Bitwarden's unencrypted JSON export.
+pub struct BitwardenJson;
+
+impl Importer for BitwardenJson {
+    fn name(&self) -> &'static str {
+        "bitwarden"
+    }
+
+    fn description(&self) -> &'static str {
+        "Bitwarden JSON"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        serde_json::from_slice(content).is_ok_and(|json| is_bitwarden_export(&json))
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let json: Value = serde_json::from_slice(content)
+            .map_err(|e| Error::new(&format!("The export is not valid JSON: {}", e)))?;
+        parse_export(json)
+    }
+}
+
+/// Recognizes a Bitwarden export by the shape of its JSON.
+pub fn is_bitwarden_export(json: &Value) -> bool {
+    json.get("items").is_some_and(Value::is_array) && json.get("encrypted").is_some()
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl BitwardenItem {
+    /// Reports the parts of the item that passlane has no place for.
+    fn skip_extras(&self, skipped: &mut Vec<SkippedField>) {
+        for field in &self.fields {
+            let name = field.name.as_deref().unwrap_or("(unnamed)");
+            skipped.push(SkippedField::new(&self.name, &format!("custom field '{}'", name)));
+        }
+        if self.password_history.as_ref().is_some_and(|h| !h.is_empty()) {
+            skipped.push(SkippedField::new(&self.name, "password history"));
+        }
+    }
+
+    fn to_credential(&self, entries: &mut ImportedEntries, folder: Option<String>) {
+        let empty = BitwardenLogin {
+            uris: Vec::new(),
+            username: None,
+            password: None,
+            totp: None,
+            fido2_credentials: None,
+        };
+        let login = self.login.as_ref().unwrap_or(&empty);
+        let uris: Vec<&str> = login
+            .uris
+            .iter()
+            .filter_map(|uri| non_empty(&uri.uri))
+            .collect();
+        for (index, uri) in uris.iter().enumerate().skip(1) {
+            entries
+                .skipped
+                .push(SkippedField::new(&self.name, &format!("URI {} ({})", index + 1, uri)));
+        }
+        if login.fido2_credentials.as_ref().is_some_and(|p| !p.is_empty()) {
+            entries.skipped.push(SkippedField::new(&self.name, "passkey"));
+        }
+        let username = login.username.as_deref().unwrap_or("");
+        let mut credential = Credential::new(
+            None,
+            login.password.as_deref().unwrap_or(""),
+            uris.first().copied().unwrap_or(&self.name),
+            username,
+            non_empty(&self.notes),
+            self.revision_date,
+        );
+        if let Some(value) = non_empty(&login.totp) {
+            match parse_totp(value, &self.name, username) {
+                Ok(totp) => {
+                    credential = credential.with_totp_id(Some(*totp.id()));
+                    entries.totps.push(totp);
+                }
+                Err(e) => entries
+                    .skipped
+                    .push(SkippedField::new(&self.name, &format!("TOTP ({})", e.message))),
+            }
+        }
+        entries.credentials.push(Imported {
+            entry: credential,
+            folder,
+        });
+    }
+
+    fn to_payment(&self, entries: &mut ImportedEntries, folder: Option<String>) {
+        let Some(card) = &self.card else {
+            entries.skipped.push(SkippedField::new(&self.name, "card details"));
+            return;
+        };
+        let number = |value: &Option<String>| non_empty(value).and_then(|v| v.parse::<u32>().ok());
+        let expiry = Expiry {
+            month: number(&card.exp_month).unwrap_or(0),
+            year: number(&card.exp_year).unwrap_or(0),
+        };
+        entries.payments.push(Imported {
+            entry: PaymentCard::new(
+                None,
+                &self.name,
+                non_empty(&card.cardholder_name).unwrap_or(""),
+                non_empty(&card.number).unwrap_or(""),
+                non_empty(&card.code).unwrap_or(""),
+                expiry,
+                None,
+                None,
+                self.revision_date,
+            ),
+            folder,
+        });
+        if non_empty(&self.notes).is_some() {
+            entries.skipped.push(SkippedField::new(&self.name, "notes"));
+        }
+    }
+
+    fn to_note(&self, entries: &mut ImportedEntries, folder: Option<String>) {
+        entries.notes.push(Imported {
+            entry: Note::new(
+                None,
+                &self.name,
+                self.notes.as_deref().unwrap_or(""),
+                self.revision_date,
+            ),
+            folder,
+        });
+    }
+}
+
+/// Reads a Bitwarden JSON export.
+pub fn parse_export(json: Value) -> Result<ImportedEntries, Error> {
+    let export: BitwardenExport = serde_json::from_value(json)
+        .map_err(|e| Error::new(&format!("Invalid Bitwarden export: {}", e)))?;
+    if export.encrypted {
+        return Err(Error::new(
+            "Encrypted Bitwarden exports are not supported, export again in the .json format",
+        ));
+    }
+    let folder_names: HashMap<&str, &str> = export
+        .folders
+        .iter()
+        .chain(export.collections.iter())
+        .map(|folder| (folder.id.as_str(), folder.name.as_str()))
+        .collect();
+
+    let mut entries = ImportedEntries::default();
+    for item in &export.items {
+        let folder = item
+            .folder_id
+            .iter()
+            .chain(item.collection_ids.iter().flatten())
+            .find_map(|id| folder_names.get(id.as_str()))
+            .map(|name| name.to_string());
+        match item.item_type {
+            LOGIN => item.to_credential(&mut entries, folder),
+            SECURE_NOTE => item.to_note(&mut entries, folder),
+            CARD => item.to_payment(&mut entries, folder),
+            IDENTITY => {
+                entries.skipped.push(SkippedField::new(&item.name, "identity"));
+                continue;
+            }
+            other => {
+                entries
+                    .skipped
+                    .push(SkippedField::new(&item.name, &format!("item of type {}", other)));
+                continue;
+            }
+        }
+        item.skip_extras(&mut entries.skipped);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export() -> Value {
+        json!({
+            "encrypted": false,
+            "folders": [{ "id": "f1", "name": "Work" }],
+            "items": [
+                {
+                    "id": "i1", "type": 1, "name": "GitHub", "folderId": "f1",
+                    "notes": "work account",
+                    "revisionDate": "2024-03-01T10:00:00.000Z",
+                    "fields": [{ "name": "Recovery email", "value": "x@example.com", "type": 0 }],
+                    "login": {
+                        "uris": [{ "match": null, "uri": "https://github.com" },
+                                 { "match": null, "uri": "https://gist.github.com" }],
+                        "username": "alice", "password": "s3cret",
+                        "totp": "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub"
+                    }
+                },
+                {
+                    "id": "i2", "type": 1, "name": "Router", "folderId": null,
+                    "login": { "uris": [], "username": "admin", "password": "pw", "totp": "JBSWY3DPEHPK3PXP" }
+                },
+                {
+                    "id": "i3", "type": 3, "name": "Visa", "folderId": "f1",
+                    "card": { "cardholderName": "Alice Smith", "brand": "Visa", "number": "4111111111111111",
+                              "expMonth": "7", "expYear": "2027", "code": "123" }
+                },
+                { "id": "i4", "type": 2, "name": "Wifi", "notes": "password: hunter2", "secureNote": { "type": 0 } },
+                { "id": "i5", "type": 4, "name": "Me", "identity": { "firstName": "Alice" } }
+            ]
+        })
+    }
+
+    #[test]
+    fn maps_items_to_entities() {
+        let entries = parse_export(export()).unwrap();
+
+        assert_eq!(entries.credentials.len(), 2);
+        let github = &entries.credentials[0];
+        assert_eq!(github.folder.as_deref(), Some("Work"));
+        assert_eq!(github.entry.service(), "https://github.com");
+        assert_eq!(github.entry.username(), "alice");
+        assert_eq!(github.entry.password(), "s3cret");
+        assert_eq!(github.entry.note(), Some("work account"));
+        let router = &entries.credentials[1];
+        assert_eq!(router.folder, None);
+        assert_eq!(router.entry.service(), "Router");
+
+        assert_eq!(entries.totps.len(), 2);
+        assert_eq!(github.entry.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.totps[0].label(), "GitHub:alice");
+        assert_eq!(router.entry.totp_id(), Some(entries.totps[1].id()));
+        assert_eq!(entries.totps[1].label(), "Router:admin");
+
+        let visa = &entries.payments[0];
+        assert_eq!(visa.folder.as_deref(), Some("Work"));
+        assert_eq!(visa.entry.name_on_card(), "Alice Smith");
+        assert_eq!(visa.entry.expiry_str(), "7/2027");
+        assert_eq!(visa.entry.cvv(), "123");
+
+        assert_eq!(entries.notes[0].entry.title(), "Wifi");
+        assert_eq!(entries.notes[0].entry.content(), "password: hunter2");
+        assert_eq!(entries.folders(), vec![Some("Work".to_string()), None]);
+    }
+
+    #[test]
+    fn lists_skipped_fields() {
+        let skipped = parse_export(export()).unwrap().skipped;
+        assert_eq!(
+            skipped,
+            vec![
+                SkippedField::new("GitHub", "URI 2 (https://gist.github.com)"),
+                SkippedField::new("GitHub", "custom field 'Recovery email'"),
+                SkippedField::new("Me", "identity"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_encrypted_exports() {
+        let json = json!({ "encrypted": true, "encKeyValidation_DO_NOT_EDIT": "x", "items": [] });
+        assert!(is_bitwarden_export(&json));
+        let error = parse_export(json).err().unwrap();
+        assert!(error.message.contains("Encrypted"));
+        assert!(!is_bitwarden_export(&json!({ "services": [] })));
+    }
+}
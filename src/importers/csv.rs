@@ -0,0 +1,669 @@
+//! Reads the CSV exports of browsers and password managers. Each format is
+//! recognized by its header row. Logins become credentials, with the one
+//! time password columns going to the TOTP vault, and the folder columns
+//! become groups.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use csv::{ReaderBuilder, StringRecord};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::importers::{parse_totp, Imported, ImportedEntries, Importer, InvalidRow, SkippedField};
+use crate::store::{CSVCredential, CSVPaymentCard, CSVSecureNote, CSV_TAG_SEPARATOR};
+use crate::vault::entities::{Address, Credential, Error, Expiry, Note, PaymentCard};
+
+/// The URL LastPass gives its secure notes.
+const LASTPASS_NOTE_URL: &str = "http://sn";
+
+/// The CSV importers, the most specific header first.
+pub fn importers() -> Vec<Box<dyn Importer>> {
+    vec![
+        Box::new(FirefoxCsv),
+        Box::new(LastPassCsv),
+        Box::new(DashlaneCsv),
+        Box::new(KeePassXcCsv),
+        Box::new(ChromeCsv),
+        Box::new(PasslaneCsv),
+        Box::new(PasslaneCardsCsv),
+        Box::new(PasslaneNotesCsv),
+    ]
+}
+
+/// The lowercased column names of the header row.
+fn header(content: &[u8]) -> Option<Vec<String>> {
+    let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+    let mut reader = ReaderBuilder::new().flexible(true).from_reader(content);
+    let header = reader.headers().ok()?;
+    Some(header.iter().map(|column| column.trim().to_lowercase()).collect())
+}
+
+fn has_columns(content: &[u8], columns: &[&str]) -> bool {
+    header(content).is_some_and(|header| columns.iter().all(|c| header.iter().any(|h| h == c)))
+}
+
+/// The rows of a CSV export, with the columns looked up by their lowercased
+/// name.
+struct CsvTable {
+    columns: HashMap<String, usize>,
+    rows: Vec<StringRecord>,
+}
+
+impl CsvTable {
+    fn read(content: &[u8], format: &str) -> Result<CsvTable, Error> {
+        let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+        let mut reader = ReaderBuilder::new().flexible(true).from_reader(content);
+        let columns = reader
+            .headers()
+            .map_err(|e| Error::new(&format!("Invalid {} CSV: {}", format, e)))?
+            .iter()
+            .enumerate()
+            .map(|(index, column)| (column.trim().to_lowercase(), index))
+            .collect();
+        let rows = reader
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::new(&format!("Invalid {} CSV: {}", format, e)))?;
+        Ok(CsvTable { columns, rows })
+    }
+
+    fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        self.rows.iter().map(|record| Row {
+            columns: &self.columns,
+            record,
+        })
+    }
+}
+
+struct Row<'a> {
+    columns: &'a HashMap<String, usize>,
+    record: &'a StringRecord,
+}
+
+impl Row<'_> {
+    /// The value of a column, when it is not blank.
+    fn get(&self, column: &str) -> Option<&str> {
+        self.columns
+            .get(column)
+            .and_then(|&index| self.record.get(index))
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    fn text(&self, column: &str) -> &str {
+        self.get(column).unwrap_or("")
+    }
+
+    fn is_blank(&self) -> bool {
+        self.record.iter().all(|value| value.trim().is_empty())
+    }
+}
+
+/// Adds a credential, linking the authorizer in `totp` when there is one.
+/// `name` identifies the entry in the list of skipped fields.
+fn push_credential(
+    entries: &mut ImportedEntries,
+    name: &str,
+    mut credential: Credential,
+    totp: Option<&str>,
+    folder: Option<&str>,
+) {
+    if let Some(value) = totp {
+        match parse_totp(value, name, credential.username()) {
+            Ok(totp) => {
+                credential = credential.with_totp_id(Some(*totp.id()));
+                entries.totps.push(totp);
+            }
+            Err(e) => entries
+                .skipped
+                .push(SkippedField::new(name, &format!("TOTP ({})", e.message))),
+        }
+    }
+    entries.credentials.push(Imported {
+        entry: credential,
+        folder: folder.map(str::to_string),
+    });
+}
+
+/// Reads the rows of one of passlane's own layouts. A row that cannot be
+/// read is reported with its line number and the others are still imported.
+/// Each of `required` names a column, or alternatives separated by `|`.
+fn read_passlane_rows<T: DeserializeOwned>(
+    content: &[u8],
+    required: &[&str],
+    mut add: impl FnMut(T, &mut ImportedEntries) -> Result<(), String>,
+) -> Result<ImportedEntries, Error> {
+    let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+    let mut reader = ReaderBuilder::new().from_reader(content);
+    let headers = reader.headers()?.clone();
+    for column in required {
+        if !column.split('|').any(|name| headers.iter().any(|h| h == name)) {
+            return Err(Error::new(&format!(
+                "The CSV has no '{}' column",
+                column.replace('|', "' or '")
+            )));
+        }
+    }
+    let mut entries = ImportedEntries::default();
+    for record in reader.records() {
+        let result = record.map_err(|e| (e.position().map_or(0, |p| p.line()), e.to_string()));
+        let result = result.and_then(|record| {
+            let line = record.position().map_or(0, |p| p.line());
+            record
+                .deserialize::<T>(Some(&headers))
+                .map_err(|e| match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => e.to_string(),
+                })
+                .and_then(|row| add(row, &mut entries))
+                .map_err(|message| (line, message))
+        });
+        if let Err((line, message)) = result {
+            entries.invalid_rows.push(InvalidRow { line, message });
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_uuid(value: Option<&str>) -> Option<Uuid> {
+    value.map(str::trim).and_then(|s| Uuid::parse_str(s).ok())
+}
+
+fn parse_tags(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or("")
+        .split(CSV_TAG_SEPARATOR)
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// passlane's own `export` layout, which is also accepted with the `url` and
+/// `guid` columns of Firefox.
+pub struct PasslaneCsv;
+
+impl Importer for PasslaneCsv {
+    fn name(&self) -> &'static str {
+        "passlane"
+    }
+
+    fn description(&self) -> &'static str {
+        "passlane CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["service", "username", "password"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        read_passlane_rows(content, &["service|url", "username", "password"], |row: CSVCredential, entries| {
+            if row.service.is_empty() && row.username.is_empty() && row.password.is_empty() {
+                return Ok(());
+            }
+            let totp_id = match row.totp_id.as_deref() {
+                Some(id) => Some(
+                    Uuid::parse_str(id.trim()).map_err(|_| format!("invalid totp_id '{}'", id))?,
+                ),
+                None => None,
+            };
+            let credential = Credential::new(
+                parse_uuid(row.uuid.as_deref()).as_ref(),
+                &row.password,
+                &row.service,
+                &row.username,
+                row.note.as_deref(),
+                row.last_modified,
+            )
+            .with_password_rules(row.password_rules.as_deref())
+            .with_totp_id(totp_id)
+            .with_tags(parse_tags(row.tags.as_deref()));
+            entries.credentials.push(Imported {
+                entry: credential,
+                folder: None,
+            });
+            Ok(())
+        })
+    }
+}
+
+/// The payment cards written by `export -p`.
+pub struct PasslaneCardsCsv;
+
+impl Importer for PasslaneCardsCsv {
+    fn name(&self) -> &'static str {
+        "passlane-cards"
+    }
+
+    fn description(&self) -> &'static str {
+        "passlane payment cards CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["name", "name_on_card", "number", "cvv", "expiry"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let required = ["name", "name_on_card", "number", "cvv", "expiry", "color", "billing_address"];
+        read_passlane_rows(content, &required, |row: CSVPaymentCard, entries| {
+            let expiry: Expiry = row
+                .expiry
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid expiry '{}': {}", row.expiry, e))?;
+            let address = match row.billing_address.trim() {
+                "" => None,
+                address => {
+                    let address = address
+                        .parse::<Address>()
+                        .map_err(|e| format!("invalid billing address '{}': {}", address, e))?;
+                    Some(match parse_uuid(row.billing_address_id.as_deref()) {
+                        Some(id) => address.with_id(id),
+                        None => address,
+                    })
+                }
+            };
+            let card = PaymentCard::new(
+                parse_uuid(row.uuid.as_deref()).as_ref(),
+                &row.name,
+                &row.name_on_card,
+                &row.number,
+                &row.cvv,
+                expiry,
+                Some(row.color.as_str()).filter(|color| !color.is_empty()),
+                address.as_ref(),
+                row.last_modified,
+            )
+            .with_tags(parse_tags(row.tags.as_deref()));
+            entries.payments.push(Imported {
+                entry: card,
+                folder: None,
+            });
+            Ok(())
+        })
+    }
+}
+
+/// The secure notes written by `export -n`.
+pub struct PasslaneNotesCsv;
+
+impl Importer for PasslaneNotesCsv {
+    fn name(&self) -> &'static str {
+        "passlane-notes"
+    }
+
+    fn description(&self) -> &'static str {
+        "passlane secure notes CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        header(content).is_some_and(|header| {
+            header.iter().any(|h| h == "title")
+                && header.iter().any(|h| h == "note")
+                && !header.iter().any(|h| h == "password")
+        })
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        read_passlane_rows(content, &["title", "note"], |row: CSVSecureNote, entries| {
+            let note = Note::new(
+                parse_uuid(row.uuid.as_deref()).as_ref(),
+                &row.title,
+                &row.note,
+                row.last_modified,
+            )
+            .with_tags(parse_tags(row.tags.as_deref()));
+            entries.notes.push(Imported {
+                entry: note,
+                folder: None,
+            });
+            Ok(())
+        })
+    }
+}
+
+/// Firefox's `logins.csv`. The `guid` column becomes the UUID and
+/// `timePasswordChanged` the modification time.
+pub struct FirefoxCsv;
+
+impl Importer for FirefoxCsv {
+    fn name(&self) -> &'static str {
+        "firefox"
+    }
+
+    fn description(&self) -> &'static str {
+        "Firefox CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["url", "username", "password", "httprealm", "formactionorigin"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let table = CsvTable::read(content, self.description())?;
+        let mut entries = ImportedEntries::default();
+        for row in table.rows().filter(|row| !row.is_blank()) {
+            let uuid = row
+                .get("guid")
+                .map(|guid| guid.trim_matches(|c| c == '{' || c == '}'))
+                .and_then(|guid| Uuid::parse_str(guid).ok());
+            let changed = row
+                .get("timepasswordchanged")
+                .and_then(|ms| ms.trim().parse::<i64>().ok())
+                .and_then(DateTime::from_timestamp_millis);
+            let credential = Credential::new(
+                uuid.as_ref(),
+                row.text("password"),
+                row.text("url"),
+                row.text("username"),
+                None,
+                changed,
+            );
+            push_credential(&mut entries, row.text("url"), credential, None, None);
+        }
+        Ok(entries)
+    }
+}
+
+/// The passwords CSV of Chrome and other Chromium based browsers.
+pub struct ChromeCsv;
+
+impl Importer for ChromeCsv {
+    fn name(&self) -> &'static str {
+        "chrome"
+    }
+
+    fn description(&self) -> &'static str {
+        "Chrome CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        header(content).is_some_and(|header| {
+            let known = ["name", "url", "username", "password", "note"];
+            header.len() >= 4
+                && header.iter().all(|column| known.contains(&column.as_str()))
+                && known[..4].iter().all(|c| header.iter().any(|h| h == c))
+        })
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let table = CsvTable::read(content, self.description())?;
+        let mut entries = ImportedEntries::default();
+        for row in table.rows().filter(|row| !row.is_blank()) {
+            let name = row.get("name").or(row.get("url")).unwrap_or("");
+            let credential = Credential::new(
+                None,
+                row.text("password"),
+                row.get("url").unwrap_or(name),
+                row.text("username"),
+                row.get("note"),
+                None,
+            );
+            push_credential(&mut entries, name, credential, None, None);
+        }
+        Ok(entries)
+    }
+}
+
+/// LastPass's CSV export. Rows with the URL `http://sn` are secure notes, the
+/// `totp` column holds the authorizer's secret and `grouping` the folder.
+pub struct LastPassCsv;
+
+impl Importer for LastPassCsv {
+    fn name(&self) -> &'static str {
+        "lastpass"
+    }
+
+    fn description(&self) -> &'static str {
+        "LastPass CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["url", "username", "password", "extra", "name", "grouping"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let table = CsvTable::read(content, self.description())?;
+        let mut entries = ImportedEntries::default();
+        for row in table.rows().filter(|row| !row.is_blank()) {
+            let name = row.get("name").or(row.get("url")).unwrap_or("");
+            let folder = row.get("grouping");
+            if row.get("url") == Some(LASTPASS_NOTE_URL) {
+                entries.notes.push(Imported {
+                    entry: Note::new(None, name, row.text("extra"), None),
+                    folder: folder.map(str::to_string),
+                });
+                continue;
+            }
+            let credential = Credential::new(
+                None,
+                row.text("password"),
+                row.get("url").unwrap_or(name),
+                row.text("username"),
+                row.get("extra"),
+                None,
+            );
+            push_credential(&mut entries, name, credential, row.get("totp"), folder);
+        }
+        Ok(entries)
+    }
+}
+
+/// Dashlane's `credentials.csv`. The `otpSecret` or `otpUrl` column holds
+/// the authorizer and `category` the folder. Only the first of the three
+/// username columns is kept.
+pub struct DashlaneCsv;
+
+impl Importer for DashlaneCsv {
+    fn name(&self) -> &'static str {
+        "dashlane"
+    }
+
+    fn description(&self) -> &'static str {
+        "Dashlane CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["username", "username2", "title", "password", "url"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let table = CsvTable::read(content, self.description())?;
+        let mut entries = ImportedEntries::default();
+        for row in table.rows().filter(|row| !row.is_blank()) {
+            let name = row.get("title").or(row.get("url")).unwrap_or("");
+            for column in ["username2", "username3"] {
+                if let Some(value) = row.get(column) {
+                    entries
+                        .skipped
+                        .push(SkippedField::new(name, &format!("{} ({})", column, value)));
+                }
+            }
+            let credential = Credential::new(
+                None,
+                row.text("password"),
+                row.get("url").unwrap_or(name),
+                row.text("username"),
+                row.get("note"),
+                None,
+            );
+            let totp = row.get("otpurl").or(row.get("otpsecret"));
+            push_credential(&mut entries, name, credential, totp, row.get("category"));
+        }
+        Ok(entries)
+    }
+}
+
+/// KeePassXC's CSV export. The `Group` column is the path of the entry's
+/// group below the root group and the `TOTP` column an `otpauth://` URL.
+pub struct KeePassXcCsv;
+
+impl Importer for KeePassXcCsv {
+    fn name(&self) -> &'static str {
+        "keepassxc"
+    }
+
+    fn description(&self) -> &'static str {
+        "KeePassXC CSV"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        has_columns(content, &["group", "title", "username", "password", "url", "notes"])
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        let table = CsvTable::read(content, self.description())?;
+        let mut entries = ImportedEntries::default();
+        for row in table.rows().filter(|row| !row.is_blank()) {
+            let name = row.get("title").or(row.get("url")).unwrap_or("");
+            let folder = row.get("group").and_then(|group| {
+                let group = group.trim();
+                let below_root = match group.split_once('/') {
+                    Some(("Root", rest)) => rest,
+                    _ if group == "Root" => "",
+                    _ => group,
+                };
+                Some(below_root).filter(|g| !g.is_empty())
+            });
+            let modified = row
+                .get("last modified")
+                .and_then(|value| DateTime::parse_from_rfc3339(value.trim()).ok())
+                .map(|time| time.with_timezone(&Utc));
+            let credential = Credential::new(
+                None,
+                row.text("password"),
+                row.get("url").unwrap_or(name),
+                row.text("username"),
+                row.get("notes"),
+                modified,
+            );
+            push_credential(&mut entries, name, credential, row.get("totp"), folder);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_rows_that_fail_to_parse() {
+        let entries = PasslaneCardsCsv.parse(include_bytes!("fixtures/passlane-cards.csv")).unwrap();
+        assert_eq!(entries.payments.len(), 1);
+        let visa = &entries.payments[0].entry;
+        assert_eq!(visa.expiry_str(), "7/2027");
+        assert_eq!(visa.color().map(String::as_str), Some("blue"));
+        assert_eq!(visa.billing_address().unwrap().city(), "Springfield");
+        assert_eq!(
+            entries.invalid_rows,
+            vec![
+                InvalidRow {
+                    line: 3,
+                    message: "invalid expiry '13-2027': Invalid format. Expected MM/YYYY".to_string()
+                },
+                InvalidRow {
+                    line: 4,
+                    message: "invalid billing address 'Mannerheimintie 1, Helsinki': Invalid format. Expected Street, Zip, City, [State,] Country".to_string()
+                },
+            ]
+        );
+
+        let error = PasslaneNotesCsv.parse(b"name,content\nx,y\n").err().unwrap();
+        assert_eq!(error.message, "The CSV has no 'title' column");
+        let entries = PasslaneCsv.parse(b"service,username,password\na,b\n").unwrap();
+        assert_eq!(entries.invalid_rows[0].line, 2);
+    }
+
+    #[test]
+    fn reads_old_note_exports() {
+        let entries = PasslaneNotesCsv.parse(include_bytes!("fixtures/passlane-notes.csv")).unwrap();
+        assert_eq!(entries.notes.len(), 2);
+        assert_eq!(entries.notes[1].entry.content(), "front: 1234\nback: 4321");
+        assert!(entries.invalid_rows.is_empty());
+    }
+
+    #[test]
+    fn reads_firefox_logins() {
+        let entries = FirefoxCsv.parse(include_bytes!("fixtures/firefox.csv")).unwrap();
+        assert_eq!(entries.credentials.len(), 2);
+        let login = &entries.credentials[0].entry;
+        assert_eq!(login.service(), "https://example.com");
+        assert_eq!(login.username(), "alice");
+        assert_eq!(login.password(), "hunter2");
+        assert_eq!(login.uuid().to_string(), "e48714ba-66e8-4a2d-8f09-9caff063fbbf");
+        assert_eq!(login.last_modified().timestamp_millis(), 1723657076271);
+        assert_eq!(entries.credentials[1].entry.uuid().get_version_num(), 4);
+    }
+
+    #[test]
+    fn reads_chrome_passwords() {
+        let entries = ChromeCsv.parse(include_bytes!("fixtures/chrome.csv")).unwrap();
+        assert_eq!(entries.credentials.len(), 2);
+        let login = &entries.credentials[0].entry;
+        assert_eq!(login.service(), "https://accounts.google.com/");
+        assert_eq!(login.username(), "alice@gmail.com");
+        assert_eq!(login.note(), Some("personal"));
+        assert_eq!(entries.credentials[1].entry.note(), None);
+        assert!(entries.totps.is_empty());
+    }
+
+    #[test]
+    fn reads_lastpass_logins_notes_and_authorizers() {
+        let entries = LastPassCsv.parse(include_bytes!("fixtures/lastpass.csv")).unwrap();
+        assert_eq!(entries.credentials.len(), 2);
+        let github = &entries.credentials[0];
+        assert_eq!(github.folder.as_deref(), Some("Work"));
+        assert_eq!(github.entry.service(), "https://github.com");
+        assert_eq!(github.entry.note(), Some("work account"));
+        assert_eq!(github.entry.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.totps[0].label(), "GitHub:alice");
+        assert_eq!(entries.credentials[1].folder, None);
+        assert_eq!(entries.credentials[1].entry.totp_id(), None);
+
+        assert_eq!(entries.notes.len(), 1);
+        assert_eq!(entries.notes[0].entry.title(), "Wifi");
+        assert_eq!(entries.notes[0].entry.content(), "password: hunter2");
+        assert_eq!(entries.notes[0].folder.as_deref(), Some("Home"));
+    }
+
+    #[test]
+    fn reads_dashlane_credentials() {
+        let entries = DashlaneCsv.parse(include_bytes!("fixtures/dashlane.csv")).unwrap();
+        assert_eq!(entries.credentials.len(), 2);
+        let github = &entries.credentials[0];
+        assert_eq!(github.entry.service(), "https://github.com");
+        assert_eq!(github.folder.as_deref(), Some("Work"));
+        assert_eq!(github.entry.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.credentials[1].entry.service(), "Router");
+        assert_eq!(
+            entries.skipped,
+            vec![SkippedField::new("GitHub", "username2 (alice@example.com)")]
+        );
+    }
+
+    #[test]
+    fn reads_keepassxc_entries() {
+        let entries = KeePassXcCsv.parse(include_bytes!("fixtures/keepassxc.csv")).unwrap();
+        assert_eq!(entries.credentials.len(), 3);
+        let github = &entries.credentials[0];
+        assert_eq!(github.folder.as_deref(), Some("Work/Dev"));
+        assert_eq!(github.entry.note(), Some("line one\nline two"));
+        assert_eq!(github.entry.last_modified().to_rfc3339(), "2024-03-01T10:00:00+00:00");
+        assert_eq!(github.entry.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.credentials[1].folder, None);
+        assert_eq!(entries.credentials[2].folder.as_deref(), Some("Other"));
+        assert_eq!(entries.skipped, vec![]);
+    }
+
+    #[test]
+    fn reports_invalid_authorizers() {
+        let content = b"url,username,password,totp,extra,name,grouping,fav\n\
+                        https://x.com,bob,pw,otpauth://totp/X:bob?issuer=X,,X,,0\n";
+        let entries = LastPassCsv.parse(content).unwrap();
+        assert_eq!(entries.credentials.len(), 1);
+        assert_eq!(entries.credentials[0].entry.totp_id(), None);
+        assert_eq!(entries.skipped.len(), 1);
+        assert!(entries.skipped[0].field.starts_with("TOTP"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Reads the entries of another KeePass file, such as one made with
+//! `export --kdbx` or shared by another team. Logins keep their link to an
+//! authorizer, or are linked to the one time password of their own entry,
+//! as KeePassXC stores it.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::importers::{Imported, ImportedEntries, SkippedField};
+use crate::vault::vault_trait::Vault;
+
+/// The credentials, payment cards, notes and authorizers of `source`, all in
+/// `group`. With `new_uuids`, every entry gets a fresh UUID and the links
+/// between them follow; otherwise the entries keep their UUIDs, so that
+/// importing the same file again finds them in the vault.
+pub fn read_kdbx(source: &dyn Vault, group: Option<&str>, new_uuids: bool) -> ImportedEntries {
+    let totps = source.find_totp(None);
+    let totp_ids: HashMap<Uuid, Uuid> = totps
+        .iter()
+        .map(|totp| (*totp.id(), if new_uuids { Uuid::new_v4() } else { *totp.id() }))
+        .collect();
+    let new_id = |id: &Uuid| if new_uuids { Uuid::new_v4() } else { *id };
+    let folder = group.map(str::to_string);
+
+    let credentials = source
+        .grep(None)
+        .into_iter()
+        .map(|credential| {
+            let linked = credential
+                .totp_id()
+                .or(Some(credential.uuid()))
+                .and_then(|id| totp_ids.get(id))
+                .copied();
+            let uuid = new_id(credential.uuid());
+            Imported {
+                entry: credential.with_totp_id(linked).with_uuid(uuid),
+                folder: folder.clone(),
+            }
+        })
+        .collect();
+    let payments = source
+        .find_payments()
+        .into_iter()
+        .map(|card| {
+            let id = new_id(card.id());
+            Imported { entry: card.with_id(id), folder: folder.clone() }
+        })
+        .collect();
+    let notes = source
+        .find_notes()
+        .into_iter()
+        .map(|note| {
+            let id = new_id(&note.id());
+            Imported { entry: note.with_id(id), folder: folder.clone() }
+        })
+        .collect();
+    let totps = totps
+        .into_iter()
+        .map(|totp| {
+            let id = totp_ids[totp.id()];
+            totp.with_id(id)
+        })
+        .collect();
+    let skipped = source
+        .find_recovery_codes(None)
+        .iter()
+        .map(|codes| SkippedField::new(codes.service(), "recovery codes"))
+        .collect();
+
+    ImportedEntries {
+        credentials,
+        payments,
+        notes,
+        totps,
+        skipped,
+        ..ImportedEntries::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::entities::{Credential, Note, RecoveryCodes, Totp};
+    use crate::vault::keepass_vault::KeepassVault;
+    use crate::vault::vault_trait::{NoteVault, PasswordVault, RecoveryCodesVault, TotpVault};
+
+    fn source(dir: &tempfile::TempDir) -> (KeepassVault, Credential, Totp) {
+        let path = dir.path().join("shared.kdbx");
+        let mut source = KeepassVault::new(path.to_str().unwrap(), "shared-pw", None).unwrap();
+        let url = "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub";
+        let totp = Totp::new(None, url, "GitHub:alice", "GitHub", "JBSWY3DPEHPK3PXP", "SHA1", 30, 6, None);
+        source.save_totps(std::slice::from_ref(&totp)).unwrap();
+        let github = Credential::new(None, "pw", "github.com", "alice", None, None)
+            .with_totp_id(Some(*totp.id()));
+        source.save_credentials(&vec![github.clone()]).unwrap();
+        source.save_note(&Note::new(None, "Wifi", "hunter2", None)).unwrap();
+        let codes = RecoveryCodes::new(None, "github.com", RecoveryCodes::parse_codes("abcd-1234").unwrap(), None);
+        source.save_recovery_codes(&codes).unwrap();
+        (source, github, totp)
+    }
+
+    #[test]
+    fn keeps_uuids_and_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, github, totp) = source(&dir);
+        let entries = read_kdbx(&source, Some("Partner"), false);
+
+        assert_eq!(entries.credentials.len(), 1);
+        let credential = &entries.credentials[0];
+        assert_eq!(credential.entry.uuid(), github.uuid());
+        assert_eq!(credential.entry.totp_id(), Some(totp.id()));
+        assert_eq!(credential.folder.as_deref(), Some("Partner"));
+        assert_eq!(entries.notes[0].folder.as_deref(), Some("Partner"));
+        assert_eq!(entries.totps.len(), 1);
+        assert_eq!(entries.totps[0].id(), totp.id());
+        assert_eq!(entries.skipped, vec![SkippedField::new("github.com", "recovery codes")]);
+    }
+
+    #[test]
+    fn new_uuids_keep_the_links_between_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, github, totp) = source(&dir);
+        let entries = read_kdbx(&source, None, true);
+
+        let credential = &entries.credentials[0].entry;
+        assert_ne!(credential.uuid(), github.uuid());
+        assert_ne!(entries.totps[0].id(), totp.id());
+        assert_eq!(credential.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.credentials[0].folder, None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Readers for the exports of other password managers. Each turns an export
+//! into passlane entities, keeping the folder each entry was in and noting
+//! the fields it has no place for.
+
+pub mod bitwarden;
+pub mod csv;
+pub mod kdbx;
+pub mod onepassword;
+pub mod zip;
+
+use log::debug;
+
+use crate::otp_import::{build_totp, parse_otpauth_url, OtpKind};
+use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
+
+/// Reads one export format. Formats are recognized by their content, or
+/// named with `--format`.
+pub trait Importer {
+    /// The name given to `--format`.
+    fn name(&self) -> &'static str;
+    /// The format as shown to the user.
+    fn description(&self) -> &'static str;
+    fn detect(&self, content: &[u8]) -> bool;
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error>;
+}
+
+/// All importers, in the order they are tried when detecting the format.
+pub fn importers() -> Vec<Box<dyn Importer>> {
+    let mut importers: Vec<Box<dyn Importer>> =
+        vec![Box::new(bitwarden::BitwardenJson), Box::new(onepassword::OnePux)];
+    importers.extend(csv::importers());
+    importers
+}
+
+/// The names of the importers in `importers`, for `--format`.
+pub fn format_names(importers: &[Box<dyn Importer>]) -> Vec<&'static str> {
+    importers.iter().map(|importer| importer.name()).collect()
+}
+
+/// The authorizer of a one time password field: an `otpauth://` URL, a
+/// `steam://` secret or a bare base32 secret.
+pub(crate) fn parse_totp(value: &str, issuer: &str, account: &str) -> Result<Totp, Error> {
+    let value = value.trim();
+    if value.starts_with("otpauth://") {
+        parse_otpauth_url(value)
+    } else if let Some(secret) = value.strip_prefix("steam://") {
+        build_totp(issuer, account, secret, "SHA1", 5, 30, OtpKind::Steam)
+    } else {
+        build_totp(issuer, account, value, "SHA1", 6, 30, OtpKind::Totp)
+    }
+}
+
+/// An entry and the folder it was in, which becomes a group in the vault.
+#[derive(Clone)]
+pub struct Imported<T> {
+    pub entry: T,
+    pub folder: Option<String>,
+}
+
+/// A field of an exported entry that was not imported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkippedField {
+    pub entry: String,
+    pub field: String,
+}
+
+impl SkippedField {
+    pub fn new(entry: &str, field: &str) -> Self {
+        SkippedField {
+            entry: entry.to_string(),
+            field: field.to_string(),
+        }
+    }
+}
+
+/// A row of a CSV export that could not be read, by the line it starts on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidRow {
+    pub line: u64,
+    pub message: String,
+}
+
+/// Everything read from an export. Credentials with a one time password are
+/// linked to their authorizer in `totps`.
+#[derive(Default)]
+pub struct ImportedEntries {
+    pub credentials: Vec<Imported<Credential>>,
+    pub payments: Vec<Imported<PaymentCard>>,
+    pub notes: Vec<Imported<Note>>,
+    pub totps: Vec<Totp>,
+    pub skipped: Vec<SkippedField>,
+    pub invalid_rows: Vec<InvalidRow>,
+}
+
+impl ImportedEntries {
+    /// The folders of all entries, in the order they first appear.
+    pub fn folders(&self) -> Vec<Option<String>> {
+        let mut folders: Vec<Option<String>> = Vec::new();
+        let all = self
+            .credentials
+            .iter()
+            .map(|c| &c.folder)
+            .chain(self.payments.iter().map(|p| &p.folder))
+            .chain(self.notes.iter().map(|n| &n.folder));
+        for folder in all {
+            if !folders.contains(folder) {
+                folders.push(folder.clone());
+            }
+        }
+        folders
+    }
+}
+
+/// Reads an export with the importer called `format`, or with the first of
+/// `importers` that recognizes its content.
+pub fn parse_export(
+    content: &[u8],
+    format: Option<&str>,
+    importers: Vec<Box<dyn Importer>>,
+) -> Result<ImportedEntries, Error> {
+    let names = format_names(&importers).join(", ");
+    let importer = match format {
+        Some(format) => importers
+            .into_iter()
+            .find(|importer| importer.name().eq_ignore_ascii_case(format))
+            .ok_or_else(|| {
+                Error::new(&format!("Unknown export format '{}', expected one of {}", format, names))
+            })?,
+        None => importers
+            .into_iter()
+            .find(|importer| importer.detect(content))
+            .ok_or_else(|| {
+                Error::new(&format!(
+                    "Unknown export format, use --format to name it ({})",
+                    names
+                ))
+            })?,
+    };
+    debug!("reading the export as {}", importer.description());
+    importer.parse(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(content: &[u8]) -> Option<&'static str> {
+        importers()
+            .into_iter()
+            .find(|importer| importer.detect(content))
+            .map(|importer| importer.name())
+    }
+
+    #[test]
+    fn detects_each_format() {
+        assert_eq!(detected(include_bytes!("fixtures/bitwarden.json")), Some("bitwarden"));
+        assert_eq!(detected(include_bytes!("fixtures/export.1pux")), Some("1pux"));
+        assert_eq!(detected(include_bytes!("fixtures/passlane.csv")), Some("passlane"));
+        assert_eq!(detected(include_bytes!("fixtures/firefox.csv")), Some("firefox"));
+        assert_eq!(detected(include_bytes!("fixtures/chrome.csv")), Some("chrome"));
+        assert_eq!(detected(include_bytes!("fixtures/lastpass.csv")), Some("lastpass"));
+        assert_eq!(detected(include_bytes!("fixtures/dashlane.csv")), Some("dashlane"));
+        assert_eq!(detected(include_bytes!("fixtures/keepassxc.csv")), Some("keepassxc"));
+        assert_eq!(detected(include_bytes!("fixtures/passlane-cards.csv")), Some("passlane-cards"));
+        assert_eq!(detected(include_bytes!("fixtures/passlane-notes.csv")), Some("passlane-notes"));
+        assert_eq!(detected(b"a,b,c\n1,2,3\n"), None);
+    }
+
+    #[test]
+    fn reads_every_fixture() {
+        let fixtures: [(&str, &[u8]); 8] = [
+            ("bitwarden", include_bytes!("fixtures/bitwarden.json")),
+            ("1pux", include_bytes!("fixtures/export.1pux")),
+            ("passlane", include_bytes!("fixtures/passlane.csv")),
+            ("firefox", include_bytes!("fixtures/firefox.csv")),
+            ("chrome", include_bytes!("fixtures/chrome.csv")),
+            ("lastpass", include_bytes!("fixtures/lastpass.csv")),
+            ("dashlane", include_bytes!("fixtures/dashlane.csv")),
+            ("keepassxc", include_bytes!("fixtures/keepassxc.csv")),
+        ];
+        for (format, content) in fixtures {
+            let detected = parse_export(content, None, importers()).unwrap();
+            let named = parse_export(content, Some(format), importers()).unwrap();
+            assert!(!detected.credentials.is_empty(), "{}", format);
+            assert_eq!(detected.credentials.len(), named.credentials.len(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn format_overrides_detection() {
+        let content = include_bytes!("fixtures/firefox.csv");
+        let entries = parse_export(content, Some("passlane"), importers()).unwrap();
+        assert_eq!(entries.credentials[0].entry.service(), "https://example.com");
+        // Only the Firefox importer reads the time the password was changed.
+        assert_ne!(entries.credentials[0].entry.last_modified().timestamp_millis(), 1723657076271);
+        let error = parse_export(content, Some("keepass"), importers()).err().unwrap();
+        assert!(error.message.starts_with("Unknown export format 'keepass'"));
+    }
+}
@@ -0,0 +1,525 @@
+//! Reads the `.1pux` export of 1Password 8: a zip archive whose
+//! `export.data` file holds the accounts, their vaults and the items in them
+//! as JSON. Logins and passwords become credentials, with their one time
+//! password going to the TOTP vault, credit cards become payment cards and
+//! secure notes become notes. Vaults become groups and tags are kept.
+
+use chrono::DateTime;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::importers::{parse_totp, zip, Imported, ImportedEntries, Importer, SkippedField};
+use crate::vault::entities::{Address, Credential, Error, Expiry, Note, PaymentCard};
+
+const LOGIN: &str = "001";
+const CREDIT_CARD: &str = "002";
+const SECURE_NOTE: &str = "003";
+const PASSWORD: &str = "005";
+
+/// The names of the other categories, for the list of skipped items.
+const OTHER_CATEGORIES: [(&str, &str); 19] = [
+    ("004", "identity"),
+    ("006", "document"),
+    ("100", "software license"),
+    ("101", "bank account"),
+    ("102", "database"),
+    ("103", "driver license"),
+    ("104", "outdoor license"),
+    ("105", "membership"),
+    ("106", "passport"),
+    ("107", "reward program"),
+    ("108", "social security number"),
+    ("109", "wireless router"),
+    ("110", "server"),
+    ("111", "email account"),
+    ("112", "API credential"),
+    ("113", "medical record"),
+    ("114", "SSH key"),
+    ("115", "crypto wallet"),
+    ("116", "passkey"),
+];
+
+#[derive(Deserialize)]
+struct OnePuxExport {
+    accounts: Vec<OnePuxAccount>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxAccount {
+    #[serde(default)]
+    vaults: Vec<OnePuxVault>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVault {
+    attrs: OnePuxVaultAttrs,
+    #[serde(default)]
+    items: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVaultAttrs {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxItem {
+    category_uuid: String,
+    state: Option<String>,
+    updated_at: Option<i64>,
+    details: OnePuxDetails,
+    overview: OnePuxOverview,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxOverview {
+    #[serde(default)]
+    title: String,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<OnePuxUrl>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxUrl {
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxDetails {
+    #[serde(default)]
+    login_fields: Vec<OnePuxLoginField>,
+    notes_plain: Option<String>,
+    #[serde(default)]
+    sections: Vec<OnePuxSection>,
+    #[serde(default)]
+    password_history: Vec<Value>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxLoginField {
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    name: String,
+    designation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxSection {
+    #[serde(default)]
+    fields: Vec<OnePuxField>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxField {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    id: String,
+    value: Value,
+}
+
+impl OnePuxField {
+    /// The kind of the value, like `string`, `totp` or `monthYear`, and the
+    /// value itself.
+    fn typed_value(&self) -> Option<(&str, &Value)> {
+        self.value.as_object()?.iter().next().map(|(k, v)| (k.as_str(), v))
+    }
+
+    fn text(&self) -> Option<&str> {
+        self.typed_value()
+            .and_then(|(_, value)| value.as_str())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        match self.typed_value() {
+            None => true,
+            Some((_, Value::String(value))) => value.trim().is_empty(),
+            Some((_, Value::Null)) => true,
+            Some((_, Value::Object(value))) => value
+                .values()
+                .all(|v| v.is_null() || v.as_str().is_some_and(|s| s.trim().is_empty())),
+            Some(_) => false,
+        }
+    }
+
+    fn is_totp(&self) -> bool {
+        self.typed_value().is_some_and(|(kind, _)| kind == "totp")
+    }
+
+    fn name(&self) -> &str {
+        if self.title.is_empty() { &self.id } else { &self.title }
+    }
+}
+
+fn category_name(category: &str) -> String {
+    OTHER_CATEGORIES
+        .iter()
+        .find(|(uuid, _)| *uuid == category)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("item of category {}", category))
+}
+
+fn address(value: &Value) -> Option<Address> {
+    let part = |name: &str| value.get(name).and_then(Value::as_str).unwrap_or("");
+    let state = Some(part("state")).filter(|state| !state.is_empty());
+    (!part("street").is_empty() || !part("city").is_empty()).then(|| {
+        Address::new(None, part("street"), part("city"), part("country"), state, part("zip"))
+    })
+}
+
+struct ItemReader<'a> {
+    item: &'a OnePuxItem,
+    folder: Option<String>,
+}
+
+impl ItemReader<'_> {
+    fn title(&self) -> &str {
+        &self.item.overview.title
+    }
+
+    fn skip(&self, entries: &mut ImportedEntries, field: &str) {
+        entries.skipped.push(SkippedField::new(self.title(), field));
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &OnePuxField> {
+        self.item.details.sections.iter().flat_map(|s| s.fields.iter())
+    }
+
+    fn notes(&self) -> Option<&str> {
+        self.item
+            .details
+            .notes_plain
+            .as_deref()
+            .filter(|notes| !notes.trim().is_empty())
+    }
+
+    fn last_modified(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.item.updated_at.and_then(|secs| DateTime::from_timestamp(secs, 0))
+    }
+
+    /// Reports the fields of the sections that were not used, other than
+    /// those in `used`.
+    fn skip_other_fields(&self, entries: &mut ImportedEntries, used: &[&str]) {
+        let skipped: Vec<String> = self
+            .fields()
+            .filter(|field| !field.is_empty() && !used.contains(&field.id.as_str()))
+            .map(|field| format!("field '{}'", field.name()))
+            .collect();
+        for field in skipped {
+            self.skip(entries, &field);
+        }
+        if !self.item.details.password_history.is_empty() {
+            self.skip(entries, "password history");
+        }
+    }
+
+    fn to_credential(&self, entries: &mut ImportedEntries) {
+        let overview = &self.item.overview;
+        let details = &self.item.details;
+        let mut username = "";
+        let mut password = details.password.as_deref().unwrap_or("");
+        for field in &details.login_fields {
+            match field.designation.as_deref() {
+                Some("username") => username = &field.value,
+                Some("password") => password = &field.value,
+                _ if field.value.is_empty() => {}
+                _ => self.skip(entries, &format!("login field '{}'", field.name)),
+            }
+        }
+        let url = overview.url.as_deref().filter(|url| !url.is_empty());
+        for extra in overview.urls.iter().filter(|u| Some(u.url.as_str()) != url) {
+            self.skip(entries, &format!("URL {}", extra.url));
+        }
+        let mut credential = Credential::new(
+            None,
+            password,
+            url.unwrap_or(self.title()),
+            username,
+            self.notes(),
+            self.last_modified(),
+        )
+        .with_tags(overview.tags.clone());
+
+        let otp_fields: Vec<&OnePuxField> = self.fields().filter(|field| field.is_totp()).collect();
+        let otps: Vec<&str> = otp_fields.iter().filter_map(|field| field.text()).collect();
+        for (index, otp) in otps.iter().enumerate() {
+            if index > 0 {
+                self.skip(entries, &format!("one-time password {}", index + 1));
+                continue;
+            }
+            match parse_totp(otp, self.title(), username) {
+                Ok(totp) => {
+                    credential = credential.with_totp_id(Some(*totp.id()));
+                    entries.totps.push(totp);
+                }
+                Err(e) => self.skip(entries, &format!("one-time password ({})", e.message)),
+            }
+        }
+        let otp_ids: Vec<&str> = otp_fields.iter().map(|field| field.id.as_str()).collect();
+        self.skip_other_fields(entries, &otp_ids);
+        entries.credentials.push(Imported {
+            entry: credential,
+            folder: self.folder.clone(),
+        });
+    }
+
+    fn to_payment(&self, entries: &mut ImportedEntries) {
+        let field = |id: &str| self.fields().find(|field| field.id == id);
+        let text = |id: &str| field(id).and_then(OnePuxField::text).unwrap_or("");
+        let expiry = field("expiry")
+            .and_then(|field| field.typed_value())
+            .and_then(|(_, value)| value.as_u64())
+            .map(|month_year| Expiry {
+                month: (month_year % 100) as u32,
+                year: (month_year / 100) as u32,
+            })
+            .unwrap_or(Expiry { month: 0, year: 0 });
+        let billing_address = self
+            .fields()
+            .find_map(|field| match field.typed_value() {
+                Some(("address", value)) => Some((field.id.as_str(), address(value)?)),
+                _ => None,
+            });
+        let mut used = vec!["cardholder", "ccnum", "cvv", "expiry", "type"];
+        if let Some((id, _)) = &billing_address {
+            used.push(id);
+        }
+        self.skip_other_fields(entries, &used);
+        if self.notes().is_some() {
+            self.skip(entries, "notes");
+        }
+        let card = PaymentCard::new(
+            None,
+            self.title(),
+            text("cardholder"),
+            text("ccnum"),
+            text("cvv"),
+            expiry,
+            None,
+            billing_address.as_ref().map(|(_, address)| address),
+            self.last_modified(),
+        )
+        .with_tags(self.item.overview.tags.clone());
+        entries.payments.push(Imported {
+            entry: card,
+            folder: self.folder.clone(),
+        });
+    }
+
+    fn to_note(&self, entries: &mut ImportedEntries) {
+        self.skip_other_fields(entries, &[]);
+        let note = Note::new(
+            None,
+            self.title(),
+            self.notes().unwrap_or(""),
+            self.last_modified(),
+        )
+        .with_tags(self.item.overview.tags.clone());
+        entries.notes.push(Imported {
+            entry: note,
+            folder: self.folder.clone(),
+        });
+    }
+}
+
+/// 1Password's `.1pux` export, recognized as a zip archive.
+pub struct OnePux;
+
+impl Importer for OnePux {
+    fn name(&self) -> &'static str {
+        "1pux"
+    }
+
+    fn description(&self) -> &'static str {
+        "1Password (1PUX)"
+    }
+
+    fn detect(&self, content: &[u8]) -> bool {
+        zip::is_zip(content)
+    }
+
+    fn parse(&self, content: &[u8]) -> Result<ImportedEntries, Error> {
+        parse_export(content)
+    }
+}
+
+/// Reads a `.1pux` archive.
+pub fn parse_export(archive: &[u8]) -> Result<ImportedEntries, Error> {
+    let data = zip::read_file(archive, "export.data")?;
+    let export: OnePuxExport = serde_json::from_slice(&data)
+        .map_err(|e| Error::new(&format!("Invalid 1Password export: {}", e)))?;
+    let mut entries = ImportedEntries::default();
+    for vault in export.accounts.iter().flat_map(|account| account.vaults.iter()) {
+        for value in &vault.items {
+            // Older exports wrap each item in an object of its own.
+            let value = value.get("item").unwrap_or(value).clone();
+            let item: OnePuxItem = serde_json::from_value(value)
+                .map_err(|e| Error::new(&format!("Invalid 1Password item: {}", e)))?;
+            let reader = ItemReader {
+                item: &item,
+                folder: Some(vault.attrs.name.clone()),
+            };
+            if item.state.as_deref() == Some("archived") {
+                reader.skip(&mut entries, "archived item");
+                continue;
+            }
+            match item.category_uuid.as_str() {
+                LOGIN | PASSWORD => reader.to_credential(&mut entries),
+                CREDIT_CARD => reader.to_payment(&mut entries),
+                SECURE_NOTE => reader.to_note(&mut entries),
+                other => reader.skip(&mut entries, &category_name(other)),
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export_data() -> Value {
+        json!({
+            "accounts": [{
+                "attrs": { "accountName": "Alice", "name": "Alice", "email": "alice@example.com" },
+                "vaults": [
+                    {
+                        "attrs": { "uuid": "v1", "name": "Private" },
+                        "items": [
+                            {
+                                "uuid": "i1", "categoryUuid": "001", "state": "active", "updatedAt": 1709287200,
+                                "details": {
+                                    "loginFields": [
+                                        { "value": "alice", "name": "username", "fieldType": "T", "designation": "username" },
+                                        { "value": "s3cret", "name": "password", "fieldType": "P", "designation": "password" }
+                                    ],
+                                    "notesPlain": "work account",
+                                    "sections": [{ "title": "", "name": "add more", "fields": [
+                                        { "title": "one-time password", "id": "TOTP_1",
+                                          "value": { "totp": "otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub" } },
+                                        { "title": "recovery email", "id": "f2", "value": { "string": "x@example.com" } },
+                                        { "title": "empty", "id": "f3", "value": { "string": "" } }
+                                    ]}],
+                                    "passwordHistory": []
+                                },
+                                "overview": {
+                                    "title": "GitHub", "url": "https://github.com", "tags": ["work", "dev"],
+                                    "urls": [{ "label": "", "url": "https://github.com" },
+                                             { "label": "", "url": "https://gist.github.com" }]
+                                }
+                            },
+                            {
+                                "item": {
+                                    "uuid": "i2", "categoryUuid": "002", "state": "active",
+                                    "details": { "sections": [{ "title": "", "fields": [
+                                        { "title": "cardholder name", "id": "cardholder", "value": { "string": "Alice Smith" } },
+                                        { "title": "type", "id": "type", "value": { "creditCardType": "visa" } },
+                                        { "title": "number", "id": "ccnum", "value": { "creditCardNumber": "4111111111111111" } },
+                                        { "title": "verification number", "id": "cvv", "value": { "concealed": "123" } },
+                                        { "title": "expiry date", "id": "expiry", "value": { "monthYear": 202707 } },
+                                        { "title": "PIN", "id": "pin", "value": { "concealed": "0000" } }
+                                    ]}, { "title": "billing", "fields": [
+                                        { "title": "address", "id": "address", "value": { "address": {
+                                            "street": "1 Main St", "city": "Springfield", "country": "us", "zip": "12345", "state": "IL" } } }
+                                    ]}]},
+                                    "overview": { "title": "Visa", "tags": [] }
+                                }
+                            }
+                        ]
+                    },
+                    {
+                        "attrs": { "uuid": "v2", "name": "Shared" },
+                        "items": [
+                            {
+                                "uuid": "i3", "categoryUuid": "003", "state": "active",
+                                "details": { "notesPlain": "password: hunter2" },
+                                "overview": { "title": "Wifi", "tags": ["home"] }
+                            },
+                            {
+                                "uuid": "i4", "categoryUuid": "005", "state": "active",
+                                "details": { "password": "router-pw" },
+                                "overview": { "title": "Router" }
+                            },
+                            { "uuid": "i5", "categoryUuid": "004", "details": {}, "overview": { "title": "Me" } },
+                            { "uuid": "i6", "categoryUuid": "001", "state": "archived", "details": {}, "overview": { "title": "Old" } }
+                        ]
+                    }
+                ]
+            }]
+        })
+    }
+
+    fn archive() -> Vec<u8> {
+        let data = serde_json::to_vec(&export_data()).unwrap();
+        zip::write_archive(&[("export.attributes", b"{}"), ("export.data", &data)])
+    }
+
+    #[test]
+    fn maps_items_to_entities() {
+        let entries = parse_export(&archive()).unwrap();
+
+        assert_eq!(entries.credentials.len(), 2);
+        let github = &entries.credentials[0];
+        assert_eq!(github.folder.as_deref(), Some("Private"));
+        assert_eq!(github.entry.service(), "https://github.com");
+        assert_eq!(github.entry.username(), "alice");
+        assert_eq!(github.entry.password(), "s3cret");
+        assert_eq!(github.entry.note(), Some("work account"));
+        assert_eq!(github.entry.tags(), ["work", "dev"]);
+        assert_eq!(github.entry.totp_id(), Some(entries.totps[0].id()));
+        assert_eq!(entries.totps.len(), 1);
+        assert_eq!(
+            github.entry.last_modified().to_rfc3339(),
+            "2024-03-01T10:00:00+00:00"
+        );
+        let router = &entries.credentials[1];
+        assert_eq!(router.folder.as_deref(), Some("Shared"));
+        assert_eq!((router.entry.service(), router.entry.password()), ("Router", "router-pw"));
+
+        let visa = &entries.payments[0].entry;
+        assert_eq!(visa.name_on_card(), "Alice Smith");
+        assert_eq!(visa.number(), "4111111111111111");
+        assert_eq!(visa.cvv(), "123");
+        assert_eq!(visa.expiry_str(), "7/2027");
+        assert_eq!(visa.billing_address().unwrap().to_string(), "1 Main St, 12345, Springfield, IL, us");
+
+        assert_eq!(entries.notes[0].entry.content(), "password: hunter2");
+        assert_eq!(entries.notes[0].entry.tags(), ["home"]);
+        assert_eq!(
+            entries.folders(),
+            vec![Some("Private".to_string()), Some("Shared".to_string())]
+        );
+    }
+
+    #[test]
+    fn lists_skipped_fields() {
+        let skipped = parse_export(&archive()).unwrap().skipped;
+        assert_eq!(
+            skipped,
+            vec![
+                SkippedField::new("GitHub", "URL https://gist.github.com"),
+                SkippedField::new("GitHub", "field 'recovery email'"),
+                SkippedField::new("Visa", "field 'PIN'"),
+                SkippedField::new("Me", "identity"),
+                SkippedField::new("Old", "archived item"),
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_export_data() {
+        let archive = zip::write_archive(&[("export.attributes", b"{}")]);
+        assert!(parse_export(&archive).err().unwrap().message.contains("export.data"));
+    }
+}
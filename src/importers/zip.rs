@@ -0,0 +1,175 @@
+//! Reads one file from a zip archive, which is all the 1Password importer
+//! needs. Only stored and deflated files of a plain (not Zip64, not
+//! encrypted) archive are supported, which is what 1Password writes.
+
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+
+use crate::vault::entities::Error;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// Whether `data` starts like a zip archive.
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&LOCAL_HEADER.to_le_bytes())
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Error> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Error> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> Error {
+    Error::new("The zip archive is truncated")
+}
+
+/// Finds the end of central directory record, which is followed only by the
+/// archive comment.
+fn end_of_central_directory(data: &[u8]) -> Result<usize, Error> {
+    let signature = END_OF_CENTRAL_DIRECTORY.to_le_bytes();
+    (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| data[offset..].starts_with(&signature))
+        .ok_or_else(|| Error::new("Not a zip archive"))
+}
+
+/// The content of the file called `name` in the archive.
+pub fn read_file(data: &[u8], name: &str) -> Result<Vec<u8>, Error> {
+    let end = end_of_central_directory(data)?;
+    let count = u16_at(data, end + 10)?;
+    let mut offset = u32_at(data, end + 16)? as usize;
+    for _ in 0..count {
+        if u32_at(data, offset)? != CENTRAL_HEADER {
+            return Err(Error::new("The zip archive has an invalid central directory"));
+        }
+        let flags = u16_at(data, offset + 8)?;
+        let method = u16_at(data, offset + 10)?;
+        let compressed_size = u32_at(data, offset + 20)? as usize;
+        let name_length = u16_at(data, offset + 28)? as usize;
+        let extra_length = u16_at(data, offset + 30)? as usize;
+        let comment_length = u16_at(data, offset + 32)? as usize;
+        let local_offset = u32_at(data, offset + 42)? as usize;
+        let file_name = data
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or_else(truncated)?;
+        offset += 46 + name_length + extra_length + comment_length;
+        if file_name != name.as_bytes() {
+            continue;
+        }
+        if flags & 1 != 0 {
+            return Err(Error::new("Encrypted zip archives are not supported"));
+        }
+        if u32_at(data, local_offset)? != LOCAL_HEADER {
+            return Err(Error::new("The zip archive has an invalid file header"));
+        }
+        let start = local_offset
+            + 30
+            + u16_at(data, local_offset + 26)? as usize
+            + u16_at(data, local_offset + 28)? as usize;
+        let compressed = data
+            .get(start..start + compressed_size)
+            .ok_or_else(truncated)?;
+        return match method {
+            STORED => Ok(compressed.to_vec()),
+            DEFLATED => {
+                let mut content = Vec::new();
+                DeflateDecoder::new(compressed).read_to_end(&mut content)?;
+                Ok(content)
+            }
+            _ => Err(Error::new(&format!(
+                "{} uses zip compression method {}, which is not supported",
+                name, method
+            ))),
+        };
+    }
+    Err(Error::new(&format!("The zip archive has no {}", name)))
+}
+
+/// Writes a zip archive with the files deflated, for tests of readers of
+/// zipped exports.
+#[cfg(test)]
+pub fn write_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut archive = Vec::new();
+    let mut central = Vec::new();
+    for (name, content) in files {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let crc = {
+            let mut hasher = flate2::Crc::new();
+            hasher.update(content);
+            hasher.sum()
+        };
+        let local_offset = archive.len() as u32;
+        let header = |signature: u32| {
+            let mut header = signature.to_le_bytes().to_vec();
+            header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            header.extend_from_slice(&0u16.to_le_bytes()); // flags
+            header.extend_from_slice(&DEFLATED.to_le_bytes());
+            header.extend_from_slice(&[0; 4]); // modification time and date
+            header.extend_from_slice(&crc.to_le_bytes());
+            header.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+            header
+        };
+        archive.extend(header(LOCAL_HEADER));
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&compressed);
+
+        let mut entry = header(CENTRAL_HEADER);
+        entry.insert(4, 20); // version made by
+        entry.insert(5, 0);
+        entry.extend_from_slice(&[0; 6]); // comment length, disk, internal attributes
+        entry.extend_from_slice(&[0; 4]); // external attributes
+        entry.extend_from_slice(&local_offset.to_le_bytes());
+        entry.extend_from_slice(name.as_bytes());
+        central.extend(entry);
+    }
+    let central_offset = archive.len() as u32;
+    archive.extend_from_slice(&central);
+    archive.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]); // disk numbers
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    archive.extend_from_slice(&central_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    archive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_files_from_archives() {
+        let archive = write_archive(&[
+            ("export.attributes", b"{\"version\": 3}"),
+            ("export.data", b"{\"accounts\": []}"),
+        ]);
+        assert!(is_zip(&archive));
+        assert_eq!(read_file(&archive, "export.data").unwrap(), b"{\"accounts\": []}");
+        assert_eq!(read_file(&archive, "export.attributes").unwrap(), b"{\"version\": 3}");
+        assert!(read_file(&archive, "missing").is_err());
+        assert!(read_file(&archive[..archive.len() / 2], "export.data").is_err());
+        assert!(!is_zip(b"{}"));
+    }
+}
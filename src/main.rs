@@ -1,30 +1,83 @@
 mod actions;
 mod completion_cache;
 mod crypto;
+mod importers;
 mod keychain;
+mod otp_import;
+mod otp_migration;
+mod password_rules;
+mod qr;
 mod repl;
 mod store;
 mod ui;
 mod vault;
 
 use crate::actions::add::AddAction;
+use crate::actions::audit::AuditAction;
 use crate::actions::change_password::ChangePasswordAction;
+use crate::actions::clock_offset::ClockOffsetAction;
+use crate::actions::codes::{AddCodesAction, DeleteCodesAction, ListCodesAction, UseCodeAction};
 use crate::actions::completions::CompletionsAction;
 use crate::actions::delete::DeleteAction;
 use crate::actions::edit::EditAction;
-use crate::actions::export::ExportAction;
+use crate::actions::export::{ExportAction, ExportKdbxAction};
 use crate::actions::generate::GeneratePasswordAction;
 use crate::actions::help::PrintHelpAction;
-use crate::actions::import::ImportCsvAction;
+use crate::actions::import::{ImportAction, ImportCsvAction, ImportKdbxAction};
+use crate::actions::import_otp::{ImportMigrationAction, ImportOtpAction};
+use crate::actions::link::LinkOtpAction;
 use crate::actions::list::ListAction;
 use crate::actions::lock::LockAction;
+use crate::actions::migrate::MigrateAction;
 use crate::actions::show::ShowAction;
 use crate::actions::unlock::UnlockAction;
+use crate::actions::watch::WatchOtpAction;
 use actions::*;
-use clap::{arg, ArgAction, Command};
+use clap::{arg, value_parser, Arg, ArgAction, Command};
 use init::InitAction;
 use std::env;
 
+/// The options of `show -o` and `list -o --code` for the time codes are generated for.
+fn otp_time_args() -> Vec<Arg> {
+    vec![
+        arg!(--at <TIME> "With -o, generate the codes for this time instead of now: an RFC 3339 timestamp such as 2024-05-01T12:00:00Z, or Unix seconds. HOTP authorizers are skipped.")
+            .value_parser(|value: &str| crypto::otp::parse_time(value).map_err(|e| e.message))
+            .requires("otp"),
+        arg!(--offset <SECONDS> "With -o, move the system clock by this many seconds, overriding the offset saved with otp clock-offset. HOTP authorizers are skipped.")
+            .value_parser(value_parser!(i64))
+            .allow_negative_numbers(true)
+            .conflicts_with("at")
+            .requires("otp"),
+    ]
+}
+
+/// Password generator options shared by `gen` and `add`.
+fn password_policy_args() -> Vec<Arg> {
+    vec![
+        arg!(--length <N> "Length of the generated password.").value_parser(value_parser!(usize)),
+        arg!(--lower "Include lowercase letters, even if the saved defaults leave them out.").action(ArgAction::SetTrue).overrides_with("no-lower"),
+        arg!(--"no-lower" "Leave out lowercase letters.").action(ArgAction::SetTrue).overrides_with("lower"),
+        arg!(--upper "Include uppercase letters, even if the saved defaults leave them out.").action(ArgAction::SetTrue).overrides_with("no-upper"),
+        arg!(--"no-upper" "Leave out uppercase letters.").action(ArgAction::SetTrue).overrides_with("upper"),
+        arg!(--digits "Include digits, even if the saved defaults leave them out.").action(ArgAction::SetTrue).overrides_with("no-digits"),
+        arg!(--"no-digits" "Leave out digits.").action(ArgAction::SetTrue).overrides_with("digits"),
+        arg!(--symbols [SET] "Include symbols, even if the saved defaults leave them out, using these characters as the symbol set when given.").overrides_with("no-symbols"),
+        arg!(--"no-symbols" "Leave out symbols.").action(ArgAction::SetTrue).overrides_with("symbols"),
+        arg!(--min <N> "Minimum number of characters from each included class.").value_parser(value_parser!(usize)),
+        arg!(--ambiguous "Include easily confused characters (0O1lI), even if the saved defaults leave them out.").action(ArgAction::SetTrue).overrides_with("no-ambiguous"),
+        arg!(--"no-ambiguous" "Leave out easily confused characters (0O1lI).").action(ArgAction::SetTrue).overrides_with("ambiguous"),
+        arg!(--passphrase "Generate a passphrase of words from the EFF long word list instead.").action(ArgAction::SetTrue),
+        arg!(--words <N> "Number of words in the passphrase.").value_parser(value_parser!(usize)).requires("passphrase"),
+        arg!(--separator <SEP> "Text placed between the passphrase words.").requires("passphrase"),
+        arg!(--capitalize "Capitalize each passphrase word.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("no-capitalize"),
+        arg!(--"no-capitalize" "Keep the passphrase words lowercase.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("capitalize"),
+        arg!(--"with-digit" "Append a digit to one passphrase word.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("without-digit"),
+        arg!(--"without-digit" "Do not append a digit to a passphrase word.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("with-digit"),
+        arg!(--"with-symbol" "Append a symbol from the symbol set to one passphrase word.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("without-symbol"),
+        arg!(--"without-symbol" "Do not append a symbol to a passphrase word.").action(ArgAction::SetTrue).requires("passphrase").overrides_with("with-symbol"),
+    ]
+}
+
 pub fn cli() -> Command {
     Command::new("passlane")
         .about("A password manager using Keepass as the storage backend.")
@@ -53,6 +106,16 @@ pub fn cli() -> Command {
                 .arg(arg!(
                     -l --clipboard "Get the password to save from the clipboard."
                 ).action(ArgAction::SetTrue))
+                .args(password_policy_args())
+                .arg(arg!(
+                    --rules <RULES> "Site password rules in the passwordrules syntax, e.g. \"minlength: 12; required: upper; allowed: [-_.]\". Stored with the credential."
+                ).conflicts_with("passphrase"))
+                .arg(arg!(
+                    --"qr-image" <PATH> "With -o, read the authorizer from a QR code in a PNG or JPEG image instead of asking for its details. Google Authenticator export codes are read as well."
+                ).requires("otp"))
+                .arg(arg!(
+                    --breached <PATH> "Warn if the entered password is in a local copy of the Have I Been Pwned SHA-1 dataset (sorted file or range directory)."
+                ).conflicts_with("generate"))
         )
         .subcommand(
             Command::new("edit")
@@ -61,13 +124,41 @@ pub fn cli() -> Command {
                 .arg(arg!(-p --payments "Edit payment cards.").action(ArgAction::SetTrue))
                 .arg(arg!(-n --notes "Edit secure notes.").action(ArgAction::SetTrue))
                 .arg(arg!(-o --otp "Edit One Time Password authorizer.").action(ArgAction::SetTrue))
+                .arg(arg!(-g --generate "For credentials, replace the password with a generated one that follows the entry's password rules.").action(ArgAction::SetTrue))
+                .arg(arg!(--rules <RULES> "For credentials, set the site password rules stored with the credential. An empty value removes them."))
+                .arg(arg!(--resync <CODE> "With -o, resynchronize an HOTP authorizer with the code its token shows now.").requires("otp"))
+                .arg(arg!(--window <N> "How many counters ahead --resync looks for the code (default 20).").value_parser(value_parser!(u64)).requires("resync"))
                 .arg(arg!(<REGEXP> "The regular expression used to search services whose credentials to edit.").group("search").required(false))
                 .arg_required_else_help(true)
         )
         .subcommand(
             Command::new("csv")
-                .about("Imports credentials from a CSV file.")
-                .arg(arg!(<FILE_PATH> "The the CSV file to import."))
+                .about("Imports credentials from a CSV file. One time passwords go to the TOTP vault.")
+                .arg(arg!(<FILE_PATH> "The the CSV file to import: passlane's own export, or that of Firefox, Chrome, LastPass, Dashlane or KeePassXC."))
+                .arg(arg!(-p --payments "Import payment cards written by `export -p`.").action(ArgAction::SetTrue).conflicts_with_all(["notes", "format"]))
+                .arg(arg!(-n --notes "Import secure notes written by `export -n`.").action(ArgAction::SetTrue).conflicts_with("format"))
+                .arg(arg!(--format <FORMAT> "The layout of the CSV. Detected from the header row when not given.")
+                    .value_parser(importers::format_names(&importers::csv::importers())))
+                .arg(arg!(
+                    --"on-conflict" <MODE> "What to do with rows for an account already in the vault (same service and username, or same UUID)."
+                ).value_parser(["skip", "update", "duplicate"]).default_value("skip"))
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Imports the logins, payment cards and secure notes of another password manager's export, or of another KeePass file with --kdbx. One time passwords go to the TOTP vault.")
+                .arg(arg!(<FILE_PATH> "The export file: Bitwarden's unencrypted JSON export, a 1Password .1pux file or a CSV export (see the csv command). With --kdbx, the KeePass file."))
+                .arg(arg!(--format <FORMAT> "The password manager that made the export. Detected from the file when not given.")
+                    .value_parser(importers::format_names(&importers::importers())))
+                .arg(arg!(
+                    --kdbx "Copy the entries of another KeePass file, such as one made with export --kdbx. Its password is asked for."
+                ).action(ArgAction::SetTrue).conflicts_with("format"))
+                .arg(arg!(--keyfile <PATH> "With --kdbx, the key file of the other KeePass file.").requires("kdbx"))
+                .arg(arg!(--group <NAME> "With --kdbx, put the copied entries in a group of this name inside the Passwords, Payments and Notes groups, instead of directly in them.").requires("kdbx"))
+                .arg(arg!(--"new-uuids" "With --kdbx, give the copied entries new UUIDs instead of keeping those of the other file.").action(ArgAction::SetTrue).requires("kdbx"))
+                .arg(arg!(--"dry-run" "Show what would be imported without unlocking or changing the vault.").action(ArgAction::SetTrue))
+                .arg(arg!(
+                    --"on-conflict" <MODE> "What to do with logins for an account already in the vault (same service and username)."
+                ).value_parser(["skip", "update", "duplicate"]).default_value("skip"))
         )
         .subcommand(
             Command::new("delete")
@@ -114,6 +205,13 @@ pub fn cli() -> Command {
                 .arg(arg!(
                     --once "With -o, print the single matching OTP code to stdout and exit (no clipboard, no countdown). Errors if zero or multiple authorizers match. The code is valid only briefly."
                 ).action(ArgAction::SetTrue))
+                .args(otp_time_args())
+                .arg(arg!(
+                    --window <N> "With -o, print the codes of the N periods before and after the current one instead of the current code."
+                ).value_parser(value_parser!(u64)).requires("otp").conflicts_with_all(["once", "qr"]))
+                .arg(arg!(
+                    --qr "Show the password, or with -o the otpauth:// URL for enrolling another authenticator app, as a QR code in the terminal. The screen is cleared after 20 seconds or on any key."
+                ).action(ArgAction::SetTrue).conflicts_with_all(["out", "once"]))
                 .arg(arg!(<REGEXP> "Regular expression used to search services to show.").group("search").required(false))
                 .arg_required_else_help(true)
         )
@@ -139,8 +237,18 @@ pub fn cli() -> Command {
                     -c --credentials "List credentials (default)."
                 ).action(ArgAction::SetTrue))
                 .arg(arg!(
-                    --code "With -o, output the currently generated TOTP code for each match instead of the stored secret. Codes are valid only briefly (see valid_for_seconds)."
+                    --code "With -o, output the currently generated TOTP code for each match instead of the stored secret. Codes are valid only briefly (see valid_for_seconds). HOTP authorizers are skipped, use show -o for those."
                 ).action(ArgAction::SetTrue))
+                .arg(arg!(
+                    --watch "With -o --code, keep running and print a JSON line per authorizer each time its code rolls over, for status bars. Ends when stdout is closed."
+                ).action(ArgAction::SetTrue).requires_all(["otp", "code"]).conflicts_with("at"))
+                .args(otp_time_args())
+                .arg(arg!(
+                    --strength "With --json, add an estimate of each credential's password strength (score 0-4, guesses, crack time)."
+                ).action(ArgAction::SetTrue).requires("json"))
+                .arg(arg!(
+                    --"linked-code" "With --json, add the current code of each credential's linked authorizer (see otp link), leaving out HOTP authorizers so that no code is used up. Unlocks the one time passwords vault."
+                ).action(ArgAction::SetTrue).requires("json").conflicts_with("otp"))
                 .arg(arg!(<REGEXP> "Regular expression to filter entries.").required(false))
         )
         .subcommand(
@@ -163,7 +271,7 @@ pub fn cli() -> Command {
         )
         .subcommand(
             Command::new("export")
-                .about("Exports the vault contents to a CSV file.")
+                .about("Exports the vault contents to a CSV file, or matching entries to a new KeePass file with --kdbx.")
                 .arg(arg!(
                     -p --payments "Exporet payment cards."
                 ).action(ArgAction::SetTrue))
@@ -173,14 +281,107 @@ pub fn cli() -> Command {
                 .arg(arg!(
                     -o --otp "Shows one time passwords (OTPs)"
                 ).action(ArgAction::SetTrue))
-                .arg(arg!(<file_path> "The the CSV file to export to."))
+                .arg(arg!(
+                    --kdbx "Write the matching credentials, payment cards and notes to a new KeePass file protected by a password of its own, instead of a CSV file."
+                ).action(ArgAction::SetTrue).conflicts_with_all(["payments", "notes", "otp"]))
+                .arg(arg!(--keyfile <PATH> "With --kdbx, protect the new file with this key file besides the password. Leave the password empty to use the key file only.").requires("kdbx"))
+                .arg(arg!(--"with-otp" "With --kdbx, also copy the authorizers linked to the exported credentials from the TOTP vault.").action(ArgAction::SetTrue).requires("kdbx"))
+                .arg(arg!(<file_path> "The the CSV file to export to, or the KeePass file with --kdbx."))
+                .arg(arg!([PATTERN] "With --kdbx, export only the entries containing this text, ignoring case: in the service or username of credentials, the name or name on card of payment cards and the title of notes.").requires("kdbx"))
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Reports entries whose stored text differs from what was entered, because earlier versions removed emojis, symbols and control characters. Does not modify the vault.")
+                .arg(arg!(
+                    -o --otp "Check the one time passwords vault."
+                ).action(ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Checks the saved credentials for empty, weak, reused and old passwords, and for services without a one time password. Prints no passwords.")
+                .arg(arg!(--json "Output the findings as JSON.").action(ArgAction::SetTrue))
+                .arg(arg!(--"max-age" <DAYS> "Report passwords not changed in this many days (default 365).").value_parser(value_parser!(i64)))
+                .arg(arg!(--"min-score" <SCORE> "Report passwords whose strength score (0-4) is below this (default 3).").value_parser(value_parser!(u8).range(0..=4)))
+                .arg(arg!(--"no-otp" "Skip the check for a matching entry in the one time passwords vault.").action(ArgAction::SetTrue))
+                .arg(arg!(--unlinked "Also report credentials that are not linked to an authorizer (see otp link).").action(ArgAction::SetTrue))
+                .arg(arg!(--breached <PATH> "Also report passwords found in a local copy of the Have I Been Pwned SHA-1 dataset: a file sorted by hash or a directory of range files."))
+                .arg(arg!(--"fail-on" <SEVERITY> "Exit with a non-zero status when there are findings of this severity or higher.").value_parser(["low", "medium", "high"]))
+        )
+        .subcommand(
+            Command::new("otp")
+                .about("Manage the one time password authorizers.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("import-migration")
+                        .about("Imports the accounts of a Google Authenticator export into the TOTP vault.")
+                        .arg(arg!(<SOURCE> "An otpauth-migration:// URI scanned from an export QR code, or a file with one such URI per line."))
+                        .arg(arg!(-y --yes "Import without asking for confirmation.").action(ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Imports the authorizers of an Aegis (plain or encrypted), 2FAS or andOTP JSON export into the TOTP vault.")
+                        .arg(arg!(<FILE_PATH> "The exported JSON file."))
+                        .arg(arg!(--format <FORMAT> "The app that made the export. Detected from the file when not given.").value_parser(["aegis", "2fas", "andotp"]))
+                        .arg(arg!(-y --yes "Import without asking for confirmation.").action(ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("watch")
+                        .about("Shows the current and next codes of all matching authorizers in a full-screen view that refreshes every second.")
+                        .arg(arg!([REGEXP] "Regular expression used to search the authorizers to show."))
+                )
+                .subcommand(
+                    Command::new("clock-offset")
+                        .about("Shows or saves the number of seconds added to the system clock when generating time-based codes, to make up for a clock that is off.")
+                        .arg(arg!([SECONDS] "The offset to save, negative when the clock is ahead.").value_parser(value_parser!(i64)).allow_negative_numbers(true))
+                )
+                .subcommand(
+                    Command::new("link")
+                        .about("Links a credential to an authorizer, so that show copies the one time code after the password.")
+                        .arg(arg!(<CREDENTIAL> "Regular expression used to search the service of the credential."))
+                        .arg(arg!([AUTHORIZER] "The UUID of the authorizer, or a regular expression used to search its label and issuer.").required_unless_present("remove"))
+                        .arg(arg!(--remove "Remove the credential's link instead.").action(ArgAction::SetTrue).conflicts_with("AUTHORIZER"))
+                )
+        )
+        .subcommand(
+            Command::new("codes")
+                .about("Manage the recovery codes that services give for when two-factor authentication is unavailable.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Saves the recovery codes of a service, replacing any saved earlier. Codes are pasted in unless a file is given.")
+                        .arg(arg!(<SERVICE> "The service the codes are for."))
+                        .arg(arg!(-f --file <FILE> "A file with the codes, separated by whitespace or commas."))
+                )
+                .subcommand(
+                    Command::new("use")
+                        .about("Copies the next unused recovery code of a service to the clipboard and marks it used.")
+                        .arg(arg!(<SERVICE> "Text contained in the service name, ignoring case."))
+                        .arg(arg!(--"warn-below" <N> "Warn when fewer than this many unused codes are left.").value_parser(value_parser!(usize)).default_value("3"))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("Lists the services with recovery codes and how many are unused.")
+                        .arg(arg!([SERVICE] "Text contained in the service names to list, ignoring case."))
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .about("Deletes the recovery codes of a service.")
+                        .arg(arg!(<SERVICE> "Text contained in the service name, ignoring case."))
+                )
         )
         .subcommand(
             Command::new("gen")
-                .about("Generate a random password and copy it to the clipboard.")
+                .about("Generate a random password and copy it to the clipboard. Defaults come from ~/.passlane/.password_policy.")
                 .arg(arg!(
                     --out "Print password to stdout instead of copying to clipboard."
                 ).action(ArgAction::SetTrue))
+                .args(password_policy_args())
+                .arg(arg!(
+                    --rules <RULES> "Generate a password that satisfies site password rules in the passwordrules syntax, e.g. \"minlength: 12; required: upper; allowed: [-_.]\"."
+                ).conflicts_with("passphrase"))
+                .arg(arg!(
+                    --"save-defaults" "Save the given generator options as the defaults in ~/.passlane/.password_policy."
+                ).action(ArgAction::SetTrue))
         )
         .subcommand(
             Command::new("repl")
@@ -219,6 +420,12 @@ fn main() {
         Some(("csv", sub_matches)) => {
             VaultAction::UnlockingAction(Box::new(ImportCsvAction::new(sub_matches)))
         }
+        Some(("import", sub_matches)) if sub_matches.get_flag("kdbx") => {
+            VaultAction::UnlockingAction(Box::new(ImportKdbxAction::new(sub_matches)))
+        }
+        Some(("import", sub_matches)) => {
+            VaultAction::UnlockingAction(Box::new(ImportAction::new(sub_matches)))
+        }
         Some(("lock", _)) => VaultAction::Action(Box::new(LockAction {})),
         Some(("unlock", sub_matches)) => {
             VaultAction::Action(Box::new(UnlockAction::new(sub_matches)))
@@ -226,9 +433,51 @@ fn main() {
         Some(("passwd", sub_matches)) => {
             VaultAction::Action(Box::new(ChangePasswordAction::new(sub_matches)))
         }
+        Some(("export", sub_matches)) if sub_matches.get_flag("kdbx") => {
+            VaultAction::UnlockingAction(Box::new(ExportKdbxAction::new(sub_matches)))
+        }
         Some(("export", sub_matches)) => {
             VaultAction::UnlockingAction(Box::new(ExportAction::new(sub_matches)))
         }
+        Some(("migrate", sub_matches)) => {
+            VaultAction::UnlockingAction(Box::new(MigrateAction::new(sub_matches)))
+        }
+        Some(("audit", sub_matches)) => {
+            VaultAction::UnlockingAction(Box::new(AuditAction::new(sub_matches)))
+        }
+        Some(("otp", sub_matches)) => match sub_matches.subcommand() {
+            Some(("import-migration", otp_matches)) => {
+                VaultAction::UnlockingAction(Box::new(ImportMigrationAction::new(otp_matches)))
+            }
+            Some(("import", otp_matches)) => {
+                VaultAction::UnlockingAction(Box::new(ImportOtpAction::new(otp_matches)))
+            }
+            Some(("watch", otp_matches)) => {
+                VaultAction::UnlockingAction(Box::new(WatchOtpAction::new(otp_matches)))
+            }
+            Some(("clock-offset", otp_matches)) => {
+                VaultAction::Action(Box::new(ClockOffsetAction::new(otp_matches)))
+            }
+            Some(("link", otp_matches)) => {
+                VaultAction::UnlockingAction(Box::new(LinkOtpAction::new(otp_matches)))
+            }
+            _ => VaultAction::Action(Box::new(PrintHelpAction::new(cli()))),
+        },
+        Some(("codes", sub_matches)) => match sub_matches.subcommand() {
+            Some(("add", codes_matches)) => {
+                VaultAction::UnlockingAction(Box::new(AddCodesAction::new(codes_matches)))
+            }
+            Some(("use", codes_matches)) => {
+                VaultAction::UnlockingAction(Box::new(UseCodeAction::new(codes_matches)))
+            }
+            Some(("list", codes_matches)) => {
+                VaultAction::UnlockingAction(Box::new(ListCodesAction::new(codes_matches)))
+            }
+            Some(("delete", codes_matches)) => {
+                VaultAction::UnlockingAction(Box::new(DeleteCodesAction::new(codes_matches)))
+            }
+            _ => VaultAction::Action(Box::new(PrintHelpAction::new(cli()))),
+        },
         Some(("edit", sub_matches)) => {
             VaultAction::UnlockingAction(Box::new(EditAction::new(sub_matches)))
         }
@@ -0,0 +1,735 @@
+//! Importers for the JSON exports of phone authenticator apps: Aegis (plain
+//! or encrypted), 2FAS (`.2fas`) and andOTP, and for the `otpauth://` URLs of
+//! enrolment QR codes.
+//!
+//! An encrypted Aegis vault stores its master key once per password slot,
+//! encrypted with AES-256-GCM under a key derived from the password with
+//! scrypt. The master key in turn decrypts the base64 `db` with AES-256-GCM.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::crypto::otp::decode_secret;
+use crate::otp_migration;
+use crate::ui::input::{format_hotp_url, format_totp_url};
+use crate::vault::entities::{keep_unknown_otp_params, Error, Totp};
+
+const DEFAULT_PERIOD: u64 = 30;
+const DEFAULT_DIGITS: u32 = 6;
+const STEAM_DIGITS: u32 = 5;
+const MAX_DIGITS: u32 = 10;
+const AEGIS_PASSWORD_SLOT: u8 = 1;
+/// Limits on the scrypt cost read from an encrypted Aegis header, so that a
+/// crafted file cannot make the key derivation allocate gigabytes. Aegis uses
+/// N = 2^15, r = 8, p = 1, which needs 32 MiB.
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+const MAX_SCRYPT_P: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OtpExportFormat {
+    Aegis,
+    TwoFas,
+    AndOtp,
+}
+
+impl FromStr for OtpExportFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "aegis" => Ok(OtpExportFormat::Aegis),
+            "2fas" => Ok(OtpExportFormat::TwoFas),
+            "andotp" => Ok(OtpExportFormat::AndOtp),
+            _ => Err(Error::new(&format!(
+                "Unknown export format '{}', expected aegis, 2fas or andotp",
+                value
+            ))),
+        }
+    }
+}
+
+impl Display for OtpExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OtpExportFormat::Aegis => write!(f, "Aegis"),
+            OtpExportFormat::TwoFas => write!(f, "2FAS"),
+            OtpExportFormat::AndOtp => write!(f, "andOTP"),
+        }
+    }
+}
+
+impl OtpExportFormat {
+    /// Recognizes an export by the shape of its JSON.
+    pub fn detect(json: &Value) -> Option<OtpExportFormat> {
+        if json.get("header").is_some() && json.get("db").is_some() {
+            Some(OtpExportFormat::Aegis)
+        } else if json.get("services").is_some() || json.get("servicesEncrypted").is_some() {
+            Some(OtpExportFormat::TwoFas)
+        } else if json.is_array() {
+            Some(OtpExportFormat::AndOtp)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the codes of an authorizer are generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OtpKind {
+    Totp,
+    Hotp(u64),
+    Steam,
+}
+
+impl OtpKind {
+    /// Parses the type names used by the exports, or `None` for variants that
+    /// Passlane cannot generate, such as mOTP and Yandex.
+    fn parse(kind: &str, counter: Option<u64>) -> Option<OtpKind> {
+        match kind.to_lowercase().as_str() {
+            "totp" => Some(OtpKind::Totp),
+            "hotp" => Some(OtpKind::Hotp(counter.unwrap_or(0))),
+            "steam" => Some(OtpKind::Steam),
+            _ => None,
+        }
+    }
+}
+
+/// Builds an authorizer with the same otpauth URL that `add -o` creates. The
+/// label is `issuer:account` unless the account already names the issuer.
+/// The secret must be valid base32, and the digits and period usable for
+/// generating codes.
+pub(crate) fn build_totp(
+    issuer: &str,
+    account: &str,
+    secret: &str,
+    algorithm: &str,
+    digits: u32,
+    period: u64,
+    kind: OtpKind,
+) -> Result<Totp, Error> {
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>()
+        .to_uppercase();
+    if secret.is_empty() {
+        return Err(Error::new(&format!("{} has no secret", account)));
+    }
+    if decode_secret(&secret).is_err() {
+        return Err(Error::new(&format!("{} has a secret that is not valid base32", account)));
+    }
+    if kind != OtpKind::Steam && !(1..=MAX_DIGITS).contains(&digits) {
+        return Err(Error::new(&format!(
+            "{} has {} digits, but codes must have 1 to {}",
+            account, digits, MAX_DIGITS
+        )));
+    }
+    if !matches!(kind, OtpKind::Hotp(_)) && period == 0 {
+        return Err(Error::new(&format!("{} has a period of 0 seconds", account)));
+    }
+    let algorithm = algorithm.to_uppercase();
+    if !["SHA1", "SHA256", "SHA512"].contains(&algorithm.as_str()) {
+        return Err(Error::new(&format!(
+            "{} uses the {} algorithm, which Passlane does not support",
+            account, algorithm
+        )));
+    }
+    let (issuer, account) = (issuer.trim(), account.trim());
+    let (label, issuer) = match account.split_once(':') {
+        Some((prefix, _)) if issuer.is_empty() => (account.to_string(), prefix.to_string()),
+        Some(_) => (account.to_string(), issuer.to_string()),
+        None if issuer.is_empty() => (account.to_string(), account.to_string()),
+        None if account.is_empty() => (issuer.to_string(), issuer.to_string()),
+        None => (format!("{}:{}", issuer, account), issuer.to_string()),
+    };
+    let (digits, url) = match kind {
+        OtpKind::Totp => (
+            digits,
+            format_totp_url(&label, &secret, &issuer, period, &algorithm, digits),
+        ),
+        OtpKind::Hotp(counter) => (
+            digits,
+            format_hotp_url(&label, &secret, &issuer, counter, &algorithm, digits),
+        ),
+        OtpKind::Steam => (
+            STEAM_DIGITS,
+            format_totp_url(&label, &secret, &issuer, period, &algorithm, STEAM_DIGITS)
+                + "&encoder=steam",
+        ),
+    };
+    Ok(Totp::new(
+        None, &url, &label, &issuer, &secret, &algorithm, period, digits, None,
+    ))
+}
+
+/// Parses an `otpauth://totp/` or `otpauth://hotp/` URL as described in the
+/// Key Uri Format. Parameters that Passlane does not use are kept in the URL.
+pub(crate) fn parse_otpauth_url(url: &str) -> Result<Totp, Error> {
+    let url = url.trim();
+    let invalid = || Error::new(&format!("Not a valid otpauth:// URL: {}", url));
+    let rest = url.strip_prefix("otpauth://").ok_or_else(invalid)?;
+    let (path, query) = rest.split_once('?').ok_or_else(invalid)?;
+    let (kind, label) = path.split_once('/').ok_or_else(invalid)?;
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query).map_err(|_| invalid())?;
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let number = |name: &str| -> Result<Option<u64>, Error> {
+        param(name)
+            .map(|value| value.parse::<u64>().map_err(|_| invalid()))
+            .transpose()
+    };
+    let counter = number("counter")?;
+    let is_steam = param("encoder").is_some_and(|encoder| encoder.eq_ignore_ascii_case("steam"));
+    let kind = match OtpKind::parse(kind, counter) {
+        Some(OtpKind::Totp) if is_steam => OtpKind::Steam,
+        Some(kind) => kind,
+        None => return Err(Error::new(&format!("Unsupported OTP type: {}", kind))),
+    };
+    let label = percent_encoding::percent_decode_str(label).decode_utf8_lossy();
+    let totp = build_totp(
+        param("issuer").unwrap_or(""),
+        &label,
+        param("secret").ok_or_else(|| Error::new("The otpauth:// URL has no secret"))?,
+        param("algorithm").unwrap_or("SHA1"),
+        number("digits")?.unwrap_or(DEFAULT_DIGITS as u64) as u32,
+        number("period")?.filter(|period| *period > 0).unwrap_or(DEFAULT_PERIOD),
+        kind,
+    )?;
+    Ok(Totp::new(
+        None,
+        &keep_unknown_otp_params(totp.url(), url),
+        totp.label(),
+        totp.issuer(),
+        totp.secret(),
+        totp.algorithm(),
+        totp.period(),
+        totp.digits(),
+        None,
+    ))
+}
+
+/// The authorizers in the text of a scanned QR code: an `otpauth://` URL, or
+/// an `otpauth-migration://` URI holding several accounts.
+pub(crate) fn parse_scanned(text: &str) -> Result<Vec<Totp>, Error> {
+    if text.trim().starts_with("otpauth-migration://") {
+        otp_migration::parse_migration_uri(text)
+    } else {
+        Ok(vec![parse_otpauth_url(text)?])
+    }
+}
+
+fn skip_unsupported(name: &str, kind: &str) {
+    eprintln!("Skipping {}: {} codes are not supported", name, kind);
+}
+
+/// The authorizer built from an export entry, or `None` after telling that the
+/// entry is skipped, so that one bad entry does not stop the whole import.
+fn skip_invalid(name: &str, totp: Result<Totp, Error>) -> Option<Totp> {
+    totp.inspect_err(|e| eprintln!("Skipping {}: {}", name, e.message)).ok()
+}
+
+#[derive(Deserialize)]
+struct AegisExport {
+    header: AegisHeader,
+    db: Value,
+}
+
+#[derive(Deserialize)]
+struct AegisHeader {
+    slots: Option<Vec<AegisSlot>>,
+    params: Option<AegisKeyParams>,
+}
+
+#[derive(Deserialize)]
+struct AegisSlot {
+    #[serde(rename = "type")]
+    slot_type: u8,
+    key: String,
+    key_params: AegisKeyParams,
+    n: Option<u64>,
+    r: Option<u32>,
+    p: Option<u32>,
+    salt: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AegisKeyParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct AegisDb {
+    entries: Vec<AegisEntry>,
+}
+
+#[derive(Deserialize)]
+struct AegisEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    #[serde(default)]
+    issuer: String,
+    info: AegisInfo,
+}
+
+#[derive(Deserialize)]
+struct AegisInfo {
+    secret: String,
+    algo: Option<String>,
+    digits: Option<u32>,
+    period: Option<u64>,
+    counter: Option<u64>,
+}
+
+fn hex_field(value: &str, name: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|_| Error::new(&format!("Invalid {} in the Aegis vault", name)))
+}
+
+/// AES-256-GCM decryption of `ciphertext` with the separately stored `tag`.
+fn aes_gcm_decrypt(key: &[u8], params: &AegisKeyParams, ciphertext: &[u8]) -> Option<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let nonce = hex::decode(&params.nonce).ok().filter(|nonce| nonce.len() == 12)?;
+    let mut sealed = ciphertext.to_vec();
+    sealed.extend(hex::decode(&params.tag).ok()?);
+    cipher.decrypt(Nonce::from_slice(&nonce), sealed.as_slice()).ok()
+}
+
+impl AegisSlot {
+    /// The vault's master key, if `password` opens this slot.
+    fn master_key(&self, password: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (Some(n), Some(r), Some(p), Some(salt)) = (self.n, self.r, self.p, &self.salt) else {
+            return Err(Error::new("The Aegis password slot has no scrypt parameters"));
+        };
+        if !n.is_power_of_two() || n < 2 {
+            return Err(Error::new("Invalid scrypt parameters in the Aegis vault"));
+        }
+        if n.saturating_mul(u64::from(r)).saturating_mul(128) > MAX_SCRYPT_MEMORY || p > MAX_SCRYPT_P {
+            return Err(Error::new(
+                "The scrypt parameters of the Aegis vault ask for more memory or work than Passlane allows",
+            ));
+        }
+        let params = scrypt::Params::new(n.trailing_zeros() as u8, r, p, 32)
+            .map_err(|_| Error::new("Invalid scrypt parameters in the Aegis vault"))?;
+        let mut derived = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), &hex_field(salt, "salt")?, &params, &mut derived)
+            .map_err(|_| Error::new("Failed to derive the Aegis vault key"))?;
+        Ok(aes_gcm_decrypt(&derived, &self.key_params, &hex_field(&self.key, "key")?))
+    }
+}
+
+impl AegisExport {
+    fn is_encrypted(&self) -> bool {
+        self.db.is_string()
+    }
+
+    fn decrypted_db(self, password: Option<&str>) -> Result<AegisDb, Error> {
+        let Value::String(encrypted) = &self.db else {
+            return serde_json::from_value(self.db)
+                .map_err(|e| Error::new(&format!("Invalid Aegis export: {}", e)));
+        };
+        let password = password.ok_or_else(|| Error::new("The Aegis vault is encrypted"))?;
+        let params = self
+            .header
+            .params
+            .as_ref()
+            .ok_or_else(|| Error::new("The Aegis vault has no encryption parameters"))?;
+        let mut master_key = None;
+        for slot in self.header.slots.iter().flatten() {
+            if slot.slot_type == AEGIS_PASSWORD_SLOT {
+                master_key = slot.master_key(password)?;
+                if master_key.is_some() {
+                    break;
+                }
+            }
+        }
+        let master_key =
+            master_key.ok_or_else(|| Error::new("Wrong password for the Aegis vault"))?;
+        let ciphertext = STANDARD
+            .decode(encrypted)
+            .map_err(|_| Error::new("The Aegis vault content is not valid base64"))?;
+        let plaintext = aes_gcm_decrypt(&master_key, params, &ciphertext)
+            .ok_or_else(|| Error::new("Failed to decrypt the Aegis vault"))?;
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| Error::new(&format!("Invalid Aegis vault content: {}", e)))
+    }
+}
+
+fn parse_aegis(json: Value, password: Option<&str>) -> Result<Vec<Totp>, Error> {
+    let export: AegisExport = serde_json::from_value(json)
+        .map_err(|e| Error::new(&format!("Invalid Aegis export: {}", e)))?;
+    let mut totps = Vec::new();
+    for entry in export.decrypted_db(password)?.entries {
+        let info = &entry.info;
+        let Some(kind) = OtpKind::parse(&entry.kind, info.counter) else {
+            skip_unsupported(&entry.name, &entry.kind);
+            continue;
+        };
+        let totp = build_totp(
+            &entry.issuer,
+            &entry.name,
+            &info.secret,
+            info.algo.as_deref().unwrap_or("SHA1"),
+            info.digits.unwrap_or(DEFAULT_DIGITS),
+            info.period.unwrap_or(DEFAULT_PERIOD),
+            kind,
+        );
+        totps.extend(skip_invalid(&entry.name, totp));
+    }
+    Ok(totps)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TwoFasExport {
+    #[serde(default)]
+    services: Vec<TwoFasService>,
+    services_encrypted: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TwoFasService {
+    name: String,
+    secret: String,
+    otp: TwoFasOtp,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TwoFasOtp {
+    account: Option<String>,
+    label: Option<String>,
+    issuer: Option<String>,
+    digits: Option<u32>,
+    period: Option<u64>,
+    algorithm: Option<String>,
+    token_type: Option<String>,
+    counter: Option<u64>,
+}
+
+fn parse_two_fas(json: Value) -> Result<Vec<Totp>, Error> {
+    let export: TwoFasExport = serde_json::from_value(json)
+        .map_err(|e| Error::new(&format!("Invalid 2FAS export: {}", e)))?;
+    if export.services.is_empty() && export.services_encrypted.is_some() {
+        return Err(Error::new(
+            "Encrypted 2FAS backups are not supported, export again without a password",
+        ));
+    }
+    let mut totps = Vec::new();
+    for service in export.services {
+        let otp = &service.otp;
+        let kind = otp.token_type.as_deref().unwrap_or("TOTP");
+        let Some(kind) = OtpKind::parse(kind, otp.counter) else {
+            skip_unsupported(&service.name, kind);
+            continue;
+        };
+        let account = otp.account.as_deref().or(otp.label.as_deref()).unwrap_or("");
+        let totp = build_totp(
+            otp.issuer.as_deref().unwrap_or(&service.name),
+            account,
+            &service.secret,
+            otp.algorithm.as_deref().unwrap_or("SHA1"),
+            otp.digits.unwrap_or(DEFAULT_DIGITS),
+            otp.period.unwrap_or(DEFAULT_PERIOD),
+            kind,
+        );
+        totps.extend(skip_invalid(&service.name, totp));
+    }
+    Ok(totps)
+}
+
+#[derive(Deserialize)]
+struct AndOtpEntry {
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    label: String,
+    digits: Option<u32>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    algorithm: Option<String>,
+    period: Option<u64>,
+    counter: Option<u64>,
+}
+
+fn parse_and_otp(json: Value) -> Result<Vec<Totp>, Error> {
+    let entries: Vec<AndOtpEntry> = serde_json::from_value(json)
+        .map_err(|e| Error::new(&format!("Invalid andOTP export: {}", e)))?;
+    let mut totps = Vec::new();
+    for entry in entries {
+        let kind = entry.kind.as_deref().unwrap_or("TOTP");
+        let Some(kind) = OtpKind::parse(kind, entry.counter) else {
+            skip_unsupported(&entry.label, kind);
+            continue;
+        };
+        let totp = build_totp(
+            &entry.issuer,
+            &entry.label,
+            &entry.secret,
+            entry.algorithm.as_deref().unwrap_or("SHA1"),
+            entry.digits.unwrap_or(DEFAULT_DIGITS),
+            entry.period.unwrap_or(DEFAULT_PERIOD),
+            kind,
+        );
+        totps.extend(skip_invalid(&entry.label, totp));
+    }
+    Ok(totps)
+}
+
+/// Whether `content` is an encrypted Aegis vault, which needs a password.
+pub(crate) fn needs_password(content: &str) -> bool {
+    serde_json::from_str::<AegisExport>(content).is_ok_and(|export| export.is_encrypted())
+}
+
+/// Reads the authorizers of an export. The format is detected unless given;
+/// `password` opens an encrypted Aegis vault.
+pub(crate) fn parse_export(
+    content: &str,
+    format: Option<OtpExportFormat>,
+    password: Option<&str>,
+) -> Result<Vec<Totp>, Error> {
+    let json: Value = serde_json::from_str(content)
+        .map_err(|e| Error::new(&format!("The export is not valid JSON: {}", e)))?;
+    let format = format
+        .or_else(|| OtpExportFormat::detect(&json))
+        .ok_or_else(|| Error::new("Unknown export format, use --format to name it"))?;
+    match format {
+        OtpExportFormat::Aegis => parse_aegis(json, password),
+        OtpExportFormat::TwoFas => parse_two_fas(json),
+        OtpExportFormat::AndOtp => parse_and_otp(json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXP";
+
+    fn aegis_db() -> Value {
+        serde_json::json!({
+            "version": 2,
+            "entries": [
+                {"type": "totp", "uuid": "1", "name": "alice@example.com", "issuer": "GitHub", "note": "",
+                 "info": {"secret": SECRET, "algo": "SHA256", "digits": 8, "period": 60}},
+                {"type": "hotp", "uuid": "2", "name": "bob", "issuer": "VPN",
+                 "info": {"secret": SECRET, "algo": "SHA1", "digits": 6, "counter": 7}},
+                {"type": "steam", "uuid": "3", "name": "gabe", "issuer": "Steam",
+                 "info": {"secret": SECRET, "algo": "SHA1", "digits": 5, "period": 30}},
+                {"type": "motp", "uuid": "4", "name": "old", "issuer": "Bank",
+                 "info": {"secret": "abcdef", "algo": "MD5", "digits": 6, "period": 10, "pin": "1234"}}
+            ]
+        })
+    }
+
+    fn assert_aegis_entries(totps: &[Totp]) {
+        assert_eq!(totps.len(), 3);
+        assert_eq!(totps[0].label(), "GitHub:alice@example.com");
+        assert_eq!(totps[0].issuer(), "GitHub");
+        assert_eq!(totps[0].algorithm(), "SHA256");
+        assert_eq!(totps[0].digits(), 8);
+        assert_eq!(totps[0].period(), 60);
+        assert_eq!(totps[1].counter(), Some(7));
+        assert!(totps[2].is_steam());
+        assert_eq!(totps[2].digits(), 5);
+    }
+
+    fn encrypt(key: &[u8], nonce: &[u8], plaintext: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut sealed = Aes256Gcm::new_from_slice(key)
+            .unwrap()
+            .encrypt(Nonce::from_slice(nonce), plaintext)
+            .unwrap();
+        let tag = sealed.split_off(sealed.len() - 16);
+        (sealed, tag)
+    }
+
+    fn encrypted_aegis(password: &str) -> String {
+        let (n, r, p, salt) = (16u64, 8, 1, [7u8; 32]);
+        let mut derived = [0u8; 32];
+        let params = scrypt::Params::new(4, r, p, 32).unwrap();
+        scrypt::scrypt(password.as_bytes(), &salt, &params, &mut derived).unwrap();
+        let master_key = [42u8; 32];
+        let (key, key_tag) = encrypt(&derived, &[1; 12], &master_key);
+        let (db, db_tag) = encrypt(&master_key, &[2; 12], aegis_db().to_string().as_bytes());
+        serde_json::json!({
+            "version": 1,
+            "header": {
+                "slots": [{
+                    "type": 1, "uuid": "x", "key": hex::encode(key),
+                    "key_params": {"nonce": hex::encode([1u8; 12]), "tag": hex::encode(key_tag)},
+                    "n": n, "r": r, "p": p, "salt": hex::encode(salt), "repaired": true
+                }],
+                "params": {"nonce": hex::encode([2u8; 12]), "tag": hex::encode(db_tag)}
+            },
+            "db": STANDARD.encode(db)
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn imports_plain_aegis_exports() {
+        let export = serde_json::json!({
+            "version": 1,
+            "header": {"slots": null, "params": null},
+            "db": aegis_db()
+        })
+        .to_string();
+        assert!(!needs_password(&export));
+        assert_aegis_entries(&parse_export(&export, None, None).unwrap());
+    }
+
+    #[test]
+    fn imports_encrypted_aegis_vaults() {
+        let export = encrypted_aegis("correct horse");
+        assert!(needs_password(&export));
+        assert_aegis_entries(&parse_export(&export, None, Some("correct horse")).unwrap());
+        assert!(parse_export(&export, None, Some("wrong")).is_err());
+        assert!(parse_export(&export, None, None).is_err());
+    }
+
+    #[test]
+    fn imports_two_fas_exports() {
+        let export = serde_json::json!({
+            "schemaVersion": 4,
+            "appVersionCode": 5000000,
+            "services": [
+                {"name": "GitHub", "secret": SECRET, "updatedAt": 1,
+                 "otp": {"label": "alice", "account": "alice", "issuer": "GitHub", "digits": 6,
+                         "period": 30, "algorithm": "SHA1", "tokenType": "TOTP", "source": "Link"},
+                 "order": {"position": 0}},
+                {"name": "Steam", "secret": SECRET,
+                 "otp": {"account": "gabe", "digits": 5, "period": 30, "algorithm": "SHA1", "tokenType": "STEAM"}}
+            ]
+        })
+        .to_string();
+        let totps = parse_export(&export, None, None).unwrap();
+        assert_eq!(totps.len(), 2);
+        assert_eq!(totps[0].label(), "GitHub:alice");
+        assert_eq!(totps[1].label(), "Steam:gabe");
+        assert!(totps[1].is_steam());
+
+        let encrypted = r#"{"services": [], "servicesEncrypted": "abc:def:ghi", "schemaVersion": 4}"#;
+        assert!(parse_export(encrypted, None, None).is_err());
+    }
+
+    #[test]
+    fn imports_and_otp_exports() {
+        let export = serde_json::json!([
+            {"secret": SECRET, "issuer": "GitLab", "label": "carol", "digits": 6, "type": "TOTP",
+             "algorithm": "SHA512", "thumbnail": "Default", "last_used": 0, "period": 30, "tags": []},
+            {"secret": SECRET, "issuer": "", "label": "VPN:dave", "digits": 8, "type": "HOTP",
+             "algorithm": "SHA1", "counter": 3, "tags": []}
+        ])
+        .to_string();
+        let totps = parse_export(&export, Some(OtpExportFormat::AndOtp), None).unwrap();
+        assert_eq!(totps[0].label(), "GitLab:carol");
+        assert_eq!(totps[0].algorithm(), "SHA512");
+        assert_eq!(totps[1].issuer(), "VPN");
+        assert_eq!(totps[1].counter(), Some(3));
+        assert_eq!(totps[1].digits(), 8);
+    }
+
+    #[test]
+    fn built_urls_generate_codes() {
+        let totp = build_totp("Example", "me", "jbsw y3dp ehpk 3pxp", "sha1", 6, 30, OtpKind::Totp).unwrap();
+        assert_eq!(totp.secret(), SECRET);
+        assert_eq!(
+            totp.url(),
+            format_totp_url("Example:me", SECRET, "Example", 30, "SHA1", 6)
+        );
+        assert_eq!(totp.get_code().unwrap().value.len(), 6);
+        assert!(build_totp("x", "y", "", "SHA1", 6, 30, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", SECRET, "MD5", 6, 30, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", "not base32!", "SHA1", 6, 30, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", SECRET, "SHA1", 0, 30, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", SECRET, "SHA1", 11, 30, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", SECRET, "SHA1", 6, 0, OtpKind::Totp).is_err());
+        assert!(build_totp("x", "y", SECRET, "SHA1", 6, 0, OtpKind::Hotp(1)).is_ok());
+    }
+
+    #[test]
+    fn skips_invalid_entries() {
+        let mut db = aegis_db();
+        let entries = db["entries"].as_array_mut().unwrap();
+        entries.push(serde_json::json!({"type": "totp", "uuid": "5", "name": "zero", "issuer": "x",
+            "info": {"secret": SECRET, "algo": "SHA1", "digits": 6, "period": 0}}));
+        entries.push(serde_json::json!({"type": "totp", "uuid": "6", "name": "bad", "issuer": "x",
+            "info": {"secret": "18!", "algo": "SHA1", "digits": 6, "period": 30}}));
+        entries.push(serde_json::json!({"type": "hotp", "uuid": "7", "name": "long", "issuer": "x",
+            "info": {"secret": SECRET, "algo": "SHA1", "digits": 12, "counter": 1}}));
+        let export = serde_json::json!({"version": 1, "header": {"slots": null, "params": null}, "db": db});
+        assert_aegis_entries(&parse_export(&export.to_string(), None, None).unwrap());
+    }
+
+    #[test]
+    fn rejects_costly_scrypt_parameters() {
+        for (n, r, p) in [(1u64 << 22, 8, 1), (1 << 15, 8, 64), (1 << 62, u32::MAX, 1)] {
+            let mut export: Value = serde_json::from_str(&encrypted_aegis("pw")).unwrap();
+            let slot = &mut export["header"]["slots"][0];
+            slot["n"] = n.into();
+            slot["r"] = r.into();
+            slot["p"] = p.into();
+            let error = parse_export(&export.to_string(), None, Some("pw")).err().unwrap();
+            assert!(error.message.contains("more memory or work"), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn parses_otpauth_urls() {
+        let totp = parse_otpauth_url(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60&image=https%3A%2F%2Fexample.com%2Flogo.png",
+        )
+        .unwrap();
+        assert_eq!(totp.label(), "ACME Co:john.doe@email.com");
+        assert_eq!(totp.issuer(), "ACME Co");
+        assert_eq!(totp.secret(), "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ");
+        assert_eq!(totp.algorithm(), "SHA256");
+        assert_eq!(totp.digits(), 8);
+        assert_eq!(totp.period(), 60);
+        assert!(totp.url().ends_with("&image=https%3A%2F%2Fexample.com%2Flogo.png"));
+
+        let minimal = parse_otpauth_url("otpauth://totp/alice?secret=jbswy3dpehpk3pxp").unwrap();
+        assert_eq!((minimal.label(), minimal.issuer()), ("alice", "alice"));
+        assert_eq!((minimal.algorithm(), minimal.digits(), minimal.period()), ("SHA1", 6, 30));
+
+        let hotp = parse_otpauth_url("otpauth://hotp/VPN:bob?secret=JBSWY3DPEHPK3PXP&counter=4").unwrap();
+        assert_eq!(hotp.counter(), Some(4));
+        let steam = parse_otpauth_url("otpauth://totp/Steam:gabe?secret=JBSWY3DPEHPK3PXP&encoder=steam").unwrap();
+        assert!(steam.is_steam());
+        assert_eq!(steam.digits(), 5);
+
+        assert!(parse_otpauth_url("https://example.com/?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_otpauth_url("otpauth://totp/alice?issuer=x").is_err());
+        assert!(parse_otpauth_url("otpauth://motp/alice?secret=JBSWY3DPEHPK3PXP").is_err());
+        assert!(parse_otpauth_url("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&digits=six").is_err());
+    }
+
+    #[test]
+    fn parses_scanned_migration_uris() {
+        assert_eq!(parse_scanned("otpauth://totp/a?secret=JBSWY3DPEHPK3PXP").unwrap().len(), 1);
+        // One account, "b" with the secret "Hello" and default parameters.
+        let totps = parse_scanned("otpauth-migration://offline?data=CgoKBUhlbGxvEgFi").unwrap();
+        assert_eq!(totps.len(), 1);
+        assert_eq!(totps[0].label(), "b");
+        assert_eq!(totps[0].secret(), "JBSWY3DP");
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert!(parse_export("{}", None, None).is_err());
+        assert!(parse_export("not json", None, None).is_err());
+        assert_eq!("2FAS".parse::<OtpExportFormat>().unwrap(), OtpExportFormat::TwoFas);
+        assert!("lastpass".parse::<OtpExportFormat>().is_err());
+    }
+}
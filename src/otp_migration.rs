@@ -0,0 +1,341 @@
+//! Decoder for the `otpauth-migration://offline?data=...` URIs that Google
+//! Authenticator shows as QR codes when exporting accounts. The data is a
+//! base64 encoded protobuf `MigrationPayload`:
+//!
+//! ```text
+//! message MigrationPayload {
+//!   repeated OtpParameters otp_parameters = 1;
+//!   int32 version = 2; int32 batch_size = 3; int32 batch_index = 4; int32 batch_id = 5;
+//! }
+//! message OtpParameters {
+//!   bytes secret = 1; string name = 2; string issuer = 3;
+//!   Algorithm algorithm = 4;  // 1 SHA1, 2 SHA256, 3 SHA512, 4 MD5
+//!   DigitCount digits = 5;    // 1 six, 2 eight
+//!   OtpType type = 6;         // 1 HOTP, 2 TOTP
+//!   int64 counter = 7;
+//! }
+//! ```
+//!
+//! Only the few protobuf wire types the payload uses are decoded, so no
+//! protobuf library is needed.
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use percent_encoding::percent_decode_str;
+
+use crate::otp_import::{build_totp, OtpKind};
+use crate::vault::entities::{Error, Totp};
+
+const SCHEME: &str = "otpauth-migration://";
+const DEFAULT_PERIOD: u64 = 30;
+
+/// A decoded protobuf field value.
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| Error::new("Truncated migration data"))?;
+            self.position += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::new("Invalid varint in migration data"))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::new("Truncated migration data"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// The next field number and value, or `None` at the end of the message.
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>, Error> {
+        if self.position == self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x07 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let length = self.varint()? as usize;
+                Value::Bytes(self.take(length)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            wire_type => {
+                return Err(Error::new(&format!(
+                    "Unsupported protobuf wire type {} in migration data",
+                    wire_type
+                )));
+            }
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+/// One account of a migration payload, as stored by Google Authenticator.
+#[derive(Debug, Default, PartialEq)]
+struct OtpParameters {
+    secret: Vec<u8>,
+    name: String,
+    issuer: String,
+    algorithm: u64,
+    digits: u64,
+    otp_type: u64,
+    counter: u64,
+}
+
+fn string(bytes: &[u8]) -> Result<String, Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::new("Invalid text in migration data"))
+}
+
+fn decode_otp_parameters(data: &[u8]) -> Result<OtpParameters, Error> {
+    let mut reader = Reader::new(data);
+    let mut params = OtpParameters::default();
+    while let Some((number, value)) = reader.field()? {
+        match (number, value) {
+            (1, Value::Bytes(bytes)) => params.secret = bytes.to_vec(),
+            (2, Value::Bytes(bytes)) => params.name = string(bytes)?,
+            (3, Value::Bytes(bytes)) => params.issuer = string(bytes)?,
+            (4, Value::Varint(value)) => params.algorithm = value,
+            (5, Value::Varint(value)) => params.digits = value,
+            (6, Value::Varint(value)) => params.otp_type = value,
+            (7, Value::Varint(value)) => params.counter = value,
+            _ => {}
+        }
+    }
+    Ok(params)
+}
+
+fn decode_payload(data: &[u8]) -> Result<Vec<OtpParameters>, Error> {
+    let mut reader = Reader::new(data);
+    let mut accounts = Vec::new();
+    while let Some((number, value)) = reader.field()? {
+        if let (1, Value::Bytes(bytes)) = (number, value) {
+            accounts.push(decode_otp_parameters(bytes)?);
+        }
+    }
+    Ok(accounts)
+}
+
+impl OtpParameters {
+    fn to_totp(&self) -> Result<Totp, Error> {
+        let algorithm = match self.algorithm {
+            0 | 1 => "SHA1",
+            2 => "SHA256",
+            3 => "SHA512",
+            _ => "MD5",
+        };
+        let digits = match self.digits {
+            2 => 8,
+            _ => 6,
+        };
+        let kind = match self.otp_type {
+            1 => OtpKind::Hotp(self.counter),
+            _ => OtpKind::Totp,
+        };
+        let secret = base32::encode(base32::Alphabet::Rfc4648 { padding: false }, &self.secret);
+        build_totp(&self.issuer, &self.name, &secret, algorithm, digits, DEFAULT_PERIOD, kind)
+    }
+}
+
+/// Decodes the accounts of one `otpauth-migration://offline?data=...` URI.
+pub(crate) fn parse_migration_uri(uri: &str) -> Result<Vec<Totp>, Error> {
+    let uri = uri.trim();
+    if !uri.starts_with(SCHEME) {
+        return Err(Error::new(&format!(
+            "Not a Google Authenticator export: expected a URI starting with {}",
+            SCHEME
+        )));
+    }
+    let data = uri
+        .split_once('?')
+        .and_then(|(_, query)| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| *name == "data")
+        })
+        .map(|(_, data)| percent_decode_str(data).decode_utf8_lossy().replace(' ', "+"))
+        .ok_or_else(|| Error::new("The migration URI has no data parameter"))?;
+    let bytes = STANDARD
+        .decode(&data)
+        .or_else(|_| URL_SAFE.decode(&data))
+        .map_err(|_| Error::new("The migration data is not valid base64"))?;
+    decode_payload(&bytes)?
+        .iter()
+        .map(OtpParameters::to_totp)
+        .collect()
+}
+
+/// Decodes a migration URI, or a file with one URI per line as produced by
+/// scanning every QR code of a multi-part export.
+pub(crate) fn read_migration(source: &str) -> Result<Vec<Totp>, Error> {
+    if source.trim().starts_with(SCHEME) {
+        return parse_migration_uri(source);
+    }
+    let content = std::fs::read_to_string(source)
+        .map_err(|e| Error::new(&format!("Cannot read {}: {}", source, e)))?;
+    let mut totps = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        totps.extend(parse_migration_uri(line)?);
+    }
+    Ok(totps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes_field(number: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(number << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend_from_slice(bytes);
+    }
+
+    fn varint_field(number: u64, value: u64, out: &mut Vec<u8>) {
+        varint(number << 3, out);
+        varint(value, out);
+    }
+
+    fn account(params: &OtpParameters) -> Vec<u8> {
+        let mut out = Vec::new();
+        bytes_field(1, &params.secret, &mut out);
+        bytes_field(2, params.name.as_bytes(), &mut out);
+        bytes_field(3, params.issuer.as_bytes(), &mut out);
+        varint_field(4, params.algorithm, &mut out);
+        varint_field(5, params.digits, &mut out);
+        varint_field(6, params.otp_type, &mut out);
+        varint_field(7, params.counter, &mut out);
+        out
+    }
+
+    fn payload(accounts: &[OtpParameters]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for params in accounts {
+            bytes_field(1, &account(params), &mut out);
+        }
+        varint_field(2, 1, &mut out);
+        varint_field(3, 1, &mut out);
+        // batch_id is a negative int32 in real exports: ten bytes on the wire.
+        varint_field(5, -1544230394i64 as u64, &mut out);
+        out
+    }
+
+    fn params(name: &str, issuer: &str, algorithm: u64, digits: u64, otp_type: u64) -> OtpParameters {
+        OtpParameters {
+            secret: b"12345678901234567890".to_vec(),
+            name: name.to_string(),
+            issuer: issuer.to_string(),
+            algorithm,
+            digits,
+            otp_type,
+            counter: if otp_type == 1 { 5 } else { 0 },
+        }
+    }
+
+    #[test]
+    fn decodes_payload_fields() {
+        let accounts = vec![
+            params("alice@example.com", "GitHub", 1, 1, 2),
+            params("VPN:bob", "", 2, 2, 1),
+        ];
+        assert_eq!(decode_payload(&payload(&accounts)).unwrap(), accounts);
+        assert!(decode_payload(&payload(&accounts)[..20]).is_err());
+    }
+
+    #[test]
+    fn maps_accounts_to_authorizers() {
+        let data = STANDARD.encode(payload(&[
+            params("alice@example.com", "GitHub", 1, 1, 2),
+            params("VPN:bob", "", 2, 2, 1),
+            params("carol", "", 3, 0, 0),
+        ]));
+        let uri = format!(
+            "otpauth-migration://offline?data={}",
+            data.replace('+', "%2B").replace('/', "%2F").replace('=', "%3D")
+        );
+        let totps = parse_migration_uri(&uri).unwrap();
+        assert_eq!(totps.len(), 3);
+
+        assert_eq!(totps[0].label(), "GitHub:alice@example.com");
+        assert_eq!(totps[0].issuer(), "GitHub");
+        assert_eq!(totps[0].secret(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(totps[0].digits(), 6);
+        assert!(!totps[0].is_hotp());
+
+        assert_eq!(totps[1].label(), "VPN:bob");
+        assert_eq!(totps[1].issuer(), "VPN");
+        assert_eq!(totps[1].algorithm(), "SHA256");
+        assert_eq!(totps[1].digits(), 8);
+        assert_eq!(totps[1].counter(), Some(5));
+
+        assert_eq!(totps[2].label(), "carol");
+        assert_eq!(totps[2].algorithm(), "SHA512");
+        assert_eq!(totps[2].get_code().unwrap().value.len(), 6);
+    }
+
+    #[test]
+    fn rejects_unsupported_input() {
+        assert!(parse_migration_uri("otpauth://totp/x?secret=ABC").is_err());
+        assert!(parse_migration_uri("otpauth-migration://offline?other=1").is_err());
+        assert!(parse_migration_uri("otpauth-migration://offline?data=!!!").is_err());
+        let md5 = STANDARD.encode(payload(&[params("x", "y", 4, 1, 2)]));
+        assert!(parse_migration_uri(&format!("otpauth-migration://offline?data={}", md5)).is_err());
+    }
+
+    #[test]
+    fn reads_one_uri_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt");
+        let first = STANDARD.encode(payload(&[params("a", "A", 1, 1, 2)]));
+        let second = STANDARD.encode(payload(&[params("b", "B", 1, 1, 2), params("c", "C", 1, 1, 2)]));
+        std::fs::write(
+            &path,
+            format!("otpauth-migration://offline?data={}\n\notpauth-migration://offline?data={}\n", first, second),
+        )
+        .unwrap();
+        let totps = read_migration(path.to_str().unwrap()).unwrap();
+        assert_eq!(totps.iter().map(|t| t.label()).collect::<Vec<_>>(), vec!["A:a", "B:b", "C:c"]);
+        assert!(read_migration("/nonexistent/export.txt").is_err());
+    }
+}
@@ -0,0 +1,449 @@
+//! Parser for the `passwordrules` language that sites use to describe which
+//! passwords they accept, e.g. `minlength: 12; required: upper; allowed: [-_.]`.
+//! See <https://developer.apple.com/password-rules/> for the syntax.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::vault::entities::Error;
+
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGIT: &str = "0123456789";
+const SPECIAL: &str = "-~!@#$%^&*_+=`|(){}[:;\"'<>,.?] ";
+
+/// Rules for common sites, from their published password requirements.
+/// Matched against the service's host name, including subdomains.
+const KNOWN_SITE_RULES: &[(&str, &str)] = &[
+    ("apple.com", "minlength: 8; maxlength: 63; required: lower; required: upper; required: digit; allowed: ascii-printable;"),
+    ("icloud.com", "minlength: 8; maxlength: 63; required: lower; required: upper; required: digit; allowed: ascii-printable;"),
+    ("paypal.com", "minlength: 8; maxlength: 20; max-consecutive: 3; required: lower, upper; required: digit, [!@#$%^&*()];"),
+    ("americanexpress.com", "minlength: 8; maxlength: 20; max-consecutive: 4; required: lower, upper; required: digit; allowed: [%&_?#=];"),
+    ("bankofamerica.com", "minlength: 8; maxlength: 20; max-consecutive: 3; required: lower; required: upper; required: digit; allowed: [-@#*()+={}/?~;,._];"),
+    ("chase.com", "minlength: 8; maxlength: 32; max-consecutive: 2; required: lower, upper; required: digit; required: [!#$%+/=@~];"),
+    ("dropbox.com", "minlength: 8; allowed: ascii-printable;"),
+    ("ebay.com", "minlength: 6; maxlength: 64; required: lower, upper; required: digit;"),
+];
+
+/// A set of characters named in a rule.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CharClass {
+    Upper,
+    Lower,
+    Digit,
+    Special,
+    AsciiPrintable,
+    Unicode,
+    Custom(String),
+}
+
+impl CharClass {
+    /// The characters of this class that a generator may pick. Unicode is
+    /// narrowed to printable ASCII, and spaces are never generated.
+    pub fn generator_chars(&self) -> String {
+        match self {
+            CharClass::Upper => UPPER.to_string(),
+            CharClass::Lower => LOWER.to_string(),
+            CharClass::Digit => DIGIT.to_string(),
+            CharClass::Special => SPECIAL.to_string(),
+            CharClass::AsciiPrintable | CharClass::Unicode => (' '..='~').collect(),
+            CharClass::Custom(chars) => chars.clone(),
+        }
+        .chars()
+        .filter(|c| *c != ' ')
+        .collect()
+    }
+
+    fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Upper => UPPER.contains(c),
+            CharClass::Lower => LOWER.contains(c),
+            CharClass::Digit => DIGIT.contains(c),
+            CharClass::Special => SPECIAL.contains(c),
+            CharClass::AsciiPrintable => (' '..='~').contains(&c),
+            CharClass::Unicode => true,
+            CharClass::Custom(chars) => chars.contains(c),
+        }
+    }
+}
+
+impl Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CharClass::Upper => write!(f, "upper"),
+            CharClass::Lower => write!(f, "lower"),
+            CharClass::Digit => write!(f, "digit"),
+            CharClass::Special => write!(f, "special"),
+            CharClass::AsciiPrintable => write!(f, "ascii-printable"),
+            CharClass::Unicode => write!(f, "unicode"),
+            CharClass::Custom(chars) => {
+                // A hyphen is only literal first and a bracket only last.
+                let mut ordered = String::new();
+                if chars.contains('-') {
+                    ordered.push('-');
+                }
+                ordered.extend(chars.chars().filter(|c| *c != '-' && *c != ']'));
+                if chars.contains(']') {
+                    ordered.push(']');
+                }
+                write!(f, "[{}]", ordered)
+            }
+        }
+    }
+}
+
+/// Parsed password rules. Each entry of `required` is a list of classes of
+/// which the password must contain at least one character.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PasswordRules {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub max_consecutive: Option<usize>,
+    pub required: Vec<Vec<CharClass>>,
+    pub allowed: Vec<CharClass>,
+}
+
+impl PasswordRules {
+    /// The built-in rules for the site named by `service`, which may be a
+    /// host name or a URL.
+    pub fn for_service(service: &str) -> Option<PasswordRules> {
+        let host = host_name(service);
+        KNOWN_SITE_RULES
+            .iter()
+            .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            .map(|(_, rules)| rules.parse().expect("built-in password rules are valid"))
+    }
+
+    /// The classes that characters may be drawn from: everything required or
+    /// allowed, or all printable ASCII when the rules name no classes.
+    pub fn allowed_classes(&self) -> Vec<CharClass> {
+        let mut classes: Vec<CharClass> = self.required.iter().flatten().cloned().collect();
+        classes.extend(self.allowed.iter().cloned());
+        if classes.is_empty() {
+            classes.push(CharClass::AsciiPrintable);
+        }
+        classes
+    }
+
+    /// Whether `password` satisfies every rule.
+    pub fn accepts(&self, password: &str) -> bool {
+        let length = password.chars().count();
+        let allowed = self.allowed_classes();
+        self.min_length.is_none_or(|min| length >= min)
+            && self.max_length.is_none_or(|max| length <= max)
+            && self
+                .max_consecutive
+                .is_none_or(|max| longest_run(password) <= max)
+            && self
+                .required
+                .iter()
+                .all(|classes| password.chars().any(|c| classes.iter().any(|cl| cl.contains(c))))
+            && password
+                .chars()
+                .all(|c| allowed.iter().any(|cl| cl.contains(c)))
+    }
+}
+
+/// Length of the longest run of one repeated character.
+pub fn longest_run(value: &str) -> usize {
+    let chars: Vec<char> = value.chars().collect();
+    chars
+        .chunk_by(|a, b| a == b)
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// The lowercase host of `service`, which may be a host name or a URL.
+pub(crate) fn host_name(service: &str) -> String {
+    let without_scheme = service.split_once("://").map_or(service, |(_, rest)| rest);
+    let host = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or("");
+    let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+    let host = host.split(':').next().unwrap_or("");
+    host.trim_end_matches('.').to_lowercase()
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, Error> {
+    value.trim().parse().map_err(|_| {
+        Error::new(&format!(
+            "Password rule '{}' needs a whole number, got '{}'",
+            name,
+            value.trim()
+        ))
+    })
+}
+
+fn parse_classes(value: &str) -> Result<Vec<CharClass>, Error> {
+    let mut classes = Vec::new();
+    let mut chars = value.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        if first == '[' {
+            chars.next();
+            let mut custom = String::new();
+            loop {
+                match chars.next() {
+                    // A bracket followed by another one is a literal bracket.
+                    Some(']') if chars.peek() == Some(&']') => {
+                        custom.push(']');
+                        chars.next();
+                        break;
+                    }
+                    Some(']') => break,
+                    Some(c) if (' '..='~').contains(&c) => {
+                        if !custom.contains(c) {
+                            custom.push(c);
+                        }
+                    }
+                    Some(c) => {
+                        return Err(Error::new(&format!(
+                            "Password rules may only list printable ASCII characters in brackets, got {:?}",
+                            c
+                        )));
+                    }
+                    None => return Err(Error::new("Unclosed '[' in password rules")),
+                }
+            }
+            if !custom.is_empty() {
+                classes.push(CharClass::Custom(custom));
+            }
+        } else {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c.is_whitespace() {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            classes.push(match name.to_lowercase().as_str() {
+                "upper" => CharClass::Upper,
+                "lower" => CharClass::Lower,
+                "digit" => CharClass::Digit,
+                "special" => CharClass::Special,
+                "ascii-printable" => CharClass::AsciiPrintable,
+                "unicode" => CharClass::Unicode,
+                _ => {
+                    return Err(Error::new(&format!(
+                        "Unknown character class '{}' in password rules",
+                        name
+                    )));
+                }
+            });
+        }
+    }
+    Ok(classes)
+}
+
+/// Splits rules on the semicolons that are not inside a bracketed class.
+fn split_rules(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_brackets = false;
+    let mut chars = value.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' if !in_brackets => in_brackets = true,
+            ']' if in_brackets => {
+                if chars.peek().is_some_and(|(_, next)| *next == ']') {
+                    chars.next();
+                }
+                in_brackets = false;
+            }
+            ';' if !in_brackets => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+impl FromStr for PasswordRules {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut rules = PasswordRules::default();
+        for rule in split_rules(value) {
+            if rule.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = rule.split_once(':').ok_or_else(|| {
+                Error::new(&format!(
+                    "Password rule '{}' should have the form 'name: value'",
+                    rule.trim()
+                ))
+            })?;
+            let name = name.trim().to_lowercase();
+            match name.as_str() {
+                // Repeated limits combine to the strictest one.
+                "minlength" => {
+                    let min = parse_number(&name, value)?;
+                    rules.min_length = Some(rules.min_length.map_or(min, |m| m.max(min)));
+                }
+                "maxlength" => {
+                    let max = parse_number(&name, value)?;
+                    rules.max_length = Some(rules.max_length.map_or(max, |m| m.min(max)));
+                }
+                "max-consecutive" => {
+                    let max = parse_number(&name, value)?;
+                    rules.max_consecutive =
+                        Some(rules.max_consecutive.map_or(max, |m| m.min(max)));
+                }
+                "required" => {
+                    let classes = parse_classes(value)?;
+                    if !classes.is_empty() {
+                        rules.required.push(classes);
+                    }
+                }
+                "allowed" => rules.allowed.extend(parse_classes(value)?),
+                _ => {
+                    return Err(Error::new(&format!(
+                        "Unknown password rule '{}'",
+                        name
+                    )));
+                }
+            }
+        }
+        if let (Some(min), Some(max)) = (rules.min_length, rules.max_length)
+            && min > max
+        {
+            return Err(Error::new(&format!(
+                "Password rules set minlength {} above maxlength {}",
+                min, max
+            )));
+        }
+        if rules.max_consecutive == Some(0) || rules.max_length == Some(0) {
+            return Err(Error::new(
+                "Password rules must allow at least one character",
+            ));
+        }
+        Ok(rules)
+    }
+}
+
+impl Display for PasswordRules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |classes: &[CharClass]| {
+            classes
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut parts = Vec::new();
+        if let Some(min) = self.min_length {
+            parts.push(format!("minlength: {}", min));
+        }
+        if let Some(max) = self.max_length {
+            parts.push(format!("maxlength: {}", max));
+        }
+        if let Some(max) = self.max_consecutive {
+            parts.push(format!("max-consecutive: {}", max));
+        }
+        for classes in &self.required {
+            parts.push(format!("required: {}", join(classes)));
+        }
+        if !self.allowed.is_empty() {
+            parts.push(format!("allowed: {}", join(&self.allowed)));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_documented_example() {
+        let rules: PasswordRules = "minlength: 12; maxlength: 20; required: upper; allowed: [-_.]"
+            .parse()
+            .unwrap();
+        assert_eq!(rules.min_length, Some(12));
+        assert_eq!(rules.max_length, Some(20));
+        assert_eq!(rules.required, vec![vec![CharClass::Upper]]);
+        assert_eq!(rules.allowed, vec![CharClass::Custom("-_.".to_string())]);
+    }
+
+    #[test]
+    fn parses_brackets_containing_separators() {
+        let rules: PasswordRules = "required: lower, upper; required: digit, [;,:]]; allowed: [-]]"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rules.required,
+            vec![
+                vec![CharClass::Lower, CharClass::Upper],
+                vec![CharClass::Digit, CharClass::Custom(";,:]".to_string())],
+            ]
+        );
+        assert_eq!(rules.allowed, vec![CharClass::Custom("-]".to_string())]);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let text = "minlength: 8; maxlength: 20; max-consecutive: 3; required: lower, upper; required: digit, [-!]]; allowed: special";
+        let rules: PasswordRules = text.parse().unwrap();
+        assert_eq!(rules.to_string(), text);
+        assert_eq!(rules.to_string().parse::<PasswordRules>().unwrap(), rules);
+    }
+
+    #[test]
+    fn repeated_limits_keep_the_strictest() {
+        let rules: PasswordRules = "minlength: 8; minlength: 10; maxlength: 30; maxlength: 20"
+            .parse()
+            .unwrap();
+        assert_eq!(rules.min_length, Some(10));
+        assert_eq!(rules.max_length, Some(20));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        for bad in [
+            "minlength: twelve",
+            "required: uppercase",
+            "maxlenght: 10",
+            "allowed: [abc",
+            "minlength: 20; maxlength: 10",
+            "required upper",
+        ] {
+            assert!(bad.parse::<PasswordRules>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn accepts_checks_every_rule() {
+        let rules: PasswordRules = "minlength: 6; maxlength: 8; max-consecutive: 2; required: upper; required: digit; allowed: lower"
+            .parse()
+            .unwrap();
+        assert!(rules.accepts("Abcde1"));
+        assert!(!rules.accepts("Abcd1"), "too short");
+        assert!(!rules.accepts("Abcdefgh1"), "too long");
+        assert!(!rules.accepts("Abbbc1"), "three in a row");
+        assert!(!rules.accepts("abcde1"), "no uppercase");
+        assert!(!rules.accepts("Abcde1!"), "symbol not allowed");
+    }
+
+    #[test]
+    fn built_in_rules_match_hosts_and_urls() {
+        assert!(PasswordRules::for_service("paypal.com").is_some());
+        assert!(PasswordRules::for_service("https://www.paypal.com/signin?x=1").is_some());
+        assert!(PasswordRules::for_service("user@PayPal.com:443").is_some());
+        assert!(PasswordRules::for_service("notpaypal.com").is_none());
+        assert!(PasswordRules::for_service("example.com").is_none());
+        for (domain, _) in KNOWN_SITE_RULES {
+            assert!(PasswordRules::for_service(domain).is_some());
+        }
+    }
+}
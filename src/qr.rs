@@ -0,0 +1,525 @@
+//! A small QR code encoder (ISO/IEC 18004) for showing secrets in the
+//! terminal. Data is always encoded in byte mode with the medium error
+//! correction level, in the smallest version (1 to 40) that fits.
+//!
+//! Reading QR codes from screenshots uses the `rqrr` decoder.
+
+use crate::vault::entities::Error;
+
+const MIN_VERSION: usize = 1;
+const MAX_VERSION: usize = 40;
+/// Error correction codewords per block and number of blocks for each
+/// version at level M, indexed by version (index 0 is unused).
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+const NUM_ERROR_CORRECTION_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+    25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+/// The two format bits of error correction level M.
+const FORMAT_BITS_M: u32 = 0;
+/// Light modules around the symbol, as required for reliable scanning.
+const QUIET_ZONE: usize = 4;
+
+pub struct QrCode {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+fn bit(value: u32, index: usize) -> bool {
+    (value >> index) & 1 != 0
+}
+
+/// Number of modules that hold data and error correction codewords, after
+/// the function patterns are taken out.
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        result -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[version] * NUM_ERROR_CORRECTION_BLOCKS[version]
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1d);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root: u8 = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (x, y) in result.iter_mut().zip(divisor) {
+            *x ^= gf_multiply(*y, factor);
+        }
+    }
+    result
+}
+
+/// The data codewords of `data` in byte mode, padded to the capacity of
+/// `version`.
+fn data_codewords_for(data: &[u8], version: usize) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: usize, length: usize| {
+        for i in (0..length).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len(), if version <= 9 { 8 } else { 16 });
+    for byte in data {
+        push(*byte as usize, 8);
+    }
+    let capacity = data_codewords(version) * 8;
+    let terminator = (capacity - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    bits.extend(std::iter::repeat_n(false, (8 - bits.len() % 8) % 8));
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, bit| (acc << 1) | *bit as u8))
+        .collect();
+    for pad in [0xec, 0x11].iter().cycle() {
+        if codewords.len() == capacity / 8 {
+            break;
+        }
+        codewords.push(*pad);
+    }
+    codewords
+}
+
+/// Splits the data into blocks, appends their error correction codewords and
+/// interleaves the blocks.
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8> {
+    let blocks = NUM_ERROR_CORRECTION_BLOCKS[version];
+    let ecc_length = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw_codewords % blocks;
+    let short_block_length = raw_codewords / blocks;
+    let divisor = reed_solomon_divisor(ecc_length);
+
+    let mut block_data: Vec<Vec<u8>> = Vec::with_capacity(blocks);
+    let mut offset = 0;
+    for i in 0..blocks {
+        let length = short_block_length - ecc_length + usize::from(i >= short_blocks);
+        let mut block = data[offset..offset + length].to_vec();
+        offset += length;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        block_data.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..block_data[0].len() {
+        for (j, block) in block_data.iter().enumerate() {
+            // Skip the padding byte of the short blocks.
+            if i != short_block_length - ecc_length || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+impl QrCode {
+    /// Encodes `data` in the smallest version that fits.
+    pub fn encode(data: &[u8]) -> Result<QrCode, Error> {
+        let version = (MIN_VERSION..=MAX_VERSION)
+            .find(|version| {
+                let count_bits = if *version <= 9 { 8 } else { 16 };
+                4 + count_bits + data.len() * 8 <= data_codewords(*version) * 8
+            })
+            .ok_or_else(|| Error::new("The text is too long for a QR code"))?;
+        let codewords = add_error_correction(&data_codewords_for(data, version), version);
+
+        let size = version * 4 + 17;
+        let mut qr = QrCode {
+            size,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&codewords);
+
+        let best_mask = (0..8)
+            .min_by_key(|mask| {
+                qr.apply_mask(*mask);
+                qr.draw_format_bits(*mask);
+                let penalty = qr.penalty_score();
+                qr.apply_mask(*mask);
+                penalty
+            })
+            .expect("eight masks");
+        qr.apply_mask(best_mask);
+        qr.draw_format_bits(best_mask);
+        Ok(qr)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the module at column `x` and row `y` is dark.
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn alignment_positions(&self, version: usize) -> Vec<usize> {
+        if version == 1 {
+            return Vec::new();
+        }
+        let count = version / 7 + 2;
+        let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+        let mut positions: Vec<usize> = (0..count - 1).map(|i| self.size - 7 - i * step).collect();
+        positions.push(6);
+        positions.reverse();
+        positions
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        for i in 0..self.size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        let far = self.size - 4;
+        for (x, y) in [(3, 3), (far, 3), (3, far)] {
+            for dy in -4isize..=4 {
+                for dx in -4isize..=4 {
+                    let (xx, yy) = (x as isize + dx, y as isize + dy);
+                    if (0..self.size as isize).contains(&xx) && (0..self.size as isize).contains(&yy) {
+                        let distance = dx.abs().max(dy.abs());
+                        self.set_function(xx as usize, yy as usize, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+
+        let positions = self.alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, x) in positions.iter().enumerate() {
+            for (j, y) in positions.iter().enumerate() {
+                // The corners taken by the finder patterns have no alignment pattern.
+                if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                    continue;
+                }
+                for dy in -2isize..=2 {
+                    for dx in -2isize..=2 {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        self.set_function((*x as isize + dx) as usize, (*y as isize + dy) as usize, dark);
+                    }
+                }
+            }
+        }
+
+        // Reserve the format areas; the real bits are drawn with the mask.
+        self.draw_format_bits(0);
+
+        if version >= 7 {
+            let mut remainder = version as u32;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1f25);
+            }
+            let bits = (version as u32) << 12 | remainder;
+            for i in 0..18 {
+                let (a, b) = (self.size - 11 + i % 3, i / 3);
+                self.set_function(a, b, bit(bits, i));
+                self.set_function(b, a, bit(bits, i));
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let data = FORMAT_BITS_M << 3 | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+
+        for i in 0..=5 {
+            self.set_function(8, i, bit(bits, i));
+        }
+        self.set_function(8, 7, bit(bits, 6));
+        self.set_function(8, 8, bit(bits, 7));
+        self.set_function(7, 8, bit(bits, 8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(bits, i));
+        }
+
+        for i in 0..8 {
+            self.set_function(self.size - 1 - i, 8, bit(bits, i));
+        }
+        for i in 8..15 {
+            self.set_function(8, self.size - 15 + i, bit(bits, i));
+        }
+        self.set_function(8, self.size - 8, true);
+    }
+
+    /// Places the codewords in the zigzag order, two columns at a time from
+    /// the bottom right corner.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size as isize - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..self.size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { self.size - 1 - vertical } else { vertical };
+                    if !self.is_function[y * self.size + x] && i < codewords.len() * 8 {
+                        self.modules[y * self.size + x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// Inverts the data modules selected by `mask`. Applying a mask twice
+    /// undoes it.
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                self.modules[index] ^= invert && !self.is_function[index];
+            }
+        }
+    }
+
+    /// The penalty of the current module pattern as defined by the standard;
+    /// the mask with the lowest penalty is the easiest to scan.
+    fn penalty_score(&self) -> usize {
+        const FINDER_LIKE: [[bool; 11]; 2] = [
+            [true, false, true, true, true, false, true, false, false, false, false],
+            [false, false, false, false, true, false, true, true, true, false, true],
+        ];
+        let size = self.size;
+        let mut penalty = 0;
+        let lines: Vec<Vec<bool>> = (0..size)
+            .map(|y| (0..size).map(|x| self.is_dark(x, y)).collect())
+            .chain((0..size).map(|x| (0..size).map(|y| self.is_dark(x, y)).collect()))
+            .collect();
+        for line in &lines {
+            let mut run = 1;
+            for i in 1..=size {
+                if i < size && line[i] == line[i - 1] {
+                    run += 1;
+                } else {
+                    if run >= 5 {
+                        penalty += run - 2;
+                    }
+                    run = 1;
+                }
+            }
+            penalty += line
+                .windows(11)
+                .filter(|window| FINDER_LIKE.iter().any(|pattern| window == pattern))
+                .count()
+                * 40;
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.is_dark(x, y);
+                if dark == self.is_dark(x + 1, y)
+                    && dark == self.is_dark(x, y + 1)
+                    && dark == self.is_dark(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        let total = size * size;
+        let dark = self.modules.iter().filter(|dark| **dark).count();
+        let deviation = (dark * 20).abs_diff(total * 10);
+        penalty + deviation.div_ceil(total).saturating_sub(1) * 10
+    }
+
+    /// Renders the code with half-block characters, two rows of modules per
+    /// line, in black on white regardless of the terminal's colors.
+    pub fn to_terminal_string(&self) -> String {
+        let size = self.size() + 2 * QUIET_ZONE;
+        let dark = |x: usize, y: usize| {
+            (QUIET_ZONE..QUIET_ZONE + self.size()).contains(&x)
+                && (QUIET_ZONE..QUIET_ZONE + self.size()).contains(&y)
+                && self.is_dark(x - QUIET_ZONE, y - QUIET_ZONE)
+        };
+        let mut output = String::new();
+        for y in (0..size).step_by(2) {
+            output.push_str("\x1b[30;47m");
+            for x in 0..size {
+                output.push(match (dark(x, y), y + 1 < size && dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            output.push_str("\x1b[0m\n");
+        }
+        output
+    }
+}
+
+/// Reads the text of every QR code in a PNG or JPEG image.
+pub fn decode_image(path: &str) -> Result<Vec<String>, Error> {
+    let image = image::open(path)
+        .map_err(|e| Error::new(&format!("Cannot read image {}: {}", path, e)))?
+        .to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32)[0],
+    );
+    let texts: Vec<String> = prepared
+        .detect_grids()
+        .iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, text)| text)
+        .collect();
+    if texts.is_empty() {
+        return Err(Error::new(&format!("No QR code found in {}", path)));
+    }
+    Ok(texts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: usize = 4;
+
+    /// Scans `qr` with an independent decoder.
+    fn decode(qr: &QrCode) -> String {
+        let width = (qr.size() + 2 * QUIET_ZONE) * SCALE;
+        let mut image = rqrr::PreparedImage::prepare_from_greyscale(width, width, |x, y| {
+            let (x, y) = (x / SCALE, y / SCALE);
+            let inside = |v: usize| (QUIET_ZONE..QUIET_ZONE + qr.size()).contains(&v);
+            if inside(x) && inside(y) && qr.is_dark(x - QUIET_ZONE, y - QUIET_ZONE) {
+                0
+            } else {
+                255
+            }
+        });
+        let grids = image.detect_grids();
+        assert_eq!(grids.len(), 1);
+        grids[0].decode().expect("decodable QR code").1
+    }
+
+    #[test]
+    fn capacities_match_the_standard() {
+        // Byte mode capacities at level M from the standard's tables.
+        for (version, capacity) in [(1, 14), (2, 26), (7, 122), (10, 213), (27, 1125), (40, 2331)] {
+            let count_bits = if version <= 9 { 1 } else { 2 };
+            assert_eq!(data_codewords(version) - count_bits - 1, capacity, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn encoded_text_scans_back() {
+        let url = "otpauth://totp/GitHub:alice%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=GitHub&period=30&algorithm=SHA1&digits=6";
+        for text in ["", "a", "hunter2", "correct horse battery staple", url, &"x".repeat(300), &"ÄÖ-".repeat(120), &"z".repeat(2000)] {
+            let qr = QrCode::encode(text.as_bytes()).unwrap();
+            assert_eq!(decode(&qr), text);
+        }
+    }
+
+    #[test]
+    fn picks_the_smallest_version() {
+        assert_eq!(QrCode::encode(&[b'a'; 14]).unwrap().size(), 21);
+        assert_eq!(QrCode::encode(&[b'a'; 15]).unwrap().size(), 25);
+        assert_eq!(QrCode::encode(&[b'a'; 2331]).unwrap().size(), 177);
+        assert!(QrCode::encode(&[b'a'; 2332]).is_err());
+    }
+
+    /// Draws `qr` into an image of `SCALE` pixels per module.
+    fn image(qr: &QrCode) -> image::GrayImage {
+        let width = ((qr.size() + 2 * QUIET_ZONE) * SCALE) as u32;
+        image::GrayImage::from_fn(width, width, |x, y| {
+            let (x, y) = (x as usize / SCALE, y as usize / SCALE);
+            let inside = |v: usize| (QUIET_ZONE..QUIET_ZONE + qr.size()).contains(&v);
+            let dark = inside(x) && inside(y) && qr.is_dark(x - QUIET_ZONE, y - QUIET_ZONE);
+            image::Luma([if dark { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn decodes_png_and_jpeg_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "otpauth://totp/ACME:bob?secret=JBSWY3DPEHPK3PXP&issuer=ACME";
+        let qr = QrCode::encode(url.as_bytes()).unwrap();
+        for name in ["code.png", "code.jpg"] {
+            let path = dir.path().join(name);
+            image(&qr).save(&path).unwrap();
+            assert_eq!(decode_image(path.to_str().unwrap()).unwrap(), vec![url.to_string()]);
+        }
+        let blank = dir.path().join("blank.png");
+        image::GrayImage::from_pixel(64, 64, image::Luma([255])).save(&blank).unwrap();
+        assert!(decode_image(blank.to_str().unwrap()).is_err());
+        assert!(decode_image("/nonexistent/code.png").is_err());
+    }
+
+    #[test]
+    fn renders_two_rows_per_line() {
+        let qr = QrCode::encode(b"hello").unwrap();
+        let rendered = qr.to_terminal_string();
+        assert_eq!(rendered.lines().count(), (21 + 2 * QUIET_ZONE).div_ceil(2));
+        // The top left finder pattern starts after the quiet zone.
+        assert!(rendered.lines().nth(2).unwrap().contains("█▀▀▀▀▀█"));
+    }
+}
@@ -6,7 +6,7 @@ pub enum ReplCommand {
     Add { item_type: ItemType },
     Edit { item_type: ItemType, grep: Option<String> },
     Delete { item_type: ItemType, grep: Option<String> },
-    Gen,
+    Gen { passphrase: bool },
     Import { file_path: Option<String> },
     Export { item_type: ItemType, file_path: Option<String> },
     Lock,
@@ -79,7 +79,13 @@ pub fn parse_input(line: &str) -> ReplCommand {
             let (item_type, grep) = parse_type_and_arg(rest, ItemType::Credential);
             ReplCommand::Delete { item_type, grep }
         }
-        "gen" => ReplCommand::Gen,
+        "gen" => {
+            let passphrase = rest.first().is_some_and(|t| {
+                let lower = t.to_lowercase();
+                lower == "passphrase" || lower == "--passphrase"
+            });
+            ReplCommand::Gen { passphrase }
+        }
         "import" => {
             let file_path = rest.first().map(|s| s.to_string());
             ReplCommand::Import { file_path }
@@ -166,8 +172,14 @@ mod tests {
 
     #[test]
     fn test_gen() {
-        assert_eq!(parse_input("gen"), ReplCommand::Gen);
-        assert_eq!(parse_input("GEN"), ReplCommand::Gen);
+        assert_eq!(parse_input("gen"), ReplCommand::Gen { passphrase: false });
+        assert_eq!(parse_input("GEN"), ReplCommand::Gen { passphrase: false });
+    }
+
+    #[test]
+    fn test_gen_passphrase() {
+        assert_eq!(parse_input("gen passphrase"), ReplCommand::Gen { passphrase: true });
+        assert_eq!(parse_input("gen --passphrase"), ReplCommand::Gen { passphrase: true });
     }
 
     #[test]
@@ -14,7 +14,7 @@ Available commands:
   add [type]              Add a new entry (default: credential)
   edit [type] [pattern]   Edit an existing entry
   delete [type] [pattern] Delete an entry
-  gen                     Generate a random password
+  gen [passphrase]        Generate a random password or passphrase
   import <file>           Import credentials from a CSV file
   export [type] <file>    Export entries to a CSV file
   unlock [otp]            Store master password in keychain
@@ -75,9 +75,13 @@ delete [type] [pattern] — Delete an entry
         ),
         "gen" => println!(
             r#"
-gen — Generate a random password
+gen [passphrase] — Generate a random password or passphrase
 
-  Generates a secure random password, prints it, and copies it to clipboard."#
+  Generates a secure random password, prints it, and copies it to clipboard.
+  'gen passphrase' generates words from the EFF long word list instead and
+  shows its entropy.
+  The length and character classes come from ~/.passlane/.password_policy;
+  use 'passlane gen --save-defaults' with generator options to change them."#
         ),
         "import" => println!(
             r#"
@@ -85,7 +89,9 @@ import <file> — Import credentials from a CSV file
 
   import /path/to/file.csv
 
-  The CSV file should have columns: username, password, service"#
+  The CSV file should have columns: username, password, service
+  Rows for accounts already in the vault are skipped. Use
+  'passlane csv --on-conflict update|duplicate' to change this."#
         ),
         "export" => println!(
             r#"
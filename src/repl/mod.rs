@@ -17,7 +17,9 @@ use crate::actions::init::InitAction;
 use crate::actions::lock::LockAction;
 use crate::actions::unlock::UnlockAction;
 use crate::actions::show::ShowAction;
-use crate::actions::{Action, ItemType, UnlockingAction};
+use crate::actions::{generate_password, Action, ItemType, OnConflict, UnlockingAction};
+use crate::crypto::otp::DEFAULT_RESYNC_WINDOW;
+use crate::crypto::PolicyOverrides;
 use crate::completion_cache;
 use crate::{keychain, store};
 
@@ -154,6 +156,10 @@ fn dispatch(command: ReplCommand) -> Result<(), String> {
                 stdout_only: false,
                 plain: false,
                 once: false,
+                qr: false,
+                at: None,
+                offset: None,
+                window: None,
             };
             match action.execute() {
                 Ok(Some(msg)) => println!("{}", msg),
@@ -168,6 +174,10 @@ fn dispatch(command: ReplCommand) -> Result<(), String> {
                 clipboard: false,
                 item_type,
                 is_totp,
+                policy: PolicyOverrides::default(),
+                rules: None,
+                breached: None,
+                qr_image: None,
             };
             match action.run() {
                 Ok(msg) => println!("{}", msg),
@@ -183,6 +193,10 @@ fn dispatch(command: ReplCommand) -> Result<(), String> {
                 grep,
                 item_type,
                 is_totp,
+                generate: false,
+                rules: None,
+                resync: None,
+                window: DEFAULT_RESYNC_WINDOW,
             };
             match action.execute() {
                 Ok(Some(msg)) => println!("{}", msg),
@@ -206,8 +220,12 @@ fn dispatch(command: ReplCommand) -> Result<(), String> {
                 Err(e) => return Err(e.message),
             }
         }
-        ReplCommand::Gen => {
-            let password = crate::crypto::generate();
+        ReplCommand::Gen { passphrase } => {
+            let overrides = PolicyOverrides {
+                passphrase,
+                ..PolicyOverrides::default()
+            };
+            let password = generate_password(&overrides, None).map_err(|e| e.message)?;
             copy_to_clipboard(&password);
             println!("{}", password);
             println!("Password copied to clipboard.");
@@ -217,7 +235,12 @@ fn dispatch(command: ReplCommand) -> Result<(), String> {
                 Some(p) => p,
                 None => return Err("Usage: import <file> — a CSV file path is required".to_string()),
             };
-            let action = ImportCsvAction { file_path };
+            let action = ImportCsvAction {
+                file_path,
+                item_type: ItemType::Credential,
+                format: None,
+                on_conflict: OnConflict::Skip,
+            };
             match action.execute() {
                 Ok(Some(msg)) => println!("{}", msg),
                 Ok(None) => {}
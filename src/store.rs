@@ -1,6 +1,7 @@
+use crate::crypto::PasswordPolicy;
 use crate::vault::entities::{Credential, Error, Note, PaymentCard};
 use chrono::{DateTime, Utc};
-use csv::{ReaderBuilder, Writer};
+use csv::Writer;
 use serde::{Deserialize, Serialize};
 use std::fs::create_dir;
 use std::fs::OpenOptions;
@@ -25,7 +26,31 @@ impl From<serde_json::Error> for Error {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// A credential in the CSV layout of `export`. The `url` and `guid` columns
+/// of Firefox are read as the service and the UUID.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CSVCredential {
+    #[serde(default, alias = "guid")]
+    pub uuid: Option<String>,
+    pub password: String,
+    #[serde(alias = "url")]
+    pub service: String,
+    pub username: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub password_rules: Option<String>,
+    #[serde(default)]
+    pub totp_id: Option<String>,
+    #[serde(default)]
+    pub tags: Option<String>,
+}
+
+/// A payment card in the CSV layout of `export -p`. The columns after
+/// `billing_address` were added later and may be missing from older exports.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CSVPaymentCard {
     pub name: String,
     pub name_on_card: String,
@@ -34,12 +59,36 @@ pub struct CSVPaymentCard {
     pub expiry: String,
     pub color: String,
     pub billing_address: String,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub billing_address_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// A secure note in the CSV layout of `export -n`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CSVSecureNote {
     pub title: String,
     pub note: String,
+    #[serde(default)]
+    pub uuid: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Option<String>,
+}
+
+/// Tags are written to a single column, separated by semicolons like in
+/// KeePass.
+pub(crate) const CSV_TAG_SEPARATOR: char = ';';
+
+fn csv_tags(tags: &[String]) -> Option<String> {
+    let separator = CSV_TAG_SEPARATOR.to_string();
+    Some(tags.join(&separator)).filter(|tags| !tags.is_empty())
 }
 
 fn home_dir() -> PathBuf {
@@ -55,47 +104,6 @@ fn dir_path() -> PathBuf {
     dir_path
 }
 
-#[derive(Debug, Deserialize)]
-struct CsvImportRow {
-    #[serde(alias = "url")]
-    service: String,
-    username: String,
-    password: String,
-    #[serde(default, alias = "guid")]
-    uuid: Option<String>,
-    #[serde(default)]
-    note: Option<String>,
-    #[serde(default)]
-    last_modified: Option<DateTime<Utc>>,
-}
-
-pub fn read_from_csv(file_path: &str) -> anyhow::Result<Vec<Credential>> {
-    let path = PathBuf::from(file_path);
-    let in_file = OpenOptions::new().read(true).open(path)?;
-    let mut reader = ReaderBuilder::new().has_headers(true).from_reader(in_file);
-    let mut credentials = Vec::new();
-    for result in reader.deserialize::<CsvImportRow>() {
-        let row = result?;
-        if row.service.is_empty() && row.username.is_empty() && row.password.is_empty() {
-            continue;
-        }
-        let parsed_uuid = row
-            .uuid
-            .as_deref()
-            .filter(|s| !s.is_empty())
-            .and_then(|s| Uuid::parse_str(s).ok());
-        credentials.push(Credential::new(
-            parsed_uuid.as_ref(),
-            &row.password,
-            &row.service,
-            &row.username,
-            row.note.as_deref(),
-            row.last_modified,
-        ));
-    }
-    Ok(credentials)
-}
-
 fn read_from_file(path: &PathBuf) -> Option<String> {
     let mut file = OpenOptions::new()
         .read(true)
@@ -182,7 +190,17 @@ pub(crate) fn write_credentials_to_csv(
 ) -> Result<i64, Error> {
     let mut wtr = Writer::from_writer(create_private_file(file_path)?);
     for cred in creds {
-        wtr.serialize(cred)?;
+        wtr.serialize(CSVCredential {
+            uuid: Some(cred.uuid().to_string()),
+            password: cred.password().to_string(),
+            service: cred.service().to_string(),
+            username: cred.username().to_string(),
+            note: cred.note().map(str::to_string),
+            last_modified: Some(*cred.last_modified()),
+            password_rules: cred.password_rules().map(str::to_string),
+            totp_id: cred.totp_id().map(Uuid::to_string),
+            tags: csv_tags(cred.tags()),
+        })?;
     }
     wtr.flush()?;
     Ok(creds.len() as i64)
@@ -208,6 +226,10 @@ pub(crate) fn write_payment_cards_to_csv(
                 Some(address) => format!("{}", address),
                 None => String::from(""),
             },
+            uuid: Some(card.id().to_string()),
+            last_modified: Some(*card.last_modified()),
+            tags: csv_tags(card.tags()),
+            billing_address_id: card.billing_address().map(|address| address.id().to_string()),
         })?;
     }
     wtr.flush()?;
@@ -220,6 +242,9 @@ pub(crate) fn write_secure_notes_to_csv(file_path: &str, notes: &Vec<Note>) -> R
         wtr.serialize(CSVSecureNote {
             title: note.title().to_string(),
             note: note.content().to_string(),
+            uuid: Some(note.id().to_string()),
+            last_modified: Some(note.last_modified()),
+            tags: csv_tags(note.tags()),
         })?;
     }
     wtr.flush()?;
@@ -254,6 +279,52 @@ pub(crate) fn save_totp_keyfile_path(path: &str) -> Result<(), Error> {
     save_config_path(".totp_keyfile_path", path)
 }
 
+/// Reads the password policy from `~/.passlane/.password_policy`, falling back to
+/// the built-in defaults when the file does not exist.
+pub(crate) fn load_password_policy() -> Result<PasswordPolicy, Error> {
+    let path = dir_path().join(".password_policy");
+    if !path.exists() {
+        return Ok(PasswordPolicy::default());
+    }
+    read_password_policy(&path)
+}
+
+fn read_password_policy(path: &Path) -> Result<PasswordPolicy, Error> {
+    let content = std::fs::read_to_string(path)?;
+    let policy: PasswordPolicy = serde_json::from_str(&content).map_err(|e| {
+        Error::new(&format!("Invalid password policy in {}: {}", path.display(), e))
+    })?;
+    policy.validate().map_err(|e| {
+        Error::new(&format!("Invalid password policy in {}: {}", path.display(), e))
+    })?;
+    Ok(policy)
+}
+
+pub(crate) fn save_password_policy(policy: &PasswordPolicy) -> Result<String, Error> {
+    let path = dir_path().join(".password_policy");
+    std::fs::write(&path, serde_json::to_string_pretty(policy)?)?;
+    Ok(path.display().to_string())
+}
+
+/// Reads the clock offset for one time passwords, in seconds, from
+/// `~/.passlane/.clock_offset`. Without the file the offset is 0.
+pub(crate) fn load_clock_offset() -> Result<i64, Error> {
+    let path = dir_path().join(".clock_offset");
+    if !path.exists() {
+        return Ok(0);
+    }
+    std::fs::read_to_string(&path)?.trim().parse().map_err(|_| {
+        Error::new(&format!(
+            "Invalid clock offset in {}: expected a number of seconds",
+            path.display()
+        ))
+    })
+}
+
+pub(crate) fn save_clock_offset(offset: i64) -> Result<(), Error> {
+    save_config_path(".clock_offset", &offset.to_string())
+}
+
 pub fn has_vault_path() -> bool {
     config_file_exists(".vault_path")
 }
@@ -269,9 +340,16 @@ pub fn has_keyfile_path() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::vault::entities::Credential;
+    use crate::importers::csv::{PasslaneCardsCsv, PasslaneCsv, PasslaneNotesCsv};
+    use crate::importers::Importer;
+    use crate::vault::entities::{Address, Credential, Expiry};
     use tempfile::NamedTempFile;
 
+    fn read_from_csv(file_path: &str) -> Result<Vec<Credential>, Error> {
+        let entries = PasslaneCsv.parse(&std::fs::read(file_path)?)?;
+        Ok(entries.credentials.into_iter().map(|c| c.entry).collect())
+    }
+
     #[cfg(unix)]
     #[test]
     fn test_csv_export_is_owner_only() {
@@ -365,4 +443,100 @@ mod tests {
         // A fresh uuid should have been generated since the guid was unparseable.
         assert_eq!(imported[0].uuid().get_version_num(), 4);
     }
+
+    #[test]
+    fn test_read_password_policy_reports_invalid_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{"length": 2, "min_per_class": 1}}"#).unwrap();
+        let err = read_password_policy(file.path()).unwrap_err();
+        assert!(err.message.starts_with("Invalid password policy in"), "{}", err.message);
+
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, r#"{{"length": 20, "exclude_ambiguous": true}}"#).unwrap();
+        let policy = read_password_policy(file.path()).unwrap();
+        assert_eq!(policy.length, 20);
+        assert!(policy.exclude_ambiguous);
+    }
+
+    #[test]
+    fn test_csv_roundtrip_with_password_rules() {
+        let with_rules = Credential::new(None, "pass123", "shop.com", "me", None, None)
+            .with_password_rules(Some("minlength: 8; required: digit, [;,]"));
+        let without = Credential::new(None, "pass456", "other.com", "me", None, None);
+        let tmp = NamedTempFile::new().unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+        write_credentials_to_csv(&path, &vec![with_rules, without]).unwrap();
+        let imported = read_from_csv(&path).unwrap();
+        assert_eq!(
+            imported[0].password_rules(),
+            Some("minlength: 8; required: digit, [;,]")
+        );
+        assert_eq!(imported[1].password_rules(), None);
+    }
+
+    #[test]
+    fn test_csv_roundtrip_of_all_entity_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let modified = "2024-03-01T10:00:00.123456Z".parse().unwrap();
+
+        let credential = Credential::new(None, "p,\"w\"", "shop.com", "me", Some("two\nlines €"), Some(modified))
+            .with_password_rules(Some("minlength: 8"))
+            .with_totp_id(Some(Uuid::new_v4()))
+            .with_tags(vec!["work".to_string(), "shared".to_string()]);
+        let plain = Credential::new(None, "pw", "other.com", "you", None, Some(modified));
+        write_credentials_to_csv(&path("creds.csv"), &vec![credential.clone(), plain.clone()]).unwrap();
+        let entries = PasslaneCsv.parse(&std::fs::read(path("creds.csv")).unwrap()).unwrap();
+        for (read, written) in entries.credentials.iter().map(|c| &c.entry).zip([&credential, &plain]) {
+            assert_eq!(read.uuid(), written.uuid());
+            assert_eq!(read.password(), written.password());
+            assert_eq!(read.service(), written.service());
+            assert_eq!(read.username(), written.username());
+            assert_eq!(read.note(), written.note());
+            assert_eq!(read.last_modified(), written.last_modified());
+            assert_eq!(read.password_rules(), written.password_rules());
+            assert_eq!(read.totp_id(), written.totp_id());
+            assert_eq!(read.tags(), written.tags());
+        }
+
+        let address = Address::new(None, "1 Main St, Apt 4", "Springfield", "US", Some("IL"), "12345");
+        let card = PaymentCard::new(
+            None, "Visa", "Alice", "4111111111111111", "123", Expiry { month: 7, year: 2027 },
+            Some("blue"), Some(&address), Some(modified),
+        )
+        .with_tags(vec!["travel".to_string()]);
+        let bare = PaymentCard::new(
+            None, "Debit", "Alice", "5555555555554444", "321", Expiry { month: 1, year: 2028 },
+            None, None, Some(modified),
+        );
+        write_payment_cards_to_csv(&path("cards.csv"), &vec![card.clone(), bare.clone()]).unwrap();
+        let entries = PasslaneCardsCsv.parse(&std::fs::read(path("cards.csv")).unwrap()).unwrap();
+        assert!(entries.invalid_rows.is_empty());
+        for (read, written) in entries.payments.iter().map(|p| &p.entry).zip([&card, &bare]) {
+            assert_eq!(read.id(), written.id());
+            assert_eq!(read.name(), written.name());
+            assert_eq!(read.name_on_card(), written.name_on_card());
+            assert_eq!(read.number(), written.number());
+            assert_eq!(read.cvv(), written.cvv());
+            assert_eq!(read.expiry_str(), written.expiry_str());
+            assert_eq!(read.color(), written.color());
+            assert_eq!(
+                read.billing_address().map(|a| (a.id(), a.street(), a.zip(), a.city(), a.state(), a.country())),
+                written.billing_address().map(|a| (a.id(), a.street(), a.zip(), a.city(), a.state(), a.country()))
+            );
+            assert_eq!(read.last_modified(), written.last_modified());
+            assert_eq!(read.tags(), written.tags());
+        }
+
+        let note = Note::new(None, "Door, codes", "front: 1234\n\"back\": 4321", Some(modified))
+            .with_tags(vec!["home".to_string()]);
+        write_secure_notes_to_csv(&path("notes.csv"), &vec![note.clone()]).unwrap();
+        let entries = PasslaneNotesCsv.parse(&std::fs::read(path("notes.csv")).unwrap()).unwrap();
+        let read = &entries.notes[0].entry;
+        assert_eq!(read.id(), note.id());
+        assert_eq!(read.title(), note.title());
+        assert_eq!(read.content(), note.content());
+        assert_eq!(read.last_modified(), note.last_modified());
+        assert_eq!(read.tags(), note.tags());
+    }
 }
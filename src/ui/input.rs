@@ -8,7 +8,10 @@ use rustyline::validate::Validator;
 use rustyline::{Config, Editor, Result as RustylineResult};
 use rustyline_derive::Helper;
 
-use crate::vault::entities::{Address, Credential, Expiry, Note, PaymentCard, Totp};
+use crate::actions::OnConflict;
+use crate::vault::entities::{
+    keep_unknown_otp_params, Address, Credential, Expiry, Note, PaymentCard, Totp,
+};
 use inquire::{Confirm, CustomType, Password, Select, Text};
 use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
@@ -179,7 +182,12 @@ pub fn ask_credentials(password: &str) -> Credential {
     Credential::new(None, password, &service, &username, note.as_deref(), None)
 }
 
-pub(crate) fn ask_modified_credential<'a>(the_match: &'a Credential) -> Credential {
+/// Asks for changes to a credential. A `new_password` that was generated
+/// replaces the password without asking for one.
+pub(crate) fn ask_modified_credential<'a>(
+    the_match: &'a Credential,
+    new_password: Option<&str>,
+) -> Credential {
     let service = ask_with_initial(
         "Enter URL or service",
         Some(the_match.service()),
@@ -190,7 +198,10 @@ pub(crate) fn ask_modified_credential<'a>(the_match: &'a Credential) -> Credenti
         Some(the_match.username()),
         Some("Press enter and leave empty to keep the current value shown in parantheses"),
     );
-    let password = ask_new_password("Enter new password");
+    let password = match new_password {
+        Some(password) => Some(password.to_string()),
+        None => ask_new_password("Enter new password"),
+    };
     let note = ask_with_initial_optional(
         "Enter note (optional)",
         the_match.note(),
@@ -296,6 +307,26 @@ pub(crate) fn ask_modified_totp<'a>(the_match: &'a Totp) -> Totp {
     let digits = ask_with_initial("Digits", Some(&the_match.digits().to_string()), None)
         .parse::<u32>()
         .unwrap();
+    if let Some(counter) = the_match.counter() {
+        let counter = ask_with_initial("Counter", Some(&counter.to_string()), None)
+            .parse::<u64>()
+            .unwrap();
+        let algorithm = ask_with_initial("Algorithm", Some(the_match.algorithm()), None);
+        return Totp::new(
+            Some(the_match.id()),
+            &keep_unknown_otp_params(
+                &format_hotp_url(&label, &secret, &issuer, counter, &algorithm, digits),
+                the_match.url(),
+            ),
+            &label,
+            &issuer,
+            &secret,
+            &algorithm,
+            the_match.period(),
+            digits,
+            None,
+        );
+    }
     let period = ask_with_initial("Period", Some(&the_match.period().to_string()), None)
         .parse::<u64>()
         .unwrap();
@@ -303,7 +334,10 @@ pub(crate) fn ask_modified_totp<'a>(the_match: &'a Totp) -> Totp {
 
     Totp::new(
         Some(the_match.id()),
-        &format_totp_url(&label, &secret, &issuer, period, &algorithm, digits),
+        &keep_unknown_otp_params(
+            &format_totp_url(&label, &secret, &issuer, period, &algorithm, digits),
+            the_match.url(),
+        ),
         &label,
         &issuer,
         &secret,
@@ -343,6 +377,24 @@ pub fn ask_new_totp_master_password() -> String {
     ask_new_master_password_with(|q| ask_password(&format!("{} (TOTP vault)", q), None))
 }
 
+/// Asks twice for the password of a KeePass file made by `export --kdbx`. With
+/// a key file the password may be left empty, so that the key file alone
+/// opens the export.
+pub fn ask_export_password(with_keyfile: bool) -> String {
+    if with_keyfile {
+        println!("Choose a password for the exported file, or leave it empty to protect it with the key file only");
+    } else {
+        println!("Choose a password for the exported file, and share it with the recipient separately");
+    }
+    loop {
+        let password = ask_new_master_password_with(|q| ask_password(&format!("{} (export)", q), None));
+        if with_keyfile || !password.is_empty() {
+            return password;
+        }
+        println!("The exported file needs a password, or a key file given with --keyfile");
+    }
+}
+
 pub(crate) fn ask_totp_master_password() -> String {
     ask_password(
         "Please enter master password of the One Time Passwords vault",
@@ -451,7 +503,7 @@ const TOTP_URL_LABEL: &AsciiSet = &NON_ALPHANUMERIC
     .remove(b':')
     .remove(b'@');
 
-fn format_totp_url(
+pub(crate) fn format_totp_url(
     label: &str,
     secret: &str,
     issuer: &str,
@@ -470,6 +522,25 @@ fn format_totp_url(
     )
 }
 
+pub(crate) fn format_hotp_url(
+    label: &str,
+    secret: &str,
+    issuer: &str,
+    counter: u64,
+    algo: &str,
+    digits: u32,
+) -> String {
+    format!(
+        "otpauth://hotp/{}?secret={}&issuer={}&counter={}&algorithm={}&digits={}",
+        utf8_percent_encode(label, TOTP_URL_LABEL),
+        utf8_percent_encode(secret, TOTP_URL_SECRET),
+        utf8_percent_encode(issuer, TOTP_URL_QUERY),
+        counter,
+        algo,
+        digits
+    )
+}
+
 pub(crate) fn ask_totp_info() -> Totp {
     let label = ask_with_initial(
         "Enter label, typically formatted like <issuer:username>:",
@@ -484,7 +555,7 @@ pub(crate) fn ask_totp_info() -> Totp {
         None,
     );
 
-    println!("Add TOTP using settings settings (number of digits: 6, algo: SHA1, period: 30 seconds), or proceed to specify algorithm and other details (y/n)?");
+    println!("Add TOTP using settings settings (number of digits: 6, algo: SHA1, period: 30 seconds), or proceed to specify the type (TOTP or counter-based HOTP), algorithm and other details (y/n)?");
     let proceed = ask_with_initial(
         "Press y (yes) to add with defaults, n (no) to specify details.",
         Some("y"),
@@ -492,6 +563,23 @@ pub(crate) fn ask_totp_info() -> Totp {
     );
 
     if proceed.to_lowercase() == "n" || proceed.to_lowercase() == "no" {
+        let kind = ask_with_options("Type:", vec!["TOTP (time-based)", "HOTP (counter-based)"]);
+        if kind.starts_with("HOTP") {
+            let digits = ask_number("Enter number of digits:") as u32;
+            let counter = ask_number("Enter the counter value of the next code (0 for a new token):");
+            let algorithm = ask_algorithm();
+            return Totp::new(
+                None,
+                &format_hotp_url(&label, &secret, &issuer, counter, &algorithm, digits),
+                &label,
+                &issuer,
+                &secret,
+                &algorithm,
+                30,
+                digits,
+                None,
+            );
+        }
         let digits = ask_number("Enter number of digits:");
         let period = ask_number("Enter period:");
         let algorithm = ask_algorithm();
@@ -671,6 +759,31 @@ pub fn ask_open_existing_totp_vault() -> bool {
         == "Existing"
 }
 
+/// Asks what to do with a new credential for the account of `existing`.
+pub(crate) fn ask_on_conflict(existing: &Credential) -> OnConflict {
+    println!(
+        "An entry for {} with username {} already exists (last modified {}).",
+        existing.service(),
+        existing.username(),
+        existing.last_modified().format("%d.%m.%Y")
+    );
+    let answer = Select::new(
+        "What do you want to do?",
+        vec!["Update the existing entry", "Keep both", "Cancel"],
+    )
+    .prompt()
+    .unwrap();
+    match answer {
+        "Update the existing entry" => OnConflict::Update,
+        "Keep both" => OnConflict::Duplicate,
+        _ => OnConflict::Skip,
+    }
+}
+
+pub(crate) fn ask_confirm(question: &str, default: bool) -> bool {
+    Confirm::new(question).with_default(default).prompt().unwrap()
+}
+
 pub fn ask_with_options(question: &str, options: Vec<&str>) -> String {
     Select::new(question, options).prompt().unwrap().to_string()
 }
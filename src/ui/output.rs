@@ -1,7 +1,9 @@
 use comfy_table::*;
 use std::cmp::min;
 
-use crate::vault::entities::{Credential, Note, PaymentCard, Totp};
+use crate::actions::audit::{Finding, Severity};
+use crate::crypto::strength::Strength;
+use crate::vault::entities::{AlteredText, Credential, Note, PaymentCard, RecoveryCodes, Totp};
 
 pub fn show_credentials_table(credentials: &[Credential], show_password: bool, plain: bool) {
     let mut table = Table::new();
@@ -192,6 +194,7 @@ pub(crate) fn show_totp_table(totps: &[Totp], plain: bool) {
             header_cell("".to_string()),
             header_cell("Label".to_string()),
             header_cell("Issuer".to_string()),
+            header_cell("Type".to_string()),
             header_cell("Modified".to_string()),
         ]
         .into_iter()
@@ -202,8 +205,154 @@ pub(crate) fn show_totp_table(totps: &[Totp], plain: bool) {
             Cell::new(index.to_string()).fg(Color::Yellow),
             Cell::new(totp.label().to_string()),
             Cell::new(totp.issuer().to_string()),
+            Cell::new(match totp.counter() {
+                Some(counter) => format!("HOTP, counter {}", counter),
+                None if totp.is_steam() => "Steam".to_string(),
+                None => "TOTP".to_string(),
+            }),
             Cell::new(totp.last_modified().format("%d.%m.%Y %H:%M").to_string()),
         ]);
     }
     println!("{table}");
 }
+
+/// Shows the services with recovery codes and how many of their codes are unused.
+pub(crate) fn show_recovery_codes_table(entries: &[RecoveryCodes], plain: bool) {
+    let mut table = Table::new();
+    apply_plain(&mut table, plain);
+    table.set_header(
+        ["", "Service", "Unused", "Total", "Modified"]
+            .iter()
+            .map(|&h| header_cell(String::from(h)))
+            .collect::<Vec<Cell>>(),
+    );
+    for (index, entry) in entries.iter().enumerate() {
+        table.add_row(vec![
+            Cell::new(index.to_string()).fg(Color::Yellow),
+            Cell::new(entry.service()),
+            Cell::new(entry.unused_count().to_string()),
+            Cell::new(entry.codes().len().to_string()),
+            Cell::new(entry.last_modified().format("%Y-%m-%d %H:%M:%S").to_string()),
+        ]);
+    }
+    println!("{table}");
+}
+
+/// Shows the settings of authorizers read from a QR code or an export, for
+/// checking them before they are saved.
+pub(crate) fn show_totp_settings_table(totps: &[Totp]) {
+    let mut table = Table::new();
+    table.set_header(
+        ["Label", "Issuer", "Type", "Algorithm", "Digits", "Period"]
+            .into_iter()
+            .map(|title| header_cell(title.to_string()))
+            .collect::<Vec<Cell>>(),
+    );
+    for totp in totps {
+        table.add_row(vec![
+            Cell::new(totp.label().to_string()),
+            Cell::new(totp.issuer().to_string()),
+            Cell::new(match totp.counter() {
+                Some(counter) => format!("HOTP, counter {}", counter),
+                None if totp.is_steam() => "Steam".to_string(),
+                None => "TOTP".to_string(),
+            }),
+            Cell::new(totp.algorithm().to_string()),
+            Cell::new(totp.digits().to_string()),
+            Cell::new(if totp.is_hotp() {
+                "-".to_string()
+            } else {
+                format!("{} s", totp.period())
+            }),
+        ]);
+    }
+    println!("{table}");
+}
+
+pub(crate) fn show_altered_text_table(altered: &[AlteredText]) {
+    let mut table = Table::new();
+    table.set_header(
+        vec!["", "Entry", "Field", "Characters", "Status"]
+            .into_iter()
+            .map(|h| header_cell(h.to_string()))
+            .collect::<Vec<Cell>>(),
+    );
+    for (index, item) in altered.iter().enumerate() {
+        table.add_row(vec![
+            Cell::new(index.to_string()).fg(Color::Yellow),
+            Cell::new(&item.title),
+            Cell::new(&item.field),
+            Cell::new(item.characters.escape_debug().to_string()),
+            Cell::new(item.alteration.to_string()),
+        ]);
+    }
+    println!("{table}");
+}
+
+pub(crate) fn show_password_strength(strength: &Strength) {
+    println!(
+        "Password strength: {} ({}/4), about 10^{:.1} guesses, cracked in {} by an offline attack",
+        strength.score_label(),
+        strength.score,
+        strength.guesses_log10,
+        strength.crack_time
+    );
+    if let Some(warning) = &strength.warning {
+        println!("Warning: {}", warning);
+    }
+}
+
+pub(crate) fn show_audit_tables(findings: &[Finding]) {
+    let mut table = Table::new();
+    table.set_header(vec![
+        header_cell("Severity".to_string()),
+        header_cell("Check".to_string()),
+        header_cell("Service".to_string()),
+        header_cell("Username/email".to_string()),
+        header_cell("Details".to_string()),
+    ]);
+    for finding in findings {
+        table.add_row(vec![
+            severity_cell(finding),
+            Cell::new(finding.check.to_string()),
+            Cell::new(&finding.service),
+            Cell::new(&finding.username),
+            Cell::new(&finding.detail),
+        ]);
+    }
+    println!("{table}");
+
+    let mut summary = Table::new();
+    summary.set_header(vec![
+        header_cell("Check".to_string()),
+        header_cell("Severity".to_string()),
+        header_cell("Findings".to_string()),
+    ]);
+    let mut counts: Vec<(&Finding, usize)> = Vec::new();
+    for finding in findings {
+        match counts
+            .iter_mut()
+            .find(|(f, _)| f.check == finding.check && f.severity == finding.severity)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((finding, 1)),
+        }
+    }
+    for (finding, count) in counts {
+        summary.add_row(vec![
+            Cell::new(finding.check.to_string()),
+            severity_cell(finding),
+            Cell::new(count.to_string()),
+        ]);
+    }
+    println!("{summary}");
+}
+
+fn severity_cell(finding: &Finding) -> Cell {
+    let color = match finding.severity {
+        Severity::High => Color::Red,
+        Severity::Medium => Color::Yellow,
+        Severity::Low => Color::Reset,
+    };
+    Cell::new(finding.severity.to_string()).fg(color)
+}
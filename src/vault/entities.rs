@@ -1,5 +1,4 @@
 use chrono::{DateTime, Utc};
-use keepass_ng::db::TOTP;
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -9,7 +8,8 @@ use std::str::FromStr;
 use std::time::SystemTimeError;
 use uuid::Uuid;
 
-use crate::crypto::SPECIAL;
+use crate::crypto::otp;
+use crate::password_rules::host_name;
 
 #[derive(Debug)]
 pub struct Error {
@@ -64,6 +64,14 @@ pub struct Credential {
     note: Option<String>,
     #[serde(default = "default_last_modified")]
     last_modified: DateTime<Utc>,
+    /// The site's password requirements in the `passwordrules` syntax.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password_rules: Option<String>,
+    /// The UUID of the linked authorizer in the one time passwords vault.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totp_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 fn default_last_modified() -> DateTime<Utc> {
@@ -82,13 +90,36 @@ impl Credential {
         Credential {
             uuid: uuid.map(|id| id.clone()).unwrap_or_else(|| Uuid::new_v4()),
             password: password.to_string(),
-            service: sanitize(service),
-            username: sanitize(username),
-            note: note.map(|n| sanitize(n)).filter(|n| !n.is_empty()),
+            service: service.to_string(),
+            username: username.to_string(),
+            note: note.map(|n| n.to_string()).filter(|n| !n.is_empty()),
             last_modified: last_modified.unwrap_or(Utc::now()),
+            password_rules: None,
+            totp_id: None,
+            tags: Vec::new(),
         }
     }
 
+    pub fn with_password_rules(mut self, rules: Option<&str>) -> Self {
+        self.password_rules = rules.map(|r| r.to_string()).filter(|r| !r.is_empty());
+        self
+    }
+
+    pub fn with_totp_id(mut self, totp_id: Option<Uuid>) -> Self {
+        self.totp_id = totp_id;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_uuid(mut self, uuid: Uuid) -> Self {
+        self.uuid = uuid;
+        self
+    }
+
     pub fn uuid(&self) -> &Uuid {
         &self.uuid
     }
@@ -112,6 +143,47 @@ impl Credential {
     pub fn last_modified(&self) -> &DateTime<Utc> {
         &self.last_modified
     }
+
+    pub fn password_rules(&self) -> Option<&str> {
+        self.password_rules.as_deref()
+    }
+
+    pub fn totp_id(&self) -> Option<&Uuid> {
+        self.totp_id.as_ref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Whether `other` is an entry for the same account: the same UUID, or the
+    /// same service and username once the service is reduced to its host name
+    /// and both are compared case-insensitively.
+    pub fn is_same_account(&self, other: &Credential) -> bool {
+        self.uuid == other.uuid
+            || (normalized_service(&self.service) == normalized_service(&other.service)
+                && self.username.trim().to_lowercase() == other.username.trim().to_lowercase())
+    }
+
+    /// This credential with the UUID of `existing`, keeping its note, password
+    /// rules and linked authorizer where this one has none, so that saving it
+    /// updates `existing`.
+    pub fn replacing(self, existing: &Credential) -> Credential {
+        Credential {
+            uuid: existing.uuid,
+            note: self.note.or_else(|| existing.note.clone()),
+            password_rules: self
+                .password_rules
+                .or_else(|| existing.password_rules.clone()),
+            totp_id: self.totp_id.or(existing.totp_id),
+            ..self
+        }
+    }
+}
+
+fn normalized_service(service: &str) -> String {
+    let host = host_name(service.trim());
+    host.strip_prefix("www.").unwrap_or(&host).to_string()
 }
 
 #[derive(Clone, Serialize)]
@@ -125,6 +197,8 @@ pub struct PaymentCard {
     color: Option<String>,
     billing_address: Option<Address>,
     last_modified: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl PaymentCard {
@@ -141,17 +215,32 @@ impl PaymentCard {
     ) -> Self {
         PaymentCard {
             id: id.map(|id| id.clone()).unwrap_or_else(|| Uuid::new_v4()),
-            name: sanitize(name),
-            name_on_card: sanitize(name_on_card),
-            number: sanitize(number),
-            cvv: sanitize(cvv),
+            name: name.to_string(),
+            name_on_card: name_on_card.to_string(),
+            number: number.to_string(),
+            cvv: cvv.to_string(),
             expiry,
-            color: color.map(sanitize),
+            color: color.map(|c| c.to_string()),
             billing_address: billing_address.cloned(),
             last_modified: last_modified.unwrap_or_else(|| Utc::now()),
+            tags: Vec::new(),
         }
     }
 
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn id(&self) -> &Uuid {
         &self.id
     }
@@ -210,6 +299,11 @@ pub struct Totp {
     period: u64,
     digits: u32,
     last_modified: DateTime<Utc>,
+    /// The next counter value of an HOTP authorizer, `None` for TOTP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoder: Option<String>,
 }
 
 impl Totp {
@@ -227,13 +321,15 @@ impl Totp {
         Totp {
             id: id.map(|id| id.clone()).unwrap_or_else(|| Uuid::new_v4()),
             url: url.to_string(),
-            label: sanitize(label),
+            label: label.to_string(),
             issuer: issuer.to_string(),
             secret: secret.to_string(),
             algorithm: algorithm.to_string(),
             period,
             digits,
             last_modified: last_modified.unwrap_or_else(|| Utc::now()),
+            counter: hotp_counter(url),
+            encoder: query_param(url, "encoder"),
         }
     }
 
@@ -241,6 +337,75 @@ impl Totp {
         &self.id
     }
 
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn is_hotp(&self) -> bool {
+        self.counter.is_some()
+    }
+
+    /// Whether `other` is the same authorizer: same issuer, label and secret,
+    /// ignoring case, spaces and padding in the secret.
+    pub fn is_same_authorizer(&self, other: &Totp) -> bool {
+        let secret = |totp: &Totp| -> String {
+            totp.secret
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '=')
+                .collect::<String>()
+                .to_uppercase()
+        };
+        self.issuer == other.issuer && self.label == other.label && secret(self) == secret(other)
+    }
+
+    /// The `encoder` URL parameter that KeePassXC uses for non-numeric codes.
+    pub fn encoder(&self) -> Option<&str> {
+        self.encoder.as_deref()
+    }
+
+    /// Whether codes are five-character Steam Guard codes.
+    pub fn is_steam(&self) -> bool {
+        self.encoder().is_some_and(|encoder| encoder.eq_ignore_ascii_case("steam"))
+    }
+
+    pub fn counter(&self) -> Option<u64> {
+        self.counter
+    }
+
+    /// This HOTP authorizer with its counter moved past the code it produces now.
+    pub fn advanced(&self) -> Totp {
+        self.with_counter(self.counter.unwrap_or(0) + 1)
+    }
+
+    /// This HOTP authorizer with its counter set to `counter`, in the URL too.
+    pub fn with_counter(&self, counter: u64) -> Totp {
+        Totp {
+            url: with_query_param(&self.url, "counter", &counter.to_string()),
+            counter: Some(counter),
+            ..self.clone()
+        }
+    }
+
+    /// Resynchronizes an HOTP authorizer with its token: finds `code` at most
+    /// `window` counters ahead and continues after it.
+    pub fn resynced(&self, code: &str, window: u64) -> Result<Totp, Error> {
+        let counter = self
+            .counter
+            .ok_or_else(|| Error::new("Only HOTP authorizers have a counter to resynchronize"))?;
+        let key = otp::decode_secret(&self.secret)?;
+        otp::find_counter(&key, code, self.digits, &self.algorithm, counter, window)?
+            .map(|found| self.with_counter(found + 1))
+            .ok_or_else(|| {
+                Error::new(&format!(
+                    "Code {} not found within {} counters after {}",
+                    code.trim(),
+                    window,
+                    counter
+                ))
+            })
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
@@ -289,20 +454,101 @@ pub struct TotpCode {
     pub valid_for_seconds: u64,
 }
 
+/// The `counter` of an `otpauth://hotp/` URL, 0 when the URL has none.
+fn hotp_counter(url: &str) -> Option<u64> {
+    url.get(.."otpauth://hotp/".len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("otpauth://hotp/"))
+        .then(|| {
+            query_param(url, "counter")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0)
+        })
+}
+
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// `url` with the query parameter `name` set to `value`, keeping the other
+/// parameters and their order.
+fn with_query_param(url: &str, name: &str, value: &str) -> String {
+    let (base, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut found = false;
+    let mut pairs: Vec<String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if key == name => {
+                found = true;
+                format!("{}={}", name, value)
+            }
+            _ => pair.to_string(),
+        })
+        .collect();
+    if !found {
+        pairs.push(format!("{}={}", name, value));
+    }
+    format!("{}?{}", base, pairs.join("&"))
+}
+
 impl Totp {
+    /// The current code. An HOTP code stays valid until it is used, so its
+    /// `valid_for_seconds` is 0; save `advanced()` once the code is shown.
     pub fn get_code(&self) -> Result<TotpCode, Error> {
-        let totp = TOTP::from_str(&self.url)
-            .map_err(|e| Error::new(&format!("Failed to parse totp url: {:?}", e)))?;
+        self.code_with(&otp::Clock::default())
+    }
+
+    /// The current code by the time of `clock`, or of the counter for HOTP.
+    pub fn code_with(&self, clock: &otp::Clock) -> Result<TotpCode, Error> {
+        if let Some(counter) = self.counter {
+            let key = otp::decode_secret(&self.secret)?;
+            return Ok(TotpCode {
+                value: otp::hotp(&key, counter, self.digits, &self.algorithm)?,
+                valid_for_seconds: 0,
+            });
+        }
+        self.code_at(clock.now()?)
+    }
 
-        debug!("Getting code for totp: {}", totp.label);
-        let code = totp.value_now()?;
+    /// The time-based code at `time`, in seconds since the Unix epoch.
+    pub fn code_at(&self, time: u64) -> Result<TotpCode, Error> {
+        if self.period == 0 {
+            return Err(Error::new("The OTP period must be at least one second"));
+        }
+        debug!("Getting code for totp: {}", self.label);
+        let key = otp::decode_secret(&self.secret)?;
+        let counter = time / self.period;
+        let value = if self.is_steam() {
+            otp::steam(&key, counter)?
+        } else {
+            otp::hotp(&key, counter, self.digits, &self.algorithm)?
+        };
         Ok(TotpCode {
-            value: code.code,
-            valid_for_seconds: code.valid_for.as_secs(),
+            value,
+            valid_for_seconds: self.period - time % self.period,
         })
     }
 }
 
+/// `url` with the query parameters of `original` that Passlane does not set
+/// itself, such as KeePassXC's `encoder`, so that editing an entry keeps them.
+pub fn keep_unknown_otp_params(url: &str, original: &str) -> String {
+    const KNOWN: [&str; 6] = ["secret", "issuer", "period", "algorithm", "digits", "counter"];
+    let Some((_, query)) = original.split_once('?') else {
+        return url.to_string();
+    };
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(name, _)| !KNOWN.contains(name) && query_param(url, name).is_none())
+        .fold(url.to_string(), |url, (name, value)| with_query_param(&url, name, value))
+}
+
 impl PaymentCard {
     pub fn color_str(&self) -> String {
         if let Some(color) = &self.color {
@@ -390,14 +636,19 @@ impl Address {
     ) -> Self {
         Address {
             id: id.map(|id| id.clone()).unwrap_or_else(|| Uuid::new_v4()),
-            street: sanitize(street),
-            city: sanitize(city),
-            country: sanitize(country),
-            state: state.map(sanitize),
-            zip: sanitize(zip),
+            street: street.to_string(),
+            city: city.to_string(),
+            country: country.to_string(),
+            state: state.map(|s| s.to_string()),
+            zip: zip.to_string(),
         }
     }
 
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
     pub fn id(&self) -> &Uuid {
         &self.id
     }
@@ -429,13 +680,8 @@ pub struct Note {
     title: String,
     content: String,
     last_modified: DateTime<Utc>,
-}
-
-fn sanitize(value: &str) -> String {
-    value
-        .chars()
-        .filter(|c| c.is_alphanumeric() || c.is_whitespace() || SPECIAL.contains(*c))
-        .collect::<String>()
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
 impl Note {
@@ -447,11 +693,23 @@ impl Note {
     ) -> Self {
         Note {
             id: id.map(|id| id.clone()).unwrap_or_else(|| Uuid::new_v4()),
-            title: sanitize(title),
-            content: sanitize(content),
+            title: title.to_string(),
+            content: content.to_string(),
             last_modified: last_modified.unwrap_or_else(Utc::now),
+            tags: Vec::new(),
         }
     }
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
     pub fn id(&self) -> Uuid {
         self.id
     }
@@ -466,13 +724,189 @@ impl Note {
     }
 }
 
+/// One code of a recovery codes entry.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecoveryCode {
+    pub code: String,
+    pub used: bool,
+}
+
+/// The one-time backup codes that a service gives when two-factor
+/// authentication is enabled, and which of them have been used.
+#[derive(Clone, Serialize)]
+pub struct RecoveryCodes {
+    id: Uuid,
+    service: String,
+    codes: Vec<RecoveryCode>,
+    last_modified: DateTime<Utc>,
+}
+
+const USED_MARK: &str = "[x] ";
+const UNUSED_MARK: &str = "[ ] ";
+
+impl RecoveryCodes {
+    pub fn new(
+        id: Option<&Uuid>,
+        service: &str,
+        codes: Vec<RecoveryCode>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> Self {
+        RecoveryCodes {
+            id: id.copied().unwrap_or_else(Uuid::new_v4),
+            service: service.to_string(),
+            codes,
+            last_modified: last_modified.unwrap_or_else(Utc::now),
+        }
+    }
+
+    /// Unused codes from text as services show them: separated by whitespace
+    /// or commas, possibly numbered like "1. abcd-efgh". Repeated codes are
+    /// kept once.
+    pub fn parse_codes(text: &str) -> Result<Vec<RecoveryCode>, Error> {
+        let mut codes: Vec<RecoveryCode> = Vec::new();
+        let words = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .filter(|word| !(word.ends_with(['.', ')']) && word[..word.len() - 1].chars().all(|c| c.is_ascii_digit())));
+        for word in words {
+            if !codes.iter().any(|code| code.code == word) {
+                codes.push(RecoveryCode {
+                    code: word.to_string(),
+                    used: false,
+                });
+            }
+        }
+        if codes.is_empty() {
+            return Err(Error::new("No recovery codes found"));
+        }
+        Ok(codes)
+    }
+
+    /// Reads the checklist written by `to_checklist`. Lines without a mark
+    /// are codes that have not been used.
+    pub fn from_checklist(
+        id: Option<&Uuid>,
+        service: &str,
+        checklist: &str,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> Self {
+        let codes = checklist
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.strip_prefix(USED_MARK.trim_end()) {
+                Some(code) => RecoveryCode {
+                    code: code.trim().to_string(),
+                    used: true,
+                },
+                None => RecoveryCode {
+                    code: line.strip_prefix(UNUSED_MARK.trim_end()).unwrap_or(line).trim().to_string(),
+                    used: false,
+                },
+            })
+            .collect();
+        RecoveryCodes::new(id, service, codes, last_modified)
+    }
+
+    /// One code per line, marked `[x]` when used and `[ ]` when not, so that
+    /// the state is readable in other KeePass apps too.
+    pub fn to_checklist(&self) -> String {
+        self.codes
+            .iter()
+            .map(|code| format!("{}{}", if code.used { USED_MARK } else { UNUSED_MARK }, code.code))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    pub fn codes(&self) -> &[RecoveryCode] {
+        &self.codes
+    }
+
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.last_modified
+    }
+
+    pub fn unused_count(&self) -> usize {
+        self.codes.iter().filter(|code| !code.used).count()
+    }
+
+    /// Marks the first unused code as used and returns it.
+    pub fn use_next(&mut self) -> Option<String> {
+        let next = self.codes.iter_mut().find(|code| !code.used)?;
+        next.used = true;
+        Some(next.code.clone())
+    }
+}
+
+/// How earlier passlane versions, which filtered emojis, symbols and control
+/// characters out of every text field except passwords, affected a field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Alteration {
+    /// A save dropped the characters; the entry history still has the original.
+    Removed,
+    /// The vault has the full text, but it was shown, copied and exported
+    /// without these characters.
+    Hidden,
+}
+
+impl Display for Alteration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Alteration::Removed => write!(f, "removed on save, original in entry history"),
+            Alteration::Hidden => write!(f, "stored intact, previously shown without them"),
+        }
+    }
+}
+
+/// A text field whose stored value differs from what the user typed or saw.
+#[derive(Clone, Serialize)]
+pub struct AlteredText {
+    pub uuid: Uuid,
+    pub title: String,
+    pub field: String,
+    pub characters: String,
+    pub alteration: Alteration,
+}
+
+/// A part of an address with its commas and backslashes escaped by a
+/// backslash, so that it can be told apart from the separators.
+fn escape_address_part(part: &str) -> String {
+    part.replace('\\', "\\\\").replace(',', "\\,")
+}
+
+/// The parts of an address written by `Display`, unescaped and trimmed.
+fn split_address(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => parts.last_mut().unwrap().extend(chars.next()),
+            ',' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts.iter().map(|part| part.trim().to_string()).collect()
+}
+
+/// `Street, Zip, City, Country`, with the state before the country when
+/// there is one. Commas and backslashes in the parts are escaped with a
+/// backslash.
 impl Display for Address {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}, {}, {}, {}",
-            self.street, self.zip, self.city, self.country
-        )
+        let mut parts = vec![&self.street, &self.zip, &self.city];
+        parts.extend(&self.state);
+        parts.push(&self.country);
+        let parts: Vec<String> = parts.into_iter().map(|part| escape_address_part(part)).collect();
+        write!(f, "{}", parts.join(", "))
     }
 }
 
@@ -486,7 +920,7 @@ impl fmt::Display for AddressParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             AddressParseError::InvalidFormat => {
-                write!(f, "Invalid format. Expected Street, Zip, City, Country")
+                write!(f, "Invalid format. Expected Street, Zip, City, [State,] Country")
             }
             AddressParseError::ParseError(e) => e.fmt(f),
         }
@@ -505,22 +939,14 @@ impl FromStr for Address {
     type Err = AddressParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split(',').collect();
-        if parts.len() != 4 {
-            return Err(AddressParseError::InvalidFormat);
-        }
-        let street = parts[0].trim().to_string();
-        let zip = parts[1].trim().to_string();
-        let city = parts[2].trim().to_string();
-        let country = parts[3].trim().to_string();
-        Ok(Address {
-            id: Uuid::new_v4(),
-            street,
-            city,
-            country,
-            state: None,
-            zip,
-        })
+        let parts = split_address(s);
+        let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+        let (street, zip, city, state, country) = match parts[..] {
+            [street, zip, city, country] => (street, zip, city, None, country),
+            [street, zip, city, state, country] => (street, zip, city, Some(state), country),
+            _ => return Err(AddressParseError::InvalidFormat),
+        };
+        Ok(Address::new(None, street, city, country, state, zip))
     }
 }
 
@@ -528,6 +954,135 @@ impl FromStr for Address {
 mod tests {
     use super::*;
 
+    fn hotp(counter: u64) -> Totp {
+        // RFC 4226 test secret "12345678901234567890".
+        let url = format!(
+            "otpauth://hotp/VPN:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=VPN&counter={}&digits=6",
+            counter
+        );
+        Totp::new(None, &url, "VPN:alice", "VPN", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", "SHA1", 30, 6, None)
+    }
+
+    #[test]
+    fn hotp_codes_follow_the_counter() {
+        let totp = hotp(0);
+        assert!(totp.is_hotp());
+        let code = totp.get_code().unwrap();
+        assert_eq!(code.value, "755224");
+        assert_eq!(code.valid_for_seconds, 0);
+
+        let next = totp.advanced();
+        assert_eq!(next.counter(), Some(1));
+        assert!(next.url().contains("&counter=1&"));
+        assert_eq!(next.get_code().unwrap().value, "287082");
+
+        let without_counter = Totp::new(None, "otpauth://HOTP/x?secret=GEZDGNBV&issuer=x", "x", "x", "GEZDGNBV", "SHA1", 30, 6, None);
+        assert_eq!(without_counter.counter(), Some(0));
+        assert_eq!(without_counter.advanced().url(), "otpauth://HOTP/x?secret=GEZDGNBV&issuer=x&counter=1");
+    }
+
+    #[test]
+    fn hotp_resync_continues_after_the_matching_code() {
+        let totp = hotp(2);
+        assert_eq!(totp.resynced("162583", 10).unwrap().counter(), Some(8));
+        assert!(totp.resynced("162583", 4).is_err());
+        let time_based = Totp::new(None, "otpauth://totp/x?secret=GEZDGNBV&issuer=x", "x", "x", "GEZDGNBV", "SHA1", 30, 6, None);
+        assert!(!time_based.is_hotp());
+        assert!(time_based.resynced("123456", 10).is_err());
+    }
+
+    #[test]
+    fn totp_codes_match_rfc_6238_sha256() {
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+        let url = format!("otpauth://totp/x?secret={}&issuer=x&algorithm=SHA256&digits=8", secret);
+        let totp = Totp::new(None, &url, "x", "x", secret, "SHA256", 30, 8, None);
+        assert_eq!(totp.code_at(59).unwrap().value, "46119246");
+        assert_eq!(totp.code_at(59).unwrap().valid_for_seconds, 1);
+        assert_eq!(totp.code_at(1111111109).unwrap().value, "68084774");
+
+        let minute = Totp::new(None, &url, "x", "x", secret, "SHA256", 60, 7, None);
+        assert_eq!(minute.code_at(0).unwrap().value, "8920136");
+        assert_eq!(minute.code_at(59).unwrap().value, "8920136");
+        assert_eq!(minute.code_at(59).unwrap().valid_for_seconds, 1);
+        assert_eq!(minute.code_at(1111111109).unwrap().value, "0857319");
+    }
+
+    #[test]
+    fn steam_codes_use_the_encoder_parameter() {
+        let url = "otpauth://totp/Steam:u?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Steam&digits=5&encoder=steam";
+        let totp = Totp::new(None, url, "Steam:u", "Steam", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", "SHA1", 30, 5, None);
+        assert!(totp.is_steam());
+        assert_eq!(totp.code_at(0).unwrap().value, "GG5F5");
+        assert_eq!(totp.code_at(29).unwrap().value, "GG5F5");
+        assert_ne!(totp.code_at(30).unwrap().value, "GG5F5");
+    }
+
+    #[test]
+    fn edits_keep_unknown_otp_params() {
+        let original = "otpauth://totp/Steam:u?secret=OLD&issuer=Steam&digits=5&encoder=steam&image=x%2Fy";
+        let edited = "otpauth://totp/Steam:v?secret=NEW&issuer=Steam&period=30&algorithm=SHA1&digits=5";
+        assert_eq!(keep_unknown_otp_params(edited, original), format!("{}&encoder=steam&image=x%2Fy", edited));
+        assert_eq!(keep_unknown_otp_params(edited, "otpauth://totp/x?secret=OLD&digits=6"), edited);
+        assert_eq!(keep_unknown_otp_params(original, original), original);
+    }
+
+    #[test]
+    fn same_account_ignores_url_form_and_case() {
+        let existing = Credential::new(None, "a", "https://www.GitHub.com/login", "Alice", None, None);
+        let same = Credential::new(None, "b", "github.com", " alice ", None, None);
+        let other_user = Credential::new(None, "a", "github.com", "bob", None, None);
+        let other_site = Credential::new(None, "a", "gitlab.com", "alice", None, None);
+        let same_uuid = Credential::new(Some(existing.uuid()), "a", "renamed", "carol", None, None);
+        assert!(existing.is_same_account(&same));
+        assert!(!existing.is_same_account(&other_user));
+        assert!(!existing.is_same_account(&other_site));
+        assert!(existing.is_same_account(&same_uuid));
+    }
+
+    #[test]
+    fn parses_pasted_recovery_codes() {
+        let codes = RecoveryCodes::parse_codes("1. abcd-efgh  2. ijkl-mnop\n3) qrst-uvwx, abcd-efgh\n").unwrap();
+        let codes: Vec<&str> = codes.iter().map(|c| c.code.as_str()).collect();
+        assert_eq!(codes, vec!["abcd-efgh", "ijkl-mnop", "qrst-uvwx"]);
+        assert!(RecoveryCodes::parse_codes(" \n, ").is_err());
+        assert_eq!(RecoveryCodes::parse_codes("12345678 87654321").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn uses_recovery_codes_in_order_and_keeps_state_in_checklist() {
+        let codes = RecoveryCodes::parse_codes("aaaa bbbb cccc").unwrap();
+        let mut entry = RecoveryCodes::new(None, "github.com", codes, None);
+        assert_eq!(entry.use_next().as_deref(), Some("aaaa"));
+        assert_eq!(entry.unused_count(), 2);
+        assert_eq!(entry.to_checklist(), "[x] aaaa\n[ ] bbbb\n[ ] cccc");
+
+        let mut read = RecoveryCodes::from_checklist(Some(entry.id()), "github.com", &entry.to_checklist(), None);
+        assert_eq!(read.codes(), entry.codes());
+        assert_eq!(read.use_next().as_deref(), Some("bbbb"));
+        assert_eq!(read.use_next().as_deref(), Some("cccc"));
+        assert_eq!(read.use_next(), None);
+        assert_eq!(read.unused_count(), 0);
+
+        // Codes typed into the notes in another app have no mark.
+        let edited = RecoveryCodes::from_checklist(None, "x", "[x] aaaa\ndddd\n", None);
+        assert_eq!(edited.unused_count(), 1);
+        assert_eq!(edited.codes()[1].code, "dddd");
+    }
+
+    #[test]
+    fn replacing_keeps_uuid_note_rules_and_link() {
+        let totp_id = Uuid::new_v4();
+        let existing = Credential::new(None, "old", "github.com", "alice", Some("work"), None)
+            .with_password_rules(Some("minlength: 12;"))
+            .with_totp_id(Some(totp_id));
+        let updated = Credential::new(None, "new", "github.com", "alice", None, None).replacing(&existing);
+        assert_eq!(updated.uuid(), existing.uuid());
+        assert_eq!(updated.password(), "new");
+        assert_eq!(updated.note(), Some("work"));
+        assert_eq!(updated.password_rules(), Some("minlength: 12;"));
+        assert_eq!(updated.totp_id(), Some(&totp_id));
+    }
+
     fn make_card(number: &str) -> PaymentCard {
         PaymentCard::new(
             None,
@@ -578,6 +1133,23 @@ mod tests {
         assert_eq!(cred.note(), None);
     }
 
+    #[test]
+    fn test_credential_keeps_unicode_text() {
+        let cred = Credential::new(None, "pass", "café ☕ shop", "ünïcødé", Some("PIN\t€ 🔐 ¬"), None);
+        assert_eq!(cred.service(), "café ☕ shop");
+        assert_eq!(cred.username(), "ünïcødé");
+        assert_eq!(cred.note(), Some("PIN\t€ 🔐 ¬"));
+    }
+
+    #[test]
+    fn test_note_and_card_keep_unicode_text() {
+        let note = Note::new(None, "Wi-Fi 📶", "key: ¬¬ ✓", None);
+        assert_eq!(note.title(), "Wi-Fi 📶");
+        assert_eq!(note.content(), "key: ¬¬ ✓");
+        let card = make_card("4111 1111 1111 1234");
+        assert_eq!(card.number(), "4111 1111 1111 1234");
+    }
+
     #[test]
     fn test_credential_json_serialization_with_note() {
         let cred = Credential::new(None, "pass", "google.com", "user", Some("admin access"), None);
@@ -590,6 +1162,7 @@ mod tests {
         let cred = Credential::new(None, "pass", "google.com", "user", None, None);
         let json = serde_json::to_string(&cred).unwrap();
         assert!(json.contains("\"note\":null"));
+        assert!(!json.contains("password_rules"));
     }
 
     #[test]
@@ -1,14 +1,20 @@
-use crate::vault::entities::{Address, Credential, Error, Expiry, Note, PaymentCard, Totp};
-use crate::vault::vault_trait::{NoteVault, PasswordVault, PaymentVault, TotpVault, Vault};
+use crate::vault::entities::{
+    Address, Alteration, AlteredText, Credential, Error, Expiry, Note, PaymentCard,
+    RecoveryCodes, Totp,
+};
+use crate::vault::vault_trait::{
+    NoteVault, PasswordVault, PaymentVault, RecoveryCodesVault, TotpVault, Vault,
+};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use keepass_ng::db::{
-    group_get_children, node_is_entry, node_is_group, Database, Entry, Group,
-    Node, NodeIterator, NodePtr, SerializableNodePtr, TOTP,
+    group_get_children, node_is_entry, node_is_group, CustomDataItem, Database, Entry, Group,
+    Node, NodeIterator, NodePtr, SerializableNodePtr, Times, Value, TOTP,
 };
 use keepass_ng::error::DatabaseSaveError;
 use keepass_ng::{error::DatabaseOpenError, DatabaseConfig, DatabaseKey};
 
 use log::debug;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -21,6 +27,9 @@ pub struct KeepassVault {
     db: Database,
     filepath: String,
     keyfile: Option<String>,
+    /// The key holds an empty password besides the keyfile, as earlier
+    /// versions wrote it, instead of the keyfile alone.
+    empty_password_in_key: bool,
 }
 
 impl Drop for KeepassVault {
@@ -86,6 +95,315 @@ fn normalize_otp_url(url: &str) -> String {
     )
 }
 
+/// The stored `otpauth://` URL in a form keepass-ng can parse: the secret is
+/// normalized, and a missing `issuer`, which the Key URI format allows but the
+/// parser requires, is taken from the label's `Issuer:` prefix or the label.
+fn parseable_otp_url(url: &str) -> String {
+    let url = normalize_otp_url(url);
+    let Some((base, query)) = url.split_once('?') else {
+        return url;
+    };
+    if query.split('&').any(|pair| pair.starts_with("issuer=")) {
+        return url;
+    }
+    let label = base.rsplit('/').next().unwrap_or("");
+    let issuer = label
+        .split_once(':')
+        .or_else(|| label.split_once("%3A"))
+        .map_or(label, |(issuer, _)| issuer);
+    if issuer.is_empty() {
+        return url;
+    }
+    format!("{}&issuer={}", url, issuer)
+}
+
+/// keepass-ng has no public API for custom entry fields, so a credential's
+/// password rules live in a tag. Tags are split on `;` and `,`, which rules
+/// use, so the value is percent-encoded.
+const PASSWORD_RULES_TAG: &str = "passwordrules=";
+const PASSWORD_RULES_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b':');
+
+fn password_rules_from_tags(tags: &[String]) -> Option<String> {
+    tags.iter()
+        .find_map(|tag| tag.strip_prefix(PASSWORD_RULES_TAG))
+        .map(|encoded| percent_decode_str(encoded).decode_utf8_lossy().to_string())
+}
+
+fn set_password_rules_tag(entry: &mut Entry, rules: Option<&str>) {
+    let tags = entry.get_tags_mut();
+    tags.retain(|tag| !tag.starts_with(PASSWORD_RULES_TAG));
+    if let Some(rules) = rules {
+        tags.push(format!(
+            "{}{}",
+            PASSWORD_RULES_TAG,
+            utf8_percent_encode(rules, PASSWORD_RULES_ENCODE)
+        ));
+    }
+}
+
+/// The UUID of the authorizer linked to a credential, kept in a tag like the
+/// password rules.
+const TOTP_LINK_TAG: &str = "totp=";
+
+fn totp_id_from_tags(tags: &[String]) -> Option<Uuid> {
+    tags.iter()
+        .find_map(|tag| tag.strip_prefix(TOTP_LINK_TAG))
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+fn set_totp_link_tag(entry: &mut Entry, totp_id: Option<&Uuid>) {
+    let tags = entry.get_tags_mut();
+    tags.retain(|tag| !tag.starts_with(TOTP_LINK_TAG));
+    if let Some(id) = totp_id {
+        tags.push(format!("{}{}", TOTP_LINK_TAG, id));
+    }
+}
+
+/// Marks an entry holding recovery codes. keepass-ng has no way to write
+/// custom string fields, so the codes are a checklist in the password field
+/// instead, which unlike the notes is protected. Entries written by earlier
+/// versions have it in the notes.
+const RECOVERY_CODES_TAG: &str = "recovery-codes";
+
+fn node_is_recovery_codes(node: &NodePtr) -> bool {
+    node.borrow()
+        .as_any()
+        .downcast_ref::<Entry>()
+        .is_some_and(|e| e.get_tags().iter().any(|tag| tag == RECOVERY_CODES_TAG))
+}
+
+/// Whether `tag` holds passlane's own data rather than being a user's tag.
+fn is_internal_tag(tag: &str) -> bool {
+    tag.starts_with(PASSWORD_RULES_TAG) || tag.starts_with(TOTP_LINK_TAG) || tag == RECOVERY_CODES_TAG
+}
+
+/// The user tags among `tags`, so that an imported tag cannot pass for one
+/// of passlane's own.
+fn user_tags(tags: &[String]) -> impl Iterator<Item = String> + '_ {
+    tags.iter().filter(|tag| !is_internal_tag(tag)).cloned()
+}
+
+/// The tags of an entry other than those holding passlane's own data.
+fn node_user_tags(node: &NodePtr) -> Vec<String> {
+    node.borrow()
+        .as_any()
+        .downcast_ref::<Entry>()
+        .map(|e| user_tags(e.get_tags()).collect())
+        .unwrap_or_default()
+}
+
+/// keepass-ng escapes unprotected fields (title, username, URL, notes) itself
+/// and decodes them again on load. Characters XML 1.0 cannot carry do not
+/// survive that round trip: control characters become literal `&#x..;` text
+/// and U+FFFE/U+FFFF make the file unreadable. Protected fields (password, OTP
+/// URL) carry anything.
+fn is_storable_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r') || !(c.is_control() || c == '\u{FFFE}' || c == '\u{FFFF}')
+}
+
+fn check_storable(field: &str, value: &str) -> Result<(), Error> {
+    if let Some(c) = value.chars().find(|c| !is_storable_char(*c)) {
+        return Err(Error::new(&format!(
+            "{} contains the character U+{:04X}, which cannot be stored in a KDBX vault",
+            field, c as u32
+        )));
+    }
+    Ok(())
+}
+
+/// The entity names keepass-ng decodes a second time on load, so a literal
+/// "&lt;" in a text field would come back as "<".
+const DECODED_ENTITIES: [&str; 4] = ["lt;", "gt;", "quot;", "apos;"];
+
+/// The number of "amp;" between an ampersand and one of `DECODED_ENTITIES`,
+/// given the text after the ampersand.
+fn amps_before_entity(rest: &str) -> Option<usize> {
+    let mut count = 0;
+    let mut tail = rest;
+    while let Some(next) = tail.strip_prefix("amp;") {
+        count += 1;
+        tail = next;
+    }
+    DECODED_ENTITIES
+        .iter()
+        .any(|entity| tail.starts_with(entity))
+        .then_some(count)
+}
+
+/// Adds an "amp;" after every ampersand that starts an entity name, such as
+/// "&lt;" or "&amp;lt;", which keepass-ng then loads unchanged.
+fn escape_entities(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.char_indices() {
+        escaped.push(c);
+        if c == '&' && amps_before_entity(&value[i + 1..]).is_some() {
+            escaped.push_str("amp;");
+        }
+    }
+    escaped
+}
+
+/// Reverses `escape_entities`.
+fn unescape_entities(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('&') {
+        unescaped.push_str(&rest[..=pos]);
+        rest = &rest[pos + 1..];
+        if amps_before_entity(rest).is_some_and(|count| count > 0) {
+            rest = &rest["amp;".len()..];
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Rewrites the text fields of `entry` with `map`. keepass-ng gives no way to
+/// change history versions, so they are kept escaped in memory: the current
+/// version is escaped while `update_history` copies it.
+fn map_text_fields(entry: &mut Entry, map: fn(&str) -> String) {
+    for field in TEXT_FIELDS {
+        let Some(value) = entry.get(field).filter(|v| v.contains('&')).map(map) else {
+            continue;
+        };
+        match field {
+            "Title" => entry.set_title(Some(&value)),
+            "UserName" => entry.set_username(Some(&value)),
+            "URL" => entry.set_url(Some(&value)),
+            _ => entry.set_notes(Some(&value)),
+        }
+    }
+}
+
+/// Rewrites the text fields of the current version of every entry under `root`.
+fn map_all_text_fields(root: &NodePtr, map: fn(&str) -> String) {
+    for node in NodeIterator::new(root).filter(node_is_entry) {
+        if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+            map_text_fields(entry, map);
+        }
+    }
+}
+
+fn check_credential(credential: &Credential) -> Result<(), Error> {
+    check_storable("Service", credential.service())?;
+    check_storable("Username", credential.username())?;
+    credential
+        .note()
+        .map_or(Ok(()), |note| check_storable("Note", note))
+}
+
+fn check_payment(payment: &PaymentCard) -> Result<(), Error> {
+    check_storable("Card name", payment.name())?;
+    check_storable("Card details", &payment_to_note(payment))
+}
+
+fn check_note(note: &Note) -> Result<(), Error> {
+    check_storable("Title", note.title())?;
+    check_storable("Content", note.content())
+}
+
+/// The notes of a payment card entry, one field per line. The id of the
+/// billing address comes last, so that older entries without it still load.
+fn payment_to_note(payment: &PaymentCard) -> String {
+    let mut note = format!(
+        "Name on card: {}\nNumber: {}\nCVV: {}\nExpiry: {}\nColor: {}\nBilling Address: {}",
+        payment.name_on_card(),
+        payment.number(),
+        payment.cvv(),
+        payment.expiry_str(),
+        payment.color_str(),
+        payment
+            .billing_address()
+            .map(|a| a.to_string())
+            .unwrap_or_default()
+    );
+    if let Some(address) = payment.billing_address() {
+        note.push_str(&format!("\nBilling Address Id: {}", address.id()));
+    }
+    note
+}
+
+/// The characters earlier passlane versions allowed in text fields besides
+/// letters, digits and whitespace. Frozen here to recognise what they removed.
+const LEGACY_SPECIAL: &str = "£$&()*+[]@#^-_!?:;,.{}<>~%/\\|\"'`´^¨=§";
+
+fn legacy_allowed(c: char) -> bool {
+    c.is_alphanumeric() || c.is_whitespace() || LEGACY_SPECIAL.contains(c)
+}
+
+/// Split `value` into the text the legacy filter kept and the characters it dropped.
+fn legacy_filter(value: &str) -> (String, String) {
+    value.chars().partition(|c| legacy_allowed(*c))
+}
+
+const TEXT_FIELDS: [&str; 4] = ["Title", "UserName", "URL", "Notes"];
+
+/// The key of the database custom data holding when a passlane version that
+/// stores text losslessly first opened the vault. Entries modified since then
+/// were never shown through the legacy filter.
+const LOSSLESS_SINCE_KEY: &str = "passlane.lossless-since";
+const LOSSLESS_SINCE_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+fn lossless_since(db: &Database) -> Option<NaiveDateTime> {
+    match db.meta.custom_data.items.get(LOSSLESS_SINCE_KEY)?.value.as_ref()? {
+        Value::Unprotected(since) => NaiveDateTime::parse_from_str(since, LOSSLESS_SINCE_FORMAT).ok(),
+        _ => None,
+    }
+}
+
+fn set_lossless_since(db: &mut Database, since: NaiveDateTime) {
+    db.meta.custom_data.items.insert(
+        LOSSLESS_SINCE_KEY.to_string(),
+        CustomDataItem {
+            value: Some(Value::Unprotected(since.format(LOSSLESS_SINCE_FORMAT).to_string())),
+            last_modification_time: Some(since),
+        },
+    );
+}
+
+/// The characters earlier versions removed from the text fields of `entry`, or
+/// hid when showing them. Only entries last modified before `lossless_since`
+/// were ever shown by those versions, so newer ones are never reported hidden.
+fn find_altered_fields(entry: &Entry, lossless_since: Option<NaiveDateTime>) -> Vec<AlteredText> {
+    let title = entry.get_title().unwrap_or("(no title)").to_string();
+    let history = entry
+        .get_history()
+        .as_ref()
+        .map(|h| h.get_entries().as_slice())
+        .unwrap_or_default();
+    let shown_by_legacy = match (entry.get_times().get_last_modification(), lossless_since) {
+        (Some(modified), Some(since)) => modified < since,
+        _ => true,
+    };
+    let mut found = Vec::new();
+    for field in TEXT_FIELDS {
+        let current = entry.get(field).unwrap_or("");
+        let removed_on_save = history.iter().find_map(|version| {
+            let (kept, removed) = legacy_filter(&unescape_entities(version.get(field)?));
+            (!removed.is_empty() && kept == current).then_some(removed)
+        });
+        let (characters, alteration) = match removed_on_save {
+            Some(removed) => (removed, Alteration::Removed),
+            None if shown_by_legacy => (legacy_filter(current).1, Alteration::Hidden),
+            None => continue,
+        };
+        if !characters.is_empty() {
+            found.push(AlteredText {
+                uuid: entry.get_uuid(),
+                title: title.clone(),
+                field: field.to_string(),
+                characters,
+                alteration,
+            });
+        }
+    }
+    found
+}
+
 fn node_has_totp(node: &NodePtr) -> bool {
     let node = node.borrow();
     let e = node.as_any().downcast_ref::<Entry>().unwrap();
@@ -96,7 +414,7 @@ fn node_has_totp(node: &NodePtr) -> bool {
         raw.is_some()
     );
     match raw {
-        Some(url) => TOTP::from_str(&normalize_otp_url(url)).is_ok(),
+        Some(url) => TOTP::from_str(&parseable_otp_url(url)).is_ok(),
         None => false,
     }
 }
@@ -117,7 +435,7 @@ fn node_looks_like_payment(node: &NodePtr) -> bool {
 }
 
 fn node_looks_like_note(node: &NodePtr) -> bool {
-    if node_has_totp(node) {
+    if node_has_totp(node) || node_is_recovery_codes(node) {
         return false;
     }
     if node_looks_like_payment(node) {
@@ -139,7 +457,7 @@ fn node_looks_like_note(node: &NodePtr) -> bool {
 }
 
 fn node_looks_like_credential(node: &NodePtr) -> bool {
-    if node_looks_like_payment(node) {
+    if node_looks_like_payment(node) || node_is_recovery_codes(node) {
         return false;
     }
     if node_looks_like_note(node) {
@@ -163,12 +481,18 @@ impl KeepassVault {
         keyfile_path: Option<String>,
     ) -> Result<KeepassVault, Error> {
         debug!("Opening database '{}'", filepath);
-        let db = Self::open_database(filepath, password, &keyfile_path)?;
+        let (mut db, empty_password_in_key) =
+            Self::open_database(filepath, password, &keyfile_path)?;
+        map_all_text_fields(&db.root, unescape_entities);
+        if lossless_since(&db).is_none() {
+            set_lossless_since(&mut db, Times::now());
+        }
         Ok(Self {
             password: String::from(password),
             db,
             filepath: filepath.to_string(),
             keyfile: keyfile_path,
+            empty_password_in_key,
         })
     }
 
@@ -179,6 +503,7 @@ impl KeepassVault {
     ) -> Result<KeepassVault, Error> {
         let mut db = Database::new(DatabaseConfig::default());
         db.meta.database_name = Some("Passlane database".to_string());
+        set_lossless_since(&mut db, Times::now());
 
         if let Some(keyfile_path) = keyfile {
             println!("Using keyfile '{}'", keyfile_path);
@@ -188,8 +513,9 @@ impl KeepassVault {
             password: password.to_string(),
             filepath: filepath.to_string(),
             keyfile: keyfile.map(ToString::to_string),
+            empty_password_in_key: false,
         };
-        let key = Self::build_key(password, &vault.keyfile)?;
+        let key = Self::build_key(password, &vault.keyfile, false)?;
         vault.save_atomically(key)?;
 
         Ok(vault)
@@ -204,13 +530,13 @@ impl KeepassVault {
     }
 
     fn save_database(&self) -> Result<(), Error> {
-        let key = Self::build_key(&self.password, &self.keyfile)?;
+        let key = Self::build_key(&self.password, &self.keyfile, self.empty_password_in_key)?;
         debug!("Saving database to file '{}'", &self.filepath);
         self.save_atomically(key)
     }
 
     pub fn change_master_password(&mut self, mut new_password: String) -> Result<(), Error> {
-        let result = Self::build_key(&new_password, &self.keyfile).and_then(|key| {
+        let result = Self::build_key(&new_password, &self.keyfile, false).and_then(|key| {
             debug!("Re-encrypting database '{}' with new master password", &self.filepath);
             self.save_atomically(key)
         });
@@ -218,6 +544,7 @@ impl KeepassVault {
             new_password.zeroize();
             return Err(e);
         }
+        self.empty_password_in_key = false;
         let mut old_password = std::mem::replace(&mut self.password, new_password);
         old_password.zeroize();
         Ok(())
@@ -243,7 +570,10 @@ impl KeepassVault {
             if let Ok(meta) = std::fs::metadata(path) {
                 std::fs::set_permissions(&tmp_path, meta.permissions())?;
             }
-            self.db.save(&mut tmp, key)?;
+            map_all_text_fields(&self.db.root, escape_entities);
+            let saved = self.db.save(&mut tmp, key);
+            map_all_text_fields(&self.db.root, unescape_entities);
+            saved?;
             tmp.sync_all()?;
             drop(tmp);
             std::fs::rename(&tmp_path, path)?;
@@ -288,8 +618,19 @@ impl KeepassVault {
         })
     }
 
-    fn build_key(password: &str, keyfile: &Option<String>) -> Result<DatabaseKey, Error> {
+    /// The key of a vault. With a keyfile, an empty password is left out, so
+    /// that the keyfile alone opens the vault, unless `empty_password_in_key`.
+    fn build_key(
+        password: &str,
+        keyfile: &Option<String>,
+        empty_password_in_key: bool,
+    ) -> Result<DatabaseKey, Error> {
         match keyfile {
+            Some(kf) if password.is_empty() && !empty_password_in_key => {
+                debug!("Using keyfile '{}' only", kf);
+                let mut file = File::open(kf)?;
+                Ok(DatabaseKey::new().with_keyfile(&mut file)?)
+            }
             Some(kf) => {
                 debug!("Using keyfile '{}' and password", kf);
                 let mut file = File::open(kf)?;
@@ -301,11 +642,13 @@ impl KeepassVault {
         }
     }
 
+    /// Opens the vault file, and tells whether its key holds an empty password
+    /// besides the keyfile.
     fn open_database(
         filepath: &str,
         password: &str,
         keyfile: &Option<String>,
-    ) -> Result<Database, Error> {
+    ) -> Result<(Database, bool), Error> {
         match std::fs::metadata(filepath) {
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
                 return Err(Error::new(&format!(
@@ -322,10 +665,18 @@ impl KeepassVault {
             }
             Ok(_) => {}
         }
-        let (mut db_file, key) = Self::get_database_key(filepath, password, keyfile)?;
-        let mut db = Database::open(&mut db_file, key)?;
+        let open = |empty_password_in_key: bool| -> Result<Database, Error> {
+            let key = Self::build_key(password, keyfile, empty_password_in_key)?;
+            Ok(Database::open(&mut File::open(filepath)?, key)?)
+        };
+        let (mut db, empty_password_in_key) = match open(false) {
+            Err(e) if password.is_empty() && keyfile.is_some() => {
+                (open(true).map_err(|_| e)?, true)
+            }
+            opened => (opened?, false),
+        };
         db.set_recycle_bin_enabled(false);
-        Ok(db)
+        Ok((db, empty_password_in_key))
     }
 
     fn create_group(&self, parent_uuid: Uuid, group_name: &str) -> Option<Uuid> {
@@ -343,26 +694,6 @@ impl KeepassVault {
             .unwrap()
     }
 
-    fn get_database_key(
-        filepath: &str,
-        password: &str,
-        keyfile: &Option<String>,
-    ) -> Result<(File, DatabaseKey), DatabaseOpenError> {
-        let db_file = File::open(filepath)?;
-        let key = match keyfile {
-            Some(kf) => {
-                debug!("Using keyfile '{}' and password", kf);
-                let file = &mut File::open(kf).expect("Failed to open keyfile");
-                DatabaseKey::new()
-                    .with_password(password)
-                    .with_keyfile(file)
-                    .unwrap()
-            }
-            None => DatabaseKey::new().with_password(password),
-        };
-        Ok((db_file, key))
-    }
-
     fn load_credentials(&self, grep: Option<&str>) -> Vec<Credential> {
         let grep_lower = grep.map(|g| g.to_lowercase());
         NodeIterator::new(&self.get_root())
@@ -422,7 +753,45 @@ impl KeepassVault {
             .collect()
     }
 
+    fn load_recovery_codes(&self, grep: Option<&str>) -> Vec<RecoveryCodes> {
+        let grep = grep.map(str::to_lowercase);
+        NodeIterator::new(&self.get_root())
+            .filter(node_is_entry)
+            .filter(node_is_recovery_codes)
+            .map(Self::node_to_recovery_codes)
+            .filter(|codes| {
+                grep.as_ref()
+                    .is_none_or(|grep| codes.service().to_lowercase().contains(grep))
+            })
+            .collect()
+    }
+
+    fn node_to_recovery_codes(node: NodePtr) -> RecoveryCodes {
+        let password = node
+            .borrow()
+            .as_any()
+            .downcast_ref::<Entry>()
+            .and_then(|e| e.get_password().map(str::to_string))
+            .filter(|password| !password.is_empty());
+        let (title, notes, id, last_modified) = Self::get_node_note_values(node);
+        let checklist = password.unwrap_or(notes);
+        RecoveryCodes::from_checklist(
+            Some(&id),
+            &title,
+            &checklist,
+            last_modified.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)),
+        )
+    }
+
     fn node_to_credential(node: NodePtr) -> Credential {
+        let tags = node_user_tags(&node);
+        let (rules, totp_id) = node
+            .borrow()
+            .as_any()
+            .downcast_ref::<Entry>()
+            .map_or((None, None), |e| {
+                (password_rules_from_tags(e.get_tags()), totp_id_from_tags(e.get_tags()))
+            });
         let (username, service, password, note, uuid, modified_date_time) = Self::get_node_values(node);
         Credential::new(
             Some(&uuid),
@@ -432,6 +801,9 @@ impl KeepassVault {
             note.as_deref(),
             modified_date_time.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)),
         )
+        .with_password_rules(rules.as_deref())
+        .with_totp_id(totp_id)
+        .with_tags(tags)
     }
 
     fn node_to_totp(node: NodePtr) -> Totp {
@@ -483,6 +855,7 @@ impl KeepassVault {
     }
 
     fn node_to_payment(node: NodePtr) -> PaymentCard {
+        let tags = node_user_tags(&node);
         let (name, name_on_card, number, cvv, expiry, color, billing_address, id) =
             Self::get_node_payment_values(node).unwrap();
         PaymentCard::new(
@@ -493,12 +866,14 @@ impl KeepassVault {
             &cvv,
             Expiry::from_str(&expiry).unwrap(),
             color.as_deref(),
-            Some(&Address::from_str(&billing_address).unwrap()),
+            billing_address.as_ref(),
             None,
         )
+        .with_tags(tags)
     }
 
     fn node_to_note(node: NodePtr) -> Note {
+        let tags = node_user_tags(&node);
         let (title, content, id, last_modified) = Self::get_node_note_values(node);
         Note::new(
             Some(&id),
@@ -506,6 +881,7 @@ impl KeepassVault {
             &content,
             last_modified.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)),
         )
+        .with_tags(tags)
     }
 
     fn get_node_payment_values(
@@ -517,7 +893,7 @@ impl KeepassVault {
         String,
         String,
         Option<String>,
-        String,
+        Option<Address>,
         Uuid,
     )> {
         let node = node.borrow();
@@ -529,7 +905,14 @@ impl KeepassVault {
         let cvv = Self::extract_value_from_note(note, 2, "CVV");
         let expiry = Self::extract_value_from_note(note, 3, "Expiry");
         let color = Self::extract_value_from_note_opt(note, 4, "Color");
-        let billing_address = Self::extract_value_from_note(note, 5, "Billing Address");
+        let billing_address_id = Self::extract_value_from_note_opt(note, 6, "Billing Address Id")
+            .and_then(|id| Uuid::parse_str(&id).ok());
+        let billing_address = Address::from_str(&Self::extract_value_from_note(note, 5, "Billing Address"))
+            .ok()
+            .map(|address| match billing_address_id {
+                Some(id) => address.with_id(id),
+                None => address,
+            });
 
         Some((
             name.to_string(),
@@ -597,7 +980,7 @@ impl KeepassVault {
         let raw_url = e
             .get_raw_otp_value()
             .ok_or(Error::new("Failed to get URL from keepass node"))?;
-        let normalized_url = normalize_otp_url(raw_url);
+        let normalized_url = parseable_otp_url(raw_url);
         let otp: TOTP = normalized_url.parse().map_err(|e| {
             Error::new(&format!("Failed to parse OTP URL: {:?}", e))
         })?;
@@ -645,11 +1028,19 @@ impl KeepassVault {
         }
     }
 
+    /// The UUID for the entry of an entity being saved: the entity's own, so
+    /// that links to it and later updates find it, unless another node has it
+    /// already, as when an import duplicates an entry.
+    fn free_uuid(&self, uuid: &Uuid) -> Option<Uuid> {
+        self.db.search_node_by_uuid(*uuid).is_none().then_some(*uuid)
+    }
+
     fn create_password_entry(
         &mut self,
         parent_uuid: &Uuid,
         credentials: &Credential,
     ) -> keepass_ng::Result<Option<Uuid>> {
+        let uuid = self.free_uuid(credentials.uuid());
         self.db
             .create_new_entry(parent_uuid.clone(), 0)
             .map(|node| {
@@ -657,11 +1048,17 @@ impl KeepassVault {
                     .as_any_mut()
                     .downcast_mut::<Entry>()
                     .map(|entry| {
+                        if let Some(uuid) = uuid {
+                            entry.set_uuid(uuid);
+                        }
                         entry.set_title(Some(credentials.service()));
                         entry.set_username(Some(credentials.username()));
                         entry.set_password(Some(credentials.password()));
                         entry.set_url(Some(&credentials.service()));
                         entry.set_notes(credentials.note());
+                        set_password_rules_tag(entry, credentials.password_rules());
+                        set_totp_link_tag(entry, credentials.totp_id());
+                        entry.get_tags_mut().extend(user_tags(credentials.tags()));
                         entry.get_uuid()
                     })
             })
@@ -672,11 +1069,15 @@ impl KeepassVault {
         parent_uuid: &Uuid,
         totp: &Totp,
     ) -> Result<Option<Uuid>, Error> {
+        let uuid = self.free_uuid(totp.id());
         Ok(self.db.create_new_entry(*parent_uuid, 0).map(|node| {
             node.borrow_mut()
                 .as_any_mut()
                 .downcast_mut::<Entry>()
                 .map(|entry| {
+                    if let Some(uuid) = uuid {
+                        entry.set_uuid(uuid);
+                    }
                     entry.set_title(Some(totp.label()));
                     entry.set_otp(totp.url());
                     entry.get_uuid()
@@ -689,18 +1090,16 @@ impl KeepassVault {
         parent_uuid: &Uuid,
         payment: &PaymentCard,
     ) -> keepass_ng::Result<Option<Uuid>> {
+        let uuid = self.free_uuid(payment.id());
         self.db.create_new_entry(parent_uuid.clone(), 0).map(|node| {
-            let note = format!("Name on card: {}\nNumber: {}\nCVV: {}\nExpiry: {}\nColor: {}\nBilling Address: {}",
-                               payment.name_on_card(),
-                               payment.number(),
-                               payment.cvv(),
-                               payment.expiry_str(),
-                               payment.color_str(),
-                               payment.billing_address().as_ref().map(|a| a.to_string()).unwrap_or("".to_string())
-            );
+            let note = payment_to_note(payment);
             node.borrow_mut().as_any_mut().downcast_mut::<Entry>().map(|entry| {
+                if let Some(uuid) = uuid {
+                    entry.set_uuid(uuid);
+                }
                 entry.set_title(Some(payment.name()));
                 entry.set_notes(Some(&note));
+                entry.get_tags_mut().extend(user_tags(payment.tags()));
                 entry.get_uuid()
             })
         })
@@ -711,6 +1110,7 @@ impl KeepassVault {
         parent_uuid: &Uuid,
         note: &Note,
     ) -> keepass_ng::Result<Option<Uuid>> {
+        let uuid = self.free_uuid(&note.id());
         self.db
             .create_new_entry(parent_uuid.clone(), 0)
             .map(|node| {
@@ -718,13 +1118,36 @@ impl KeepassVault {
                     .as_any_mut()
                     .downcast_mut::<Entry>()
                     .map(|entry| {
+                        if let Some(uuid) = uuid {
+                            entry.set_uuid(uuid);
+                        }
                         entry.set_title(Some(note.title()));
                         entry.set_notes(Some(note.content()));
+                        entry.get_tags_mut().extend(user_tags(note.tags()));
                         entry.get_uuid()
                     })
             })
     }
 
+    fn create_recovery_codes_entry(
+        &mut self,
+        parent_uuid: &Uuid,
+        codes: &RecoveryCodes,
+    ) -> keepass_ng::Result<Option<Uuid>> {
+        let uuid = self.free_uuid(codes.id());
+        self.db.create_new_entry(*parent_uuid, 0).map(|node| {
+            node.borrow_mut().as_any_mut().downcast_mut::<Entry>().map(|entry| {
+                if let Some(uuid) = uuid {
+                    entry.set_uuid(uuid);
+                }
+                entry.set_title(Some(codes.service()));
+                entry.set_password(Some(&codes.to_checklist()));
+                entry.get_tags_mut().push(RECOVERY_CODES_TAG.to_string());
+                entry.get_uuid()
+            })
+        })
+    }
+
     fn do_delete(&mut self, uuid: &Uuid, save: bool) -> Result<(), Error> {
         debug!("Deleting with uuid '{}'", uuid);
         self.db.remove_node_by_uuid(*uuid)?;
@@ -738,6 +1161,32 @@ impl KeepassVault {
             .unwrap_or_else(|| self.create_group(self.get_root_uuid(), group_name).unwrap())
     }
 
+    /// The group for entries of a type, or with a folder, the group of that
+    /// name inside it.
+    fn find_or_create_folder_group(&mut self, type_group: &str, folder: Option<&str>) -> Uuid {
+        let parent = self.find_or_create_group(type_group);
+        match folder {
+            None => parent,
+            Some(folder) => self
+                .find_child_group(parent, folder)
+                .unwrap_or_else(|| self.create_group(parent, folder).unwrap()),
+        }
+    }
+
+    fn find_child_group(&self, parent: Uuid, group_name: &str) -> Option<Uuid> {
+        let parent = self.db.search_node_by_uuid(parent)?;
+        group_get_children(&parent)?
+            .iter()
+            .filter(|node| node_is_group(node))
+            .find(|node| {
+                node.borrow()
+                    .as_any()
+                    .downcast_ref::<Group>()
+                    .is_some_and(|group| group.get_title() == Some(group_name))
+            })
+            .map(|node| node.borrow().get_uuid())
+    }
+
     fn update_entry<F>(&mut self, uuid: Uuid, update_fn: F) -> Result<(), Error>
     where
         F: FnOnce(&mut Entry),
@@ -749,7 +1198,9 @@ impl KeepassVault {
                 let mut node = node_ref.borrow_mut();
                 if let Some(entry) = node.as_any_mut().downcast_mut::<Entry>() {
                     update_fn(entry);
+                    map_text_fields(entry, escape_entities);
                     entry.update_history();
+                    map_text_fields(entry, unescape_entities);
                 } else {
                     return Err(Error {
                         message: "Node is not an Entry".to_string(),
@@ -776,6 +1227,9 @@ impl PasswordVault for KeepassVault {
     }
 
     fn save_credentials(&mut self, credentials: &Vec<Credential>) -> Result<i8, Error> {
+        for c in credentials {
+            check_credential(c)?;
+        }
         let group = self.find_or_create_group("Passwords");
         for c in credentials {
             self.create_password_entry(&group, c)?;
@@ -790,6 +1244,7 @@ impl PasswordVault for KeepassVault {
     }
 
     fn update_credential(&mut self, credential: Credential) -> Result<(), Error> {
+        check_credential(&credential)?;
         let uuid = credential.uuid();
         self.update_entry(*uuid, |entry| {
             entry.set_title(Some(credential.service()));
@@ -797,6 +1252,8 @@ impl PasswordVault for KeepassVault {
             entry.set_password(Some(credential.password()));
             entry.set_url(Some(credential.service()));
             entry.set_notes(credential.note());
+            set_password_rules_tag(entry, credential.password_rules());
+            set_totp_link_tag(entry, credential.totp_id());
         })
     }
 
@@ -832,6 +1289,7 @@ impl PaymentVault for KeepassVault {
     }
 
     fn save_payment(&mut self, payment: PaymentCard) -> Result<(), Error> {
+        check_payment(&payment)?;
         let group = self.find_or_create_group("Payments");
         self.create_payment_entry(&group, &payment)
             .expect("Failed to save payment");
@@ -846,21 +1304,9 @@ impl PaymentVault for KeepassVault {
 
     fn update_payment(&mut self, payment: PaymentCard) -> Result<(), Error> {
         let uuid = payment.id();
+        check_payment(&payment)?;
         self.update_entry(*uuid, |entry| {
-            let note = format!(
-                "Name on card: {}\nNumber: {}\nCVV: {}\nExpiry: {}\nColor: {}\nBilling Address: {}",
-                payment.name_on_card(),
-                payment.number(),
-                payment.cvv(),
-                payment.expiry_str(),
-                payment.color_str(),
-                payment
-                    .billing_address()
-                    .as_ref()
-                    .map(|a| a.to_string())
-                    .unwrap_or("".to_string())
-            );
-
+            let note = payment_to_note(&payment);
             entry.set_title(Some(payment.name()));
             entry.set_notes(Some(&note));
         })
@@ -873,6 +1319,7 @@ impl NoteVault for KeepassVault {
     }
 
     fn save_note(&mut self, note: &Note) -> Result<(), Error> {
+        check_note(note)?;
         let group = self.find_or_create_group("Notes");
         self.create_note_entry(&group, &note)
             .expect("Failed to save note");
@@ -885,6 +1332,7 @@ impl NoteVault for KeepassVault {
     }
 
     fn update_note(&mut self, note: Note) -> Result<(), Error> {
+        check_note(&note)?;
         let uuid = note.id();
         self.update_entry(uuid, |entry| {
             entry.set_title(Some(note.title()));
@@ -899,6 +1347,7 @@ impl TotpVault for KeepassVault {
     }
 
     fn save_totp(&mut self, totp: &Totp) -> Result<(), Error> {
+        check_storable("Label", totp.label())?;
         let group = self.db.root.borrow().get_uuid();
         self.create_totp_entry(&group, &totp)
             .expect("Failed to save TOTP");
@@ -906,24 +1355,121 @@ impl TotpVault for KeepassVault {
         Ok(())
     }
 
+    fn save_totps(&mut self, totps: &[Totp]) -> Result<usize, Error> {
+        for totp in totps {
+            check_storable("Label", totp.label())?;
+        }
+        let group = self.db.root.borrow().get_uuid();
+        for totp in totps {
+            self.create_totp_entry(&group, totp)?;
+        }
+        self.save_database()?;
+        Ok(totps.len())
+    }
+
     fn delete_totp(&mut self, uuid: &Uuid) -> Result<(), Error> {
         self.do_delete(uuid, true)
     }
 
     fn update_totp(&mut self, totp: Totp) -> Result<(), Error> {
+        check_storable("Label", totp.label())?;
         let uuid = totp.id();
         self.update_entry(*uuid, |entry| {
             entry.set_title(Some(totp.label()));
             entry.set_otp(totp.url());
         })
     }
+
+    fn save_hotp_counter(&mut self, totp: &Totp) -> Result<(), Error> {
+        let node = self.db.search_node_by_uuid(*totp.id()).ok_or_else(|| {
+            Error::new(&format!("Entry with uuid '{}' not found", totp.id()))
+        })?;
+        node.borrow_mut()
+            .as_any_mut()
+            .downcast_mut::<Entry>()
+            .ok_or_else(|| Error::new("Node is not an Entry"))?
+            .set_otp(totp.url());
+        self.save_database()
+    }
 }
 
-impl Vault for KeepassVault {}
+impl RecoveryCodesVault for KeepassVault {
+    fn find_recovery_codes(&self, grep: Option<&str>) -> Vec<RecoveryCodes> {
+        self.load_recovery_codes(grep)
+    }
+
+    fn save_recovery_codes(&mut self, codes: &RecoveryCodes) -> Result<(), Error> {
+        check_storable("Service", codes.service())?;
+        check_storable("Codes", &codes.to_checklist())?;
+        let group = self.find_or_create_group("Recovery Codes");
+        self.create_recovery_codes_entry(&group, codes)?;
+        self.save_database()
+    }
+
+    fn update_recovery_codes(&mut self, codes: &RecoveryCodes) -> Result<(), Error> {
+        check_storable("Codes", &codes.to_checklist())?;
+        self.update_entry(*codes.id(), |entry| {
+            entry.set_title(Some(codes.service()));
+            entry.set_password(Some(&codes.to_checklist()));
+            entry.set_notes(None);
+        })
+    }
+
+    fn delete_recovery_codes(&mut self, uuid: &Uuid) -> Result<(), Error> {
+        self.do_delete(uuid, true)
+    }
+}
+
+impl Vault for KeepassVault {
+    fn find_altered_text(&self) -> Vec<AlteredText> {
+        NodeIterator::new(&self.get_root())
+            .filter(node_is_entry)
+            .flat_map(|node| {
+                let node = node.borrow();
+                node.as_any()
+                    .downcast_ref::<Entry>()
+                    .map(|entry| find_altered_fields(entry, lossless_since(&self.db)))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn save_to_folder(
+        &mut self,
+        folder: Option<&str>,
+        credentials: &[Credential],
+        payments: &[PaymentCard],
+        notes: &[Note],
+    ) -> Result<(), Error> {
+        credentials.iter().try_for_each(check_credential)?;
+        payments.iter().try_for_each(check_payment)?;
+        notes.iter().try_for_each(check_note)?;
+        if !credentials.is_empty() {
+            let group = self.find_or_create_folder_group("Passwords", folder);
+            for credential in credentials {
+                self.create_password_entry(&group, credential)?;
+            }
+        }
+        if !payments.is_empty() {
+            let group = self.find_or_create_folder_group("Payments", folder);
+            for payment in payments {
+                self.create_payment_entry(&group, payment)?;
+            }
+        }
+        if !notes.is_empty() {
+            let group = self.find_or_create_folder_group("Notes", folder);
+            for note in notes {
+                self.create_note_entry(&group, note)?;
+            }
+        }
+        self.save_database()
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vault::entities::keep_unknown_otp_params;
 
     #[test]
     fn default_database_config_is_kdbx4_with_argon2_kdf() {
@@ -1038,4 +1584,433 @@ mod tests {
         assert!(KeepassVault::open("new-pw", path_str, None).is_ok());
         assert!(KeepassVault::open("old-pw", path_str, None).is_err());
     }
+
+    #[test]
+    fn unicode_text_survives_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let cred = Credential::new(
+            None,
+            "pw\u{1}😀",
+            "AT&T <café> ☕",
+            "ünï 'q' \"d\"",
+            Some("tab\there\r\n€ ¬ § ´ &amp; 🔐"),
+            None,
+        );
+        vault.save_one_credential(cred).unwrap();
+        vault.save_note(&Note::new(None, "Wi-Fi 📶", "key: ¬¬ ✓", None)).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let creds = reopened.grep(None);
+        assert_eq!(creds.len(), 1);
+        assert_eq!(creds[0].password(), "pw\u{1}😀");
+        assert_eq!(creds[0].service(), "AT&T <café> ☕");
+        assert_eq!(creds[0].username(), "ünï 'q' \"d\"");
+        assert_eq!(creds[0].note(), Some("tab\there\r\n€ ¬ § ´ &amp; 🔐"));
+        let notes = reopened.find_notes();
+        assert_eq!(notes[0].title(), "Wi-Fi 📶");
+        assert_eq!(notes[0].content(), "key: ¬¬ ✓");
+    }
+
+    #[test]
+    fn unstorable_text_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let mut vault = KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap();
+
+        for service in ["bell\u{7}", "nonchar\u{FFFE}", "c1\u{85}"] {
+            let cred = Credential::new(None, "pw", service, "user", None, None);
+            let err = vault.save_one_credential(cred).expect_err(service);
+            assert!(err.message.starts_with("Service contains"), "{}", err.message);
+        }
+        assert!(vault.grep(None).is_empty());
+    }
+
+    #[test]
+    fn keys_with_an_empty_password_and_a_keyfile_still_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let keyfile = dir.path().join("vault.key").to_str().unwrap().to_string();
+        std::fs::write(&keyfile, b"key file content").unwrap();
+        let mut vault = KeepassVault::new(path_str, "", Some(&keyfile)).unwrap();
+        assert!(!KeepassVault::open("", path_str, Some(keyfile.clone())).unwrap().empty_password_in_key);
+
+        // Rewrite the key as earlier versions did, with the empty password in it.
+        vault.empty_password_in_key = true;
+        vault.save_note(&Note::new(None, "Wifi", "hunter2", None)).unwrap();
+        let mut reopened = KeepassVault::open("", path_str, Some(keyfile.clone())).unwrap();
+        assert!(reopened.empty_password_in_key);
+        reopened.save_note(&Note::new(None, "Door", "1234", None)).unwrap();
+        let reopened = KeepassVault::open("", path_str, Some(keyfile)).unwrap();
+        assert!(reopened.empty_password_in_key);
+        assert_eq!(reopened.find_notes().len(), 2);
+    }
+
+    #[test]
+    fn entity_names_survive_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let note = "a &lt; b, &amp;gt; &quot;q&apos; & <&amp;>";
+        let cred = Credential::new(None, "pw", "a &lt; b", "&amp;lt;", Some(note), None);
+        vault.save_one_credential(cred).unwrap();
+        assert_eq!(vault.grep(None)[0].note(), Some(note));
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let creds = reopened.grep(None);
+        assert_eq!(creds[0].service(), "a &lt; b");
+        assert_eq!(creds[0].username(), "&amp;lt;");
+        assert_eq!(creds[0].note(), Some(note));
+    }
+
+    #[test]
+    fn entity_names_in_history_survive_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let cred = Credential::new(None, "pw", "shop", "user", None, None);
+        vault.save_one_credential(cred).unwrap();
+        let uuid = *vault.grep(None)[0].uuid();
+        vault.update_entry(uuid, |e| e.set_notes(Some("a &lt; b"))).unwrap();
+        vault.update_entry(uuid, |e| e.set_notes(Some("plain"))).unwrap();
+
+        for _ in 0..2 {
+            let mut reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+            let node = reopened.db.search_node_by_uuid(uuid).unwrap();
+            let notes: Vec<String> = node
+                .borrow()
+                .as_any()
+                .downcast_ref::<Entry>()
+                .and_then(|e| e.get_history().clone())
+                .unwrap()
+                .get_entries()
+                .iter()
+                .filter_map(|version| version.get_notes().map(unescape_entities))
+                .collect();
+            assert!(notes.contains(&"a &lt; b".to_string()), "{:?}", notes);
+            reopened.update_entry(uuid, |e| e.set_notes(Some("again"))).unwrap();
+        }
+    }
+
+    #[test]
+    fn text_written_since_the_upgrade_is_not_reported_hidden() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let cred = Credential::new(None, "pw", "bank 🏦", "user", Some("€ 100"), None);
+        vault.save_one_credential(cred).unwrap();
+        assert!(vault.find_altered_text().is_empty());
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        assert_eq!(lossless_since(&reopened.db), lossless_since(&vault.db));
+        assert!(reopened.find_altered_text().is_empty());
+    }
+
+    #[test]
+    fn altered_text_reports_removed_and_hidden_characters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let mut vault = KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap();
+        let group = vault.find_or_create_group("Passwords");
+
+        let edited = Credential::new(None, "pw", "shop", "user", None, None);
+        let edited_uuid = vault.create_password_entry(&group, &edited).unwrap().unwrap();
+        // A version with an emoji, then the same text as an older passlane saved it.
+        vault
+            .update_entry(edited_uuid, |e| e.set_notes(Some("Café ☕")))
+            .unwrap();
+        vault
+            .update_entry(edited_uuid, |e| e.set_notes(Some("Café ")))
+            .unwrap();
+
+        let intact = Credential::new(None, "pw", "bank 🏦", "user", None, None);
+        vault.create_password_entry(&group, &intact).unwrap();
+        let plain = Credential::new(None, "pw", "plain.com", "user", Some("ok"), None);
+        vault.create_password_entry(&group, &plain).unwrap();
+        // As if these entries were written before the vault was upgraded.
+        set_lossless_since(&mut vault.db, Times::now() + chrono::Duration::days(1));
+
+        let altered = vault.find_altered_text();
+        let removed = altered.iter().find(|a| a.uuid == edited_uuid).unwrap();
+        assert_eq!(removed.field, "Notes");
+        assert_eq!(removed.characters, "☕");
+        assert_eq!(removed.alteration, Alteration::Removed);
+
+        let hidden: Vec<_> = altered.iter().filter(|a| a.title == "bank 🏦").collect();
+        assert_eq!(hidden.len(), 2, "title and URL both hold the service");
+        assert!(hidden.iter().all(|a| a.alteration == Alteration::Hidden && a.characters == "🏦"));
+        assert!(!altered.iter().any(|a| a.title == "plain.com"));
+    }
+
+    #[test]
+    fn password_rules_survive_save_update_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let rules = "minlength: 8; required: lower, upper; required: digit, [;,%&<]]";
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let cred = Credential::new(None, "pw", "shop.com", "user", None, None)
+            .with_password_rules(Some(rules));
+        vault.save_one_credential(cred).unwrap();
+        let plain = Credential::new(None, "pw", "plain.com", "user", None, None);
+        vault.save_one_credential(plain).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let saved = reopened.grep(Some("shop")).remove(0);
+        assert_eq!(saved.password_rules(), Some(rules));
+        assert_eq!(reopened.grep(Some("plain"))[0].password_rules(), None);
+
+        let mut reopened = reopened;
+        let cleared = Credential::new(Some(saved.uuid()), "pw2", "shop.com", "user", None, None);
+        reopened.update_credential(cleared).unwrap();
+        assert_eq!(reopened.grep(Some("shop"))[0].password_rules(), None);
+    }
+
+    #[test]
+    fn totp_link_survives_save_update_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+        let totp_id = Uuid::new_v4();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let cred = Credential::new(None, "pw", "github.com", "user", None, None)
+            .with_password_rules(Some("minlength: 8;"))
+            .with_totp_id(Some(totp_id));
+        vault.save_one_credential(cred).unwrap();
+
+        let mut reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let saved = reopened.grep(Some("github")).remove(0);
+        assert_eq!(saved.totp_id(), Some(&totp_id));
+        assert_eq!(saved.password_rules(), Some("minlength: 8;"));
+
+        reopened.update_credential(saved.with_totp_id(None)).unwrap();
+        let unlinked = reopened.grep(Some("github")).remove(0);
+        assert_eq!(unlinked.totp_id(), None);
+        assert_eq!(unlinked.password_rules(), Some("minlength: 8;"));
+    }
+
+    #[test]
+    fn recovery_codes_survive_use_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let parsed = RecoveryCodes::parse_codes("aaaa-1111 bbbb-2222").unwrap();
+        vault
+            .save_recovery_codes(&RecoveryCodes::new(None, "GitHub", parsed, None))
+            .unwrap();
+        vault
+            .save_one_credential(Credential::new(None, "pw", "github.com", "user", None, None))
+            .unwrap();
+
+        let mut codes = vault.find_recovery_codes(Some("github")).remove(0);
+        assert_eq!(codes.use_next().as_deref(), Some("aaaa-1111"));
+        vault.update_recovery_codes(&codes).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let saved = reopened.find_recovery_codes(None);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].service(), "GitHub");
+        assert_eq!(saved[0].unused_count(), 1);
+        assert!(reopened.find_notes().is_empty());
+        assert_eq!(reopened.grep(None).len(), 1);
+        assert!(reopened.find_recovery_codes(Some("gitlab")).is_empty());
+
+        let node = reopened.db.search_node_by_uuid(*saved[0].id()).unwrap();
+        let node = node.borrow();
+        let entry = node.as_any().downcast_ref::<Entry>().unwrap();
+        assert_eq!(entry.get_password(), Some("[x] aaaa-1111\n[ ] bbbb-2222"));
+        assert_eq!(entry.get_notes(), None);
+    }
+
+    #[test]
+    fn recovery_codes_in_the_notes_are_still_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let vault = KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap();
+        let node = vault.db.create_new_entry(vault.get_root_uuid(), 0).unwrap();
+        if let Some(entry) = node.borrow_mut().as_any_mut().downcast_mut::<Entry>() {
+            entry.set_title(Some("GitHub"));
+            entry.set_notes(Some("[x] aaaa-1111\n[ ] bbbb-2222"));
+            entry.get_tags_mut().push(RECOVERY_CODES_TAG.to_string());
+        }
+
+        let saved = vault.find_recovery_codes(None);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].unused_count(), 1);
+    }
+
+    #[test]
+    fn imported_tags_cannot_pass_for_internal_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let mut vault = KeepassVault::new(path.to_str().unwrap(), "master-pw", None).unwrap();
+        let tags = vec![RECOVERY_CODES_TAG.to_string(), "totp=x".to_string(), "work".to_string()];
+        let note = Note::new(None, "Backup codes", "aaaa-1111", None).with_tags(tags.clone());
+        let credential = Credential::new(None, "pw", "github.com", "alice", None, None).with_tags(tags);
+        vault.save_to_folder(None, &[credential], &[], &[note]).unwrap();
+
+        assert!(vault.find_recovery_codes(None).is_empty());
+        assert_eq!(vault.find_notes()[0].tags(), ["work"]);
+        assert_eq!(vault.grep(None)[0].tags(), ["work"]);
+    }
+
+    #[test]
+    fn saved_entries_keep_their_uuids_unless_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let credential = Credential::new(None, "pw", "github.com", "user", None, None);
+        let note = Note::new(None, "Wifi", "hunter2", None);
+        vault.save_credentials(&vec![credential.clone(), credential.clone()]).unwrap();
+        vault.save_note(&note).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let saved = reopened.grep(None);
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved.iter().filter(|c| c.uuid() == credential.uuid()).count(), 1);
+        assert_eq!(reopened.find_notes()[0].id(), note.id());
+    }
+
+    #[test]
+    fn saves_imported_entries_into_folder_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let card = PaymentCard::new(
+            None, "Visa", "Alice", "4111111111111111", "123",
+            Expiry { month: 7, year: 2027 }, None, None, None,
+        );
+        let note = Note::new(None, "Wifi", "hunter2", None).with_tags(vec!["home".to_string()]);
+        let github = Credential::new(None, "pw", "github.com", "alice", None, None)
+            .with_totp_id(Some(Uuid::new_v4()))
+            .with_tags(vec!["work".to_string(), "dev".to_string()]);
+        let gitlab = Credential::new(None, "pw", "gitlab.com", "alice", None, None);
+        let work_gitlab = Credential::new(None, "pw", "gitlab.com", "bob", None, None);
+        vault.save_to_folder(Some("Work"), &[github], &[card], &[note]).unwrap();
+        vault.save_to_folder(Some("Work"), &[work_gitlab], &[], &[]).unwrap();
+        vault.save_to_folder(None, &[gitlab], &[], &[]).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        assert_eq!(reopened.grep(None).len(), 3);
+        let github = reopened.grep(Some("github")).remove(0);
+        assert_eq!(github.tags(), ["work", "dev"]);
+        assert!(github.totp_id().is_some());
+        assert_eq!(reopened.find_notes()[0].tags(), ["home"]);
+        let cards = reopened.find_payments();
+        assert_eq!(cards.len(), 1);
+        assert!(cards[0].billing_address().is_none());
+        let passwords = reopened.find_group("Passwords").unwrap();
+        assert!(reopened.find_child_group(passwords, "Work").is_some());
+        let work_groups = reopened
+            .get_groups()
+            .iter()
+            .flat_map(|group| group_get_children(group).unwrap_or_default())
+            .filter(node_is_group)
+            .count();
+        assert_eq!(work_groups, 3);
+    }
+
+    #[test]
+    fn billing_address_survives_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kdbx");
+        let path_str = path.to_str().unwrap();
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        let address = Address::new(None, "1 Main St, Apt 4", "Spring\\field", "US", Some("IL"), "12345");
+        let card = PaymentCard::new(
+            None, "Visa", "Alice", "4111111111111111", "123",
+            Expiry { month: 7, year: 2027 }, None, Some(&address), None,
+        );
+        vault.save_payment(card).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let read = reopened.find_payments().remove(0);
+        let read = read.billing_address().unwrap();
+        assert_eq!(read.id(), address.id());
+        assert_eq!(read.street(), "1 Main St, Apt 4");
+        assert_eq!(read.city(), "Spring\\field");
+        assert_eq!(read.zip(), "12345");
+        assert_eq!(read.state().map(String::as_str), Some("IL"));
+    }
+
+    #[test]
+    fn hotp_counter_survives_save_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("totp.kdbx");
+        let path_str = path.to_str().unwrap();
+        // RFC 4226 test secret "12345678901234567890".
+        let url = "otpauth://hotp/VPN:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=VPN&counter=3&digits=6";
+        let hotp = Totp::new(None, url, "VPN:alice", "VPN", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", "SHA1", 30, 6, None);
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        vault.save_totp(&hotp).unwrap();
+        let saved = vault.find_totp(None).remove(0);
+        assert_eq!(saved.counter(), Some(3));
+        assert_eq!(saved.get_code().unwrap().value, "969429");
+
+        vault.save_hotp_counter(&saved.advanced()).unwrap();
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let advanced = reopened.find_totp(None).remove(0);
+        assert_eq!(advanced.counter(), Some(4));
+        assert_eq!(advanced.get_code().unwrap().value, "338314");
+    }
+
+    #[test]
+    fn steam_encoder_survives_update_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("totp.kdbx");
+        let path_str = path.to_str().unwrap();
+        let url = "otpauth://totp/Steam:gabe?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Steam&digits=5&encoder=steam";
+        let steam = Totp::new(None, url, "Steam:gabe", "Steam", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", "SHA1", 30, 5, None);
+
+        let mut vault = KeepassVault::new(path_str, "master-pw", None).unwrap();
+        vault.save_totp(&steam).unwrap();
+        let saved = vault.find_totp(None).remove(0);
+        assert!(saved.is_steam());
+        assert_eq!(saved.digits(), 5);
+
+        let renamed_url = keep_unknown_otp_params(
+            "otpauth://totp/Steam:gaben?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Steam&period=30&algorithm=SHA1&digits=5",
+            saved.url(),
+        );
+        let renamed = Totp::new(Some(saved.id()), &renamed_url, "Steam:gaben", "Steam", saved.secret(), "SHA1", 30, 5, None);
+        vault.update_totp(renamed).unwrap();
+
+        let reopened = KeepassVault::open("master-pw", path_str, None).unwrap();
+        let updated = reopened.find_totp(None).remove(0);
+        assert_eq!(updated.label(), "Steam:gaben");
+        assert!(updated.is_steam());
+        assert!(updated.get_code().unwrap().value.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(updated.get_code().unwrap().value.len(), 5);
+    }
+
+    #[test]
+    fn otp_urls_without_issuer_are_loaded() {
+        assert_eq!(
+            parseable_otp_url("otpauth://hotp/Token%3Abob?secret=JBSWY3DPEHPK3PXP&counter=1"),
+            "otpauth://hotp/Token%3Abob?secret=JBSWY3DPEHPK3PXP&counter=1&issuer=Token"
+        );
+        assert_eq!(
+            parseable_otp_url("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP"),
+            "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&issuer=alice"
+        );
+        let with_issuer = "otpauth://totp/A:b?secret=JBSWY3DPEHPK3PXP&issuer=A";
+        assert_eq!(parseable_otp_url(with_issuer), with_issuer);
+    }
 }
@@ -1,4 +1,6 @@
-use crate::vault::entities::{Credential, Error, Note, PaymentCard, Totp};
+use crate::vault::entities::{
+    AlteredText, Credential, Error, Note, PaymentCard, RecoveryCodes, Totp,
+};
 use uuid::Uuid;
 
 pub trait PasswordVault {
@@ -42,9 +44,38 @@ pub trait TotpVault {
 
     fn save_totp(&mut self, totp: &Totp) -> Result<(), Error>;
 
+    /// Saves several authorizers with a single write of the vault.
+    fn save_totps(&mut self, totps: &[Totp]) -> Result<usize, Error>;
+
     fn delete_totp(&mut self, uuid: &Uuid) -> Result<(), Error>;
 
     fn update_totp(&mut self, totp: Totp) -> Result<(), Error>;
+
+    /// Stores the counter of an HOTP authorizer without adding a history entry.
+    fn save_hotp_counter(&mut self, totp: &Totp) -> Result<(), Error>;
+}
+
+pub trait RecoveryCodesVault {
+    /// The recovery codes of services whose name contains `grep`, ignoring case.
+    fn find_recovery_codes(&self, grep: Option<&str>) -> Vec<RecoveryCodes>;
+
+    fn save_recovery_codes(&mut self, codes: &RecoveryCodes) -> Result<(), Error>;
+
+    fn update_recovery_codes(&mut self, codes: &RecoveryCodes) -> Result<(), Error>;
+
+    fn delete_recovery_codes(&mut self, uuid: &Uuid) -> Result<(), Error>;
 }
 
-pub trait Vault: PasswordVault + PaymentVault + NoteVault + TotpVault {}
+pub trait Vault: PasswordVault + PaymentVault + NoteVault + TotpVault + RecoveryCodesVault {
+    fn find_altered_text(&self) -> Vec<AlteredText>;
+
+    /// Saves imported entries with a single write of the vault. Entries of a
+    /// folder go to a group of that name inside the group for their type.
+    fn save_to_folder(
+        &mut self,
+        folder: Option<&str>,
+        credentials: &[Credential],
+        payments: &[PaymentCard],
+        notes: &[Note],
+    ) -> Result<(), Error>;
+}